@@ -0,0 +1,27 @@
+// Lexes generated sources of growing size, the time per token should stay flat
+use std::time::Instant;
+
+use lll::lll::lexer::Lexer;
+
+fn source(lines: usize) -> String {
+	let mut source = String::new();
+	for i in 0..lines {
+		source += &format!("new переменная_{i} = \"строка {i}\" + \"ascii\"; // комментарий\n");
+		source += &format!("if (x_{i} >= {i}.5 and y != nil) {{ xs[{i}] += 1; }}\n");
+	}
+	source
+}
+
+fn main() {
+	for lines in [1_000, 10_000, 100_000] {
+		let source = source(lines);
+		let bytes = source.len();
+
+		let start = Instant::now();
+		let tokens = Lexer::new(source).scan_tokens();
+		let elapsed = start.elapsed();
+
+		println!("{:>7} lines {:>9} bytes {:>8} tokens {:>10.2?} {:>6.1} ns/token",
+			lines * 2, bytes, tokens.len(), elapsed, elapsed.as_nanos() as f64 / tokens.len() as f64);
+	}
+}
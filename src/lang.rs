@@ -5,46 +5,311 @@ use crate::lll::token::TokenType;
 use crate::lll::token::Literal;
 use crate::lll::parse::Parser;
 use crate::lll::interpreter::Interpreter;
+use crate::lll::compiler::Compiler;
+use crate::lll::vm::Vm;
+use crate::lll::builtins;
+use crate::lll::budget::Limits;
+use crate::lll::capability::Capability;
+use crate::lll::capability::Capabilities;
+use crate::lll::debug;
+use crate::lll::error::ErrorKind;
+use crate::lll::gc;
+use crate::lll::fmt;
+use crate::lll::lint;
+use crate::lll::lint::Lint;
+use crate::lll::lint::Level;
+use crate::lll::lint::Lints;
+use crate::lll::lsp;
+use crate::lll::module::Modules;
+use crate::lll::manifest;
+use crate::lll::manifest::Manifest;
+
+#[derive(Clone, Copy, Default)]
+pub enum Backend {
+	#[default]
+	Tree,
+	Vm
+}
+
+#[derive(Clone)]
+pub struct Options {
+	pub backend: Backend,
+	pub dump_tokens: bool,
+	pub dump_ast: bool,
+	pub dump_bytecode: bool,
+	pub gc_stress: bool,
+	pub lints: Lints,
+	// from `--lib-dir`, searched for imports before `LLL_PATH`
+	pub lib_dirs: Vec<std::path::PathBuf>,
+	// calls deeper than this are a stack overflow error in the script
+	pub max_depth: usize,
+	// steps, time and memory the script may use, and a way to stop it
+	pub limits: Limits,
+	// which built-ins that reach outside of the script it may call
+	pub capabilities: Capabilities,
+	// what the script sees as `args`
+	pub args: Vec<String>
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Self {
+			backend: Backend::default(),
+			dump_tokens: false,
+			dump_ast: false,
+			dump_bytecode: false,
+			gc_stress: false,
+			lints: Lints::default(),
+			lib_dirs: Vec::new(),
+			max_depth: builtins::MAX_DEPTH,
+			limits: Limits::default(),
+			capabilities: Capabilities::default(),
+			args: Vec::new()
+		}
+	}
+}
+
+// How a run ended
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+	Finished,
+	// it didn't parse or some statement ended in an error, whatever could still run did
+	Failed,
+	// the script called `exit`
+	Exited(i32)
+}
+
+impl Outcome {
+	// What a process that ran only this should exit with
+	pub fn code(&self) -> i32 {
+		match self {
+			Outcome::Finished => 0,
+			Outcome::Failed => 1,
+			Outcome::Exited(code) => *code
+		}
+	}
+}
+
+// For programs that run lll scripts themselves, set up the way the command line flags would
+#[derive(Clone, Default)]
+pub struct Engine {
+	options: Options
+}
+
+impl Engine {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn backend(mut self, backend: Backend) -> Self {
+		self.options.backend = backend;
+		self
+	}
+
+	pub fn lib_dir(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+		self.options.lib_dirs.push(dir.into());
+		self
+	}
+
+	pub fn max_depth(mut self, max_depth: usize) -> Self {
+		self.options.max_depth = max_depth;
+		self
+	}
+
+	pub fn limits(mut self, limits: Limits) -> Self {
+		self.options.limits = limits;
+		self
+	}
+
+	pub fn allow(mut self, capability: Capability) -> Self {
+		self.options.capabilities.set(capability, true);
+		self
+	}
+
+	pub fn deny(mut self, capability: Capability) -> Self {
+		self.options.capabilities.set(capability, false);
+		self
+	}
+
+	// Scripts get every capability unless told otherwise, for a sandbox start from none
+	pub fn deny_all(mut self) -> Self {
+		self.options.capabilities.set_all(false);
+		self
+	}
+
+	pub fn args(mut self, args: Vec<String>) -> Self {
+		self.options.args = args;
+		self
+	}
+
+	pub fn options(&self) -> &Options {
+		&self.options
+	}
+
+	pub fn run(&self, source: &str) -> Outcome {
+		run(source.to_string(), None, &self.options)
+	}
+
+	pub fn run_file(&self, path: &std::path::Path) -> Outcome {
+		run_file(&path.to_path_buf(), &self.options)
+	}
+}
+
+// Stack for running a script: some for the deepest expression the parser lets through,
+// and a bit more for every call the tree walker makes
+const STACK_BASE: usize = 64 * 1024 * 1024;
+const STACK_PER_CALL: usize = 32 * 1024;
+
+// Where imports are looked for after the importing file's directory
+fn search_path(options: &Options) -> Vec<std::path::PathBuf> {
+	let mut dirs = options.lib_dirs.clone();
+	if let Some(path) = std::env::var_os("LLL_PATH") {
+		dirs.extend(std::env::split_paths(&path).filter(|dir| !dir.as_os_str().is_empty()));
+	}
+	dirs
+}
+
+// Runs on a thread of its own with a stack big enough for `max_depth` calls, so a script
+// that recurses too deep gets an error instead of taking the process down. Imports are
+// relative to `path`, or to where lll was started without one.
+pub fn run(source: String, path: Option<&std::path::Path>, options: &Options) -> Outcome {
+	let stack = options.max_depth.saturating_mul(STACK_PER_CALL).saturating_add(STACK_BASE);
+	std::thread::scope(|scope| {
+		let runner = std::thread::Builder::new().stack_size(stack).spawn_scoped(scope, || run_here(source, path, options));
+		match runner.map(|runner| runner.join()) {
+			Ok(Ok(code)) => code,
+			Ok(Err(panic)) => std::panic::resume_unwind(panic),
+			Err(_) => {
+				eprintln!("FATAL: cannot get a stack for {} calls, try a lower --max-depth", options.max_depth);
+				Outcome::Failed
+			}
+		}
+	})
+}
 
 // maybe need to wrap around or not
-fn run(source: String) {
+fn run_here(source: String, path: Option<&std::path::Path>, options: &Options) -> Outcome {
+	gc::set_stress(options.gc_stress);
 	let mut lexer = Lexer::new(source);
 
-	let mut tokens = lexer.scan_tokens();
+	// what doesn't scan or parse is reported and left out, the rest still runs
+	let (mut tokens, errors) = lexer.scan_with_errors();
+	for e in &errors {
+		println!("{e}");
+	}
+	let mut parsed = errors.is_empty();
 	tokens.push(Token { literal: Literal::Nil, toktype: TokenType::Eof, place: 0, line: 0 } );
 
-	//for i in &tokens {
-	//	println!("{}", i.to_string());
-	//}
+	if options.dump_tokens {
+		debug::dump_tokens(&tokens);
+	}
 
 	let mut parser = Parser::new(tokens);
-	let mut stmts = parser.parse();
-
-	let mut interpreter = Interpreter::new();
-	
-	match &mut stmts {
-		Some(v) => {
-			match interpreter.interpret(v) {
-				Ok(()) => (),
-				Err(e) => eprintln!("{e}")
+	let (stmts, errors) = parser.parse_with_errors();
+	for e in &errors {
+		println!("{e}");
+	}
+	parsed &= errors.is_empty();
+
+	let problems = lint::lint(&stmts, &options.lints);
+	for e in &problems {
+		eprintln!("{e}");
+	}
+	if problems.iter().any(|e| !e.is_warning()) {
+		return Outcome::Failed;
+	}
+
+	if options.dump_ast {
+		debug::dump_ast(&stmts);
+	}
+	if options.dump_bytecode {
+		match Compiler::compile(&stmts) {
+			Ok(script) => debug::disassemble(&script),
+			Err(e) => eprintln!("{e}")
+		}
+	}
+
+	let modules = Modules::new(path, search_path(options));
+	let path = path.map(|p| p.to_path_buf());
+	let res = match options.backend {
+		Backend::Tree => {
+			let mut interpreter = Interpreter::with_modules(modules, path);
+			interpreter.set_max_depth(options.max_depth);
+			interpreter.set_capabilities(options.capabilities);
+			interpreter.set_args(options.args.clone());
+			let res = interpreter.set_limits(options.limits.clone()).and_then(|_| interpreter.interpret(&stmts));
+			res.map(|_| interpreter.failed())
+		},
+		Backend::Vm => Compiler::compile(&stmts).and_then(|script| {
+			let mut vm = Vm::with_modules(modules, path);
+			vm.set_max_depth(options.max_depth);
+			vm.set_capabilities(options.capabilities);
+			vm.set_args(options.args.clone());
+			vm.set_limits(options.limits.clone())?;
+			vm.interpret(script).map(|_| vm.failed())
+		})
+	};
+	match res {
+		Err(e) => match e.kind() {
+			ErrorKind::Exit(code) => Outcome::Exited(code),
+			_ => {
+				eprintln!("{e}");
+				Outcome::Failed
 			}
 		},
-		None => {
-			eprintln!("FATAL: parser caused error")
-		}
+		Ok(failed) if failed || !parsed => Outcome::Failed,
+		Ok(_) => Outcome::Finished
 	}
 }
 
-pub fn run_file(path: &std::path::PathBuf) {
+pub fn run_file(path: &std::path::PathBuf, options: &Options) -> Outcome {
 	let Ok(text) = std::fs::read_to_string(path) else {
-		panic!("FATAL: не нашёл на воровской дороге файл");
+		eprintln!("FATAL: не нашёл на воровской дороге файл {}", path.display());
+		return Outcome::Failed;
+	};
+
+	run(text, Some(path), options)
+}
+
+// `lll -`, the program is all of stdin
+pub fn run_stdin(options: &Options) -> Outcome {
+	let mut text = String::new();
+	if std::io::Read::read_to_string(&mut std::io::stdin(), &mut text).is_err() {
+		eprintln!("FATAL: not valid utf-8");
+		return Outcome::Failed;
+	}
+
+	run(text, None, options)
+}
+
+// `lll run`, runs the entry of the project `dir` is in, with its roots on the search path
+pub fn run_project(dir: &std::path::Path, options: &Options) -> Outcome {
+	let Some(path) = Manifest::find(dir) else {
+		eprintln!("FATAL: no {} in {} or any parent directory", manifest::FILE, dir.display());
+		return Outcome::Failed;
+	};
+	let project = match Manifest::load(&path) {
+		Ok(project) => project,
+		Err(e) => {
+			eprintln!("{e}");
+			return Outcome::Failed;
+		}
 	};
+	if !project.entry.is_file() {
+		eprintln!("FATAL: cannot find entry {}", project.entry.display());
+		return Outcome::Failed;
+	}
 
-	run(text);
+	let mut options = options.clone();
+	options.lib_dirs = project.roots.into_iter().chain(options.lib_dirs).collect();
+	run_file(&project.entry, &options)
 }
 
-// This thing cannot work here smh, but other places would
-pub fn run_interactive() {
+// This thing cannot work here smh, but other places would. Errors don't end it, the end
+// of input or `exit` does.
+pub fn run_interactive(mut options: Options) -> Outcome {
+	// every line is a program of its own, so nearly every variable would be unused
+	options.lints.set(Lint::UnusedVariable, Level::Allow);
 	let stdin = std::io::stdin();
 
 	loop {
@@ -55,10 +320,74 @@ pub fn run_interactive() {
 		};
 
 		if res == 0 {
-			return;
+			return Outcome::Finished;
 		}
 		
 		
-		run(buf);
+		if let Outcome::Exited(code) = run(buf, None, &options) {
+			return Outcome::Exited(code);
+		}
+	}
+}
+
+// `lll fmt`, rewrites the files in place or with `check` only reports the ones that would change
+pub fn format_files(paths: &[std::path::PathBuf], check: bool) -> bool {
+	let mut ok = true;
+	for path in paths {
+		let Ok(text) = std::fs::read_to_string(path) else {
+			eprintln!("FATAL: cannot read {}", path.display());
+			ok = false;
+			continue;
+		};
+
+		match fmt::format(&text) {
+			Ok(formatted) if formatted == text => (),
+			Ok(_) if check => {
+				println!("{} is not formatted", path.display());
+				ok = false;
+			},
+			Ok(formatted) => {
+				if std::fs::write(path, formatted).is_err() {
+					eprintln!("FATAL: cannot write {}", path.display());
+					ok = false;
+				}
+			},
+			Err(e) => {
+				eprintln!("{}: {e}", path.display());
+				ok = false;
+			}
+		}
 	}
+	ok
+}
+
+// `lll fmt` without files formats stdin to stdout, with `check` only reports if it would change
+pub fn format_stdin(check: bool) -> bool {
+	let mut text = String::new();
+	if std::io::Read::read_to_string(&mut std::io::stdin(), &mut text).is_err() {
+		eprintln!("FATAL: not valid utf-8");
+		return false;
+	}
+
+	match fmt::format(&text) {
+		Ok(formatted) if check => {
+			if formatted != text {
+				println!("stdin is not formatted");
+			}
+			formatted == text
+		},
+		Ok(formatted) => {
+			print!("{formatted}");
+			true
+		},
+		Err(e) => {
+			eprintln!("{e}");
+			false
+		}
+	}
+}
+
+// Exits cleanly only after the client asked for a shutdown
+pub fn serve_lsp() -> bool {
+	lsp::serve(&mut std::io::stdin().lock(), &mut std::io::stdout().lock())
 }
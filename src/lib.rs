@@ -0,0 +1,2 @@
+pub mod lll;
+pub mod lang;
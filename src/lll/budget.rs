@@ -0,0 +1,122 @@
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
+use std::alloc::System;
+use std::cell::Cell;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::error::Error;
+use super::error::ErrorKind;
+
+// What a run may use up, for scripts that can't be trusted. Nothing is limited by default.
+#[derive(Clone, Default)]
+pub struct Limits {
+	// statements and expressions in the tree walker, instructions in the vm
+	pub steps: Option<u64>,
+	pub time: Option<Duration>,
+	// heap bytes on top of what the thread had when the limits were set, only known
+	// with `Counting` as the global allocator
+	pub memory: Option<usize>,
+	// stops the run once it is set, from any thread
+	pub cancel: Option<Arc<AtomicBool>>
+}
+
+// The clock and the cancel flag are looked at every this many steps
+const CHECK_EVERY: u64 = 1024;
+
+// How much of its `Limits` a run has used, one per interpreter or vm
+pub struct Budget {
+	limits: Limits,
+	steps: u64,
+	// starts with the first step
+	start: Option<Instant>,
+	heap: isize
+}
+
+impl Budget {
+	pub fn new(limits: Limits) -> Result<Budget, Error> {
+		if limits.memory.is_some() && !INSTALLED.load(Ordering::Relaxed) {
+			return Err(Error::fatal("a memory limit needs budget::Counting as the global allocator", None));
+		}
+		Ok(Budget { limits, steps: 0, start: None, heap: heap() })
+	}
+
+	pub fn step(&mut self) -> Result<(), Error> {
+		self.steps += 1;
+		if let Some(max) = self.limits.steps {
+			if self.steps > max {
+				return Err(exceeded(format!("step limit of {max} reached")));
+			}
+		}
+		if let Some(max) = self.limits.memory {
+			if heap() - self.heap > max as isize {
+				return Err(exceeded(format!("memory limit of {max} bytes reached")));
+			}
+		}
+
+		if self.steps % CHECK_EVERY == 1 {
+			let start = *self.start.get_or_insert_with(Instant::now);
+			if let Some(max) = self.limits.time {
+				if start.elapsed() > max {
+					return Err(exceeded(format!("time limit of {}ms reached", max.as_millis())));
+				}
+			}
+			if self.limits.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+				return Err(exceeded("cancelled".to_string()));
+			}
+		}
+		Ok(())
+	}
+}
+
+fn exceeded(msg: String) -> Error {
+	Error::fatal(&msg, None).of(ErrorKind::Limit)
+}
+
+// Keeps count of the bytes every thread has on the heap, for memory limits. A binary
+// that wants them sets it up with
+//
+//     #[global_allocator]
+//     static ALLOCATOR: budget::Counting = budget::Counting;
+pub struct Counting;
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+	// can go below zero on a thread that frees what another one allocated
+	static HEAP: Cell<isize> = const { Cell::new(0) };
+}
+
+fn heap() -> isize {
+	HEAP.with(Cell::get)
+}
+
+fn count(bytes: isize) {
+	// a load is all it takes after the first allocation, a store on every one would keep
+	// the cache line bouncing between threads
+	if !INSTALLED.load(Ordering::Relaxed) {
+		INSTALLED.store(true, Ordering::Relaxed);
+	}
+	// gone while the thread shuts down
+	let _ = HEAP.try_with(|heap| heap.set(heap.get() + bytes));
+}
+
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		count(layout.size() as isize);
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		count(-(layout.size() as isize));
+		System.dealloc(ptr, layout)
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		count(new_size as isize - layout.size() as isize);
+		System.realloc(ptr, layout, new_size)
+	}
+}
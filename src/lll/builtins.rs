@@ -0,0 +1,402 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use super::capability::Capability;
+use super::error::Error;
+use super::error::ErrorKind;
+use super::gc;
+use super::gc::Object;
+use super::token::Token;
+use super::token::Literal;
+use super::token::Items;
+use super::token::ListRef;
+use super::token::MapRef;
+use super::map::Map;
+
+// Lets natives like `map` call back into whatever runs the script
+pub trait Caller {
+	fn call_value(&mut self, callee: &Literal, t: &Token, args: Vec<Literal>) -> Result<Literal, Error>;
+	fn allows(&self, capability: Capability) -> bool;
+}
+
+// How many calls deep a script may go unless told otherwise
+pub const MAX_DEPTH: usize = 1000;
+
+// For a call that would go past the limit, catchable like any other runtime error
+pub fn stack_overflow(t: &Token, max_depth: usize) -> Error {
+	Error::fatal(format!("stack overflow, more than {max_depth} calls deep").as_str(), Some(t)).of(ErrorKind::Recursion)
+}
+
+pub struct Native {
+	pub name: &'static str,
+	pub arity: usize,
+	pub func: fn(&mut dyn Caller, &Token, Vec<Literal>) -> Result<Literal, Error>,
+	// what it reaches outside of the script for, if anything
+	pub needs: Option<Capability>
+}
+
+impl std::fmt::Debug for Native {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "<native {}>", self.name)
+	}
+}
+
+static NATIVES: &[Native] = &[
+	Native { name: "len", arity: 1, func: len, needs: None },
+	Native { name: "push", arity: 2, func: push, needs: None },
+	Native { name: "pop", arity: 1, func: pop, needs: None },
+	Native { name: "slice", arity: 3, func: slice, needs: None },
+	Native { name: "insert", arity: 3, func: insert, needs: None },
+	Native { name: "remove", arity: 2, func: remove, needs: None },
+	Native { name: "contains", arity: 2, func: contains, needs: None },
+	Native { name: "sort", arity: 1, func: sort, needs: None },
+	Native { name: "keys", arity: 1, func: keys, needs: None },
+	Native { name: "values", arity: 1, func: values, needs: None },
+	Native { name: "has", arity: 2, func: has, needs: None },
+	Native { name: "map", arity: 2, func: map, needs: None },
+	Native { name: "filter", arity: 2, func: filter, needs: None },
+	Native { name: "reduce", arity: 3, func: reduce, needs: None },
+	Native { name: "sort_by", arity: 2, func: sort_by, needs: None },
+	Native { name: "clock", arity: 0, func: clock, needs: Some(Capability::Time) },
+	Native { name: "env", arity: 1, func: env, needs: Some(Capability::Env) },
+	Native { name: "read_stdin", arity: 0, func: read_stdin, needs: Some(Capability::Stdin) },
+	Native { name: "exit", arity: 1, func: exit, needs: Some(Capability::Process) },
+];
+
+pub fn natives() -> &'static [Native] {
+	NATIVES
+}
+
+// Globals that aren't natives, every backend defines them
+pub const GLOBALS: &[&str] = &["args"];
+
+// Whatever a script can use without declaring it
+pub fn is_predefined(name: &str) -> bool {
+	NATIVES.iter().any(|n| n.name == name) || GLOBALS.contains(&name)
+}
+
+pub fn call_native(caller: &mut dyn Caller, native: &Native, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	if args.len() != native.arity {
+		return Err(Error::fatal(format!("{} expects {} arguments, got {}", native.name, native.arity, args.len()).as_str(), Some(t)).of(ErrorKind::Arity));
+	}
+	if let Some(capability) = native.needs.filter(|c| !caller.allows(*c)) {
+		return Err(Error::fatal(format!("{} needs the {} capability, which is denied", native.name, capability.name()).as_str(), Some(t)).of(ErrorKind::Denied));
+	}
+	(native.func)(caller, t, args)
+}
+
+// What the `print` statement writes for a value
+pub fn print(v: &Literal) {
+	match v {
+		Literal::Identifier(_) => print!("identifier"),
+		v => print!("{v}"),
+	}
+}
+
+pub fn new_list(vals: Vec<Literal>) -> Literal {
+	let list = Rc::new(RefCell::new(Items(vals)));
+	gc::track(Object::List(list.clone()));
+	Literal::List(list)
+}
+
+pub fn new_map(map: Map) -> Literal {
+	let map = Rc::new(RefCell::new(map));
+	gc::track(Object::Map(map.clone()));
+	Literal::Map(map)
+}
+
+// `container[key]` for lists, strings and maps
+pub fn get_item(container: &Literal, key: &Literal, t: &Token) -> Result<Literal, Error> {
+	match container {
+		Literal::List(l) => {
+			let l = l.borrow();
+			Ok(l[index(l.len(), key, t)?].clone())
+		},
+		Literal::String(s) => {
+			let chars: Vec<char> = s.chars().collect();
+			Ok(Literal::String(chars[index(chars.len(), key, t)?].to_string()))
+		},
+		Literal::Map(m) => {
+			match m.borrow().get(key) {
+				Some(v) => Ok(v.clone()),
+				None => Err(Error::fatal(format!("key {key} not found").as_str(), Some(t)).of(ErrorKind::Key))
+			}
+		},
+		_ => Err(Error::fatal("only lists, strings and maps can be indexed", Some(t)).of(ErrorKind::Type))
+	}
+}
+
+// `container[key] = val` for lists and maps
+pub fn set_item(container: &Literal, key: Literal, val: Literal, t: &Token) -> Result<(), Error> {
+	match container {
+		Literal::List(l) => {
+			let i = index(l.borrow().len(), &key, t)?;
+			l.borrow_mut()[i] = val;
+			Ok(())
+		},
+		Literal::Map(m) => {
+			if !m.borrow_mut().insert(key, val) {
+				return Err(Error::fatal("map keys must be strings or numbers", Some(t)).of(ErrorKind::Type));
+			}
+			Ok(())
+		},
+		_ => Err(Error::fatal("only list elements and map entries can be assigned", Some(t)).of(ErrorKind::Type))
+	}
+}
+
+// Turns an lll index into a position inside `len` elements, negative indices count from the end
+pub fn index(len: usize, i: &Literal, t: &Token) -> Result<usize, Error> {
+	let pos = position(len, i, t)?;
+	if pos < 0 || pos >= len as i64 {
+		return Err(Error::fatal(format!("index {i} out of range for length {len}").as_str(), Some(t)).of(ErrorKind::Index));
+	}
+
+	Ok(pos as usize)
+}
+
+fn position(len: usize, i: &Literal, t: &Token) -> Result<i64, Error> {
+	let Literal::Float(v) = i else {
+		return Err(Error::fatal("index must be a number", Some(t)).of(ErrorKind::Type));
+	};
+	if v.fract() != 0.0 {
+		return Err(Error::fatal("index must be an integer", Some(t)).of(ErrorKind::Type));
+	}
+
+	let v = *v as i64;
+	if v < 0 {
+		Ok(v + len as i64)
+	} else {
+		Ok(v)
+	}
+}
+
+fn list_arg(name: &str, v: &Literal, t: &Token) -> Result<ListRef, Error> {
+	match v {
+		Literal::List(l) => Ok(l.clone()),
+		_ => Err(Error::fatal(format!("{name} expects a list").as_str(), Some(t)).of(ErrorKind::Type))
+	}
+}
+
+fn map_arg(name: &str, v: &Literal, t: &Token) -> Result<MapRef, Error> {
+	match v {
+		Literal::Map(m) => Ok(m.clone()),
+		_ => Err(Error::fatal(format!("{name} expects a map").as_str(), Some(t)).of(ErrorKind::Type))
+	}
+}
+
+fn len(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	match &args[0] {
+		Literal::List(l) => Ok(Literal::Float(l.borrow().len() as f64)),
+		Literal::Map(m) => Ok(Literal::Float(m.borrow().len() as f64)),
+		Literal::String(s) => Ok(Literal::Float(s.chars().count() as f64)),
+		_ => Err(Error::fatal("len expects a list, a map or a string", Some(t)).of(ErrorKind::Type))
+	}
+}
+
+fn push(_: &mut dyn Caller, t: &Token, mut args: Vec<Literal>) -> Result<Literal, Error> {
+	let v = args.pop().unwrap();
+	list_arg("push", &args[0], t)?.borrow_mut().push(v);
+	Ok(Literal::Nil)
+}
+
+fn pop(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let Some(v) = list_arg("pop", &args[0], t)?.borrow_mut().pop() else {
+		return Err(Error::fatal("pop from empty list", Some(t)).of(ErrorKind::Index));
+	};
+	Ok(v)
+}
+
+fn slice(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let bounds = |len: usize| -> Result<(usize, usize), Error> {
+		let start = position(len, &args[1], t)?.clamp(0, len as i64) as usize;
+		let end = position(len, &args[2], t)?.clamp(0, len as i64) as usize;
+		Ok((start, end.max(start)))
+	};
+
+	match &args[0] {
+		Literal::List(l) => {
+			let l = l.borrow();
+			let (start, end) = bounds(l.len())?;
+			Ok(new_list(l[start..end].to_vec()))
+		},
+		Literal::String(s) => {
+			let chars: Vec<char> = s.chars().collect();
+			let (start, end) = bounds(chars.len())?;
+			Ok(Literal::String(chars[start..end].iter().collect()))
+		},
+		_ => Err(Error::fatal("slice expects a list or a string", Some(t)).of(ErrorKind::Type))
+	}
+}
+
+fn insert(_: &mut dyn Caller, t: &Token, mut args: Vec<Literal>) -> Result<Literal, Error> {
+	let v = args.pop().unwrap();
+	let l = list_arg("insert", &args[0], t)?;
+	let len = l.borrow().len();
+	let pos = position(len, &args[1], t)?;
+	if pos < 0 || pos > len as i64 {
+		return Err(Error::fatal(format!("index {} out of range for length {len}", args[1]).as_str(), Some(t)).of(ErrorKind::Index));
+	}
+
+	l.borrow_mut().insert(pos as usize, v);
+	Ok(Literal::Nil)
+}
+
+fn remove(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	if let Literal::Map(m) = &args[0] {
+		let Some(v) = m.borrow_mut().remove(&args[1]) else {
+			return Err(Error::fatal(format!("key {} not found", args[1]).as_str(), Some(t)).of(ErrorKind::Key));
+		};
+		return Ok(v);
+	}
+
+	let l = list_arg("remove", &args[0], t)?;
+	let i = index(l.borrow().len(), &args[1], t)?;
+	let v = l.borrow_mut().remove(i);
+	Ok(v)
+}
+
+fn contains(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	match (&args[0], &args[1]) {
+		(Literal::List(l), v) => Ok(Literal::Bool(l.borrow().iter().any(|i| i.equals(v)))),
+		(Literal::Map(m), k) => Ok(Literal::Bool(m.borrow().get(k).is_some())),
+		(Literal::String(s), Literal::String(v)) => Ok(Literal::Bool(s.contains(v.as_str()))),
+		_ => Err(Error::fatal("contains expects a list, a map or two strings", Some(t)).of(ErrorKind::Type))
+	}
+}
+
+fn sort(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let l = list_arg("sort", &args[0], t)?;
+	let mut l = l.borrow_mut();
+
+	if l.iter().all(|v| matches!(v, Literal::Float(_))) {
+		l.sort_by(|a, b| match (a, b) {
+			(Literal::Float(a), Literal::Float(b)) => a.total_cmp(b),
+			_ => std::cmp::Ordering::Equal
+		});
+	} else if l.iter().all(|v| matches!(v, Literal::String(_))) {
+		l.sort_by(|a, b| match (a, b) {
+			(Literal::String(a), Literal::String(b)) => a.cmp(b),
+			_ => std::cmp::Ordering::Equal
+		});
+	} else {
+		return Err(Error::fatal("sort expects a list of only numbers or only strings", Some(t)).of(ErrorKind::Type));
+	}
+
+	Ok(Literal::Nil)
+}
+
+fn keys(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let m = map_arg("keys", &args[0], t)?;
+	let keys = m.borrow().iter().map(|(k, _)| k.clone()).collect();
+	Ok(new_list(keys))
+}
+
+fn values(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let m = map_arg("values", &args[0], t)?;
+	let vals = m.borrow().iter().map(|(_, v)| v.clone()).collect();
+	Ok(new_list(vals))
+}
+
+fn has(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let m = map_arg("has", &args[0], t)?;
+	let found = m.borrow().get(&args[1]).is_some();
+	Ok(Literal::Bool(found))
+}
+
+fn map(caller: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	// cloned so the callback is free to change the list
+	let vals = list_arg("map", &args[0], t)?.borrow().to_vec();
+	let mut res = Vec::with_capacity(vals.len());
+	for v in vals {
+		res.push(caller.call_value(&args[1], t, vec![v])?);
+	}
+	Ok(new_list(res))
+}
+
+fn filter(caller: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let vals = list_arg("filter", &args[0], t)?.borrow().to_vec();
+	let mut res = Vec::new();
+	for v in vals {
+		if Literal::is_true_val(caller.call_value(&args[1], t, vec![v.clone()])?)? {
+			res.push(v);
+		}
+	}
+	Ok(new_list(res))
+}
+
+fn reduce(caller: &mut dyn Caller, t: &Token, mut args: Vec<Literal>) -> Result<Literal, Error> {
+	let mut acc = args.pop().unwrap();
+	let vals = list_arg("reduce", &args[0], t)?.borrow().to_vec();
+	for v in vals {
+		acc = caller.call_value(&args[1], t, vec![acc, v])?;
+	}
+	Ok(acc)
+}
+
+// The comparator returns a number, below zero when its first argument goes first
+fn sort_by(caller: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let l = list_arg("sort_by", &args[0], t)?;
+	let vals = l.borrow().to_vec();
+
+	let mut compare = |a: &Literal, b: &Literal| match caller.call_value(&args[1], t, vec![a.clone(), b.clone()])? {
+		Literal::Float(v) => Ok(v > 0.0),
+		_ => Err(Error::fatal("sort_by comparator must return a number", Some(t)).of(ErrorKind::Type))
+	};
+	let vals = merge_sort(vals, &mut compare)?;
+
+	l.borrow_mut().0 = vals;
+	Ok(Literal::Nil)
+}
+
+// Stable, and unlike the std sorts fine with a comparator that contradicts itself, the
+// order just comes out odd. `after(a, b)` is whether `a` goes after `b`.
+fn merge_sort(mut vals: Vec<Literal>, after: &mut impl FnMut(&Literal, &Literal) -> Result<bool, Error>) -> Result<Vec<Literal>, Error> {
+	if vals.len() < 2 {
+		return Ok(vals);
+	}
+
+	let right = vals.split_off(vals.len() / 2);
+	let mut left = merge_sort(vals, after)?.into_iter().peekable();
+	let mut right = merge_sort(right, after)?.into_iter().peekable();
+	let mut merged = Vec::with_capacity(left.len() + right.len());
+	while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+		let next = if after(a, b)? { right.next() } else { left.next() };
+		merged.extend(next);
+	}
+	merged.extend(left);
+	merged.extend(right);
+	Ok(merged)
+}
+
+// Seconds since the unix epoch, with the fraction
+fn clock(_: &mut dyn Caller, _: &Token, _: Vec<Literal>) -> Result<Literal, Error> {
+	let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+	Ok(Literal::Float(now.as_secs_f64()))
+}
+
+// The variable's value, nil when it isn't set or isn't unicode
+fn env(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let Literal::String(name) = &args[0] else {
+		return Err(Error::fatal("env expects a string", Some(t)).of(ErrorKind::Type));
+	};
+	Ok(std::env::var(name).map_or(Literal::Nil, Literal::String))
+}
+
+// Everything left on stdin, until it is closed
+fn read_stdin(_: &mut dyn Caller, t: &Token, _: Vec<Literal>) -> Result<Literal, Error> {
+	let mut text = String::new();
+	match std::io::Read::read_to_string(&mut std::io::stdin(), &mut text) {
+		Ok(_) => Ok(Literal::String(text)),
+		Err(e) => Err(Error::fatal(format!("read_stdin: {e}").as_str(), Some(t)).of(ErrorKind::Io))
+	}
+}
+
+// Unwinds out of everything, whoever runs the script gets the code
+fn exit(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	match args[0] {
+		Literal::Float(code) if code.fract() == 0.0 && code.abs() <= i32::MAX as f64 => {
+			Err(Error::fatal(format!("exit {code}").as_str(), Some(t)).of(ErrorKind::Exit(code as i32)))
+		},
+		_ => Err(Error::fatal("exit expects a whole number", Some(t)).of(ErrorKind::Type))
+	}
+}
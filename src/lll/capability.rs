@@ -0,0 +1,63 @@
+// What a built-in reaches outside of the script for, so whoever runs it can take it away
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+	FsRead,
+	FsWrite,
+	Env,
+	Time,
+	Process,
+	Stdin
+}
+
+impl Capability {
+	pub const ALL: [Capability; 6] = [
+		Capability::FsRead,
+		Capability::FsWrite,
+		Capability::Env,
+		Capability::Time,
+		Capability::Process,
+		Capability::Stdin
+	];
+
+	// What `--allow-cap` and `--deny-cap` take
+	pub fn name(&self) -> &'static str {
+		match self {
+			Capability::FsRead => "fs-read",
+			Capability::FsWrite => "fs-write",
+			Capability::Env => "env",
+			Capability::Time => "time",
+			Capability::Process => "process",
+			Capability::Stdin => "stdin"
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Capability> {
+		Capability::ALL.into_iter().find(|c| c.name() == name)
+	}
+}
+
+// Everything is allowed unless told otherwise
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+	allowed: [bool; Capability::ALL.len()]
+}
+
+impl Default for Capabilities {
+	fn default() -> Self {
+		Self { allowed: [true; Capability::ALL.len()] }
+	}
+}
+
+impl Capabilities {
+	pub fn set(&mut self, capability: Capability, allowed: bool) {
+		self.allowed[capability as usize] = allowed;
+	}
+
+	pub fn set_all(&mut self, allowed: bool) {
+		self.allowed = [allowed; Capability::ALL.len()];
+	}
+
+	pub fn allows(&self, capability: Capability) -> bool {
+		self.allowed[capability as usize]
+	}
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::token::Token;
+use super::token::Literal;
+use super::symbol::Symbol;
+
+// Operands follow the opcode byte, u16 operands are big endian.
+// `tok` operands index `Chunk::tokens` and are only used for error messages.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+	Constant, // u16 constant
+	Nil,
+	True,
+	False,
+	Pop,
+	GetLocal, // u8 slot
+	SetLocal, // u8 slot
+	DefineGlobal, // u16 tok
+	GetGlobal, // u16 tok
+	SetGlobal, // u16 tok
+	GetUpvalue, // u8 upvalue
+	SetUpvalue, // u8 upvalue
+	Add,
+	Sub,
+	Mul,
+	Div,
+	Rem,
+	Equal,
+	NotEqual,
+	Greater,
+	GreaterEqual,
+	Less,
+	LessEqual,
+	Not, // u16 tok
+	Negate,
+	Step, // u8 direction, u16 tok
+	StepIndex, // u8 direction, u16 tok of the operator, u16 tok of the bracket
+	Print,
+	Jump, // u16 forward offset
+	JumpIfFalse, // u16 forward offset, leaves the condition on the stack
+	Loop, // u16 backward offset
+	Call, // u8 argument count, u16 tok
+	Closure, // u16 function, then a (u8 is local, u8 index) pair per upvalue
+	CloseUpvalue,
+	Return,
+	List, // u16 element count
+	Map, // u16 entry count, u16 tok
+	Index, // u16 tok
+	SetIndex, // u16 tok
+	UpdateIndex, // u8 binary opcode, u16 tok
+	Import, // u16 constant path, u16 tok, pushes the exports map
+	DefineExports, // pops an exports map and defines each entry as a global
+	Try, // u8 catches, u16 forward offset to the handler
+	EndTry,
+	Throw, // u16 tok
+	Rethrow, // raises again the error a finally handler was entered with
+}
+
+impl OpCode {
+	const ALL: [OpCode; 46] = {
+		use OpCode::*;
+		[Constant, Nil, True, False, Pop, GetLocal, SetLocal, DefineGlobal, GetGlobal, SetGlobal,
+		 GetUpvalue, SetUpvalue, Add, Sub, Mul, Div, Rem, Equal, NotEqual, Greater,
+		 GreaterEqual, Less, LessEqual, Not, Negate, Step, StepIndex, Print, Jump, JumpIfFalse,
+		 Loop, Call, Closure, CloseUpvalue, Return, List, Map, Index, SetIndex, UpdateIndex,
+		 Import, DefineExports, Try, EndTry, Throw, Rethrow]
+	};
+
+	pub fn from_byte(b: u8) -> Option<OpCode> {
+		Self::ALL.get(b as usize).copied()
+	}
+}
+
+#[derive(Default)]
+pub struct Chunk {
+	pub code: Vec<u8>,
+	pub lines: Vec<usize>,
+	pub constants: Vec<Literal>,
+	pub functions: Vec<Rc<Proto>>,
+	pub tokens: Vec<Token>,
+	// where every top level statement of a script starts, the vm resumes there after an error
+	pub statements: Vec<usize>,
+	// string constants by their interned text, the same string is only stored once. Chunks
+	// are compiled on the thread of the run, so the interner goes away with it.
+	strings: HashMap<Symbol, usize>
+}
+
+impl Chunk {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn write(&mut self, byte: u8, line: usize) {
+		self.code.push(byte);
+		self.lines.push(line);
+	}
+
+	pub fn write_u16(&mut self, v: u16, line: usize) {
+		self.write((v >> 8) as u8, line);
+		self.write((v & 0xff) as u8, line);
+	}
+
+	pub fn read_u16(&self, offset: usize) -> u16 {
+		((self.code[offset] as u16) << 8) | self.code[offset + 1] as u16
+	}
+
+	pub fn add_constant(&mut self, v: Literal) -> usize {
+		if let Literal::String(s) = &v {
+			let len = self.constants.len();
+			let index = *self.strings.entry(Symbol::intern(s)).or_insert(len);
+			if index != len {
+				return index;
+			}
+		}
+		self.constants.push(v);
+		self.constants.len() - 1
+	}
+
+	pub fn add_token(&mut self, t: &Token) -> usize {
+		self.tokens.push(t.clone());
+		self.tokens.len() - 1
+	}
+
+	pub fn add_function(&mut self, f: Proto) -> usize {
+		self.functions.push(Rc::new(f));
+		self.functions.len() - 1
+	}
+}
+
+// A compiled function, closures are made from it at runtime
+pub struct Proto {
+	pub name: Option<String>,
+	pub arity: usize,
+	pub upvalues: usize,
+	pub chunk: Chunk
+}
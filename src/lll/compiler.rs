@@ -0,0 +1,650 @@
+use std::rc::Rc;
+
+use super::chunk::Chunk;
+use super::chunk::OpCode;
+use super::chunk::Proto;
+use super::error::Error;
+use super::parse::Expr;
+use super::parse::Function;
+use super::parse::Stmt;
+use super::token::Literal;
+use super::token::Token;
+use super::token::TokenType;
+use super::symbol::Symbol;
+
+struct Local {
+	name: Symbol,
+	depth: usize,
+	captured: bool
+}
+
+struct Upvalue {
+	index: u8,
+	is_local: bool
+}
+
+struct Loop {
+	depth: usize,
+	breaks: Vec<usize>,
+	continues: Vec<usize>
+}
+
+// A try the code is inside of, leaving it early has to drop its handler and run its finally
+#[derive(Clone)]
+struct Guard {
+	loops: usize,
+	finally: Option<Rc<Vec<Stmt>>>
+}
+
+// Everything known about the function currently being compiled
+struct FunctionState {
+	proto: Proto,
+	locals: Vec<Local>,
+	upvalues: Vec<Upvalue>,
+	depth: usize,
+	loops: Vec<Loop>,
+	tries: Vec<Guard>
+}
+
+impl FunctionState {
+	fn new(name: Option<String>, arity: usize) -> Self {
+		// slot zero holds the called closure itself
+		let callee = Local { name: Symbol::intern(""), depth: 0, captured: false };
+		let proto = Proto { name, arity, upvalues: 0, chunk: Chunk::new() };
+		Self { proto, locals: vec![callee], upvalues: Vec::new(), depth: 0, loops: Vec::new(), tries: Vec::new() }
+	}
+}
+
+pub struct Compiler {
+	functions: Vec<FunctionState>,
+	line: usize
+}
+
+type ResUnit = Result<(), Error>;
+impl Compiler {
+	pub fn compile(stmts: &[Stmt]) -> Result<Proto, Error> {
+		let mut compiler = Self { functions: vec![FunctionState::new(None, 0)], line: 0 };
+
+		for i in stmts {
+			let start = compiler.chunk().code.len();
+			compiler.chunk().statements.push(start);
+			compiler.statement(i)?;
+		}
+		compiler.emit(OpCode::Nil);
+		compiler.emit(OpCode::Return);
+
+		Ok(compiler.functions.pop().unwrap().proto)
+	}
+
+	fn state(&mut self) -> &mut FunctionState {
+		self.functions.last_mut().unwrap()
+	}
+
+	fn chunk(&mut self) -> &mut Chunk {
+		&mut self.state().proto.chunk
+	}
+
+	fn statement(&mut self, stmt: &Stmt) -> ResUnit {
+		match stmt {
+			Stmt::Print(v) => {
+				self.expression(v)?;
+				self.emit(OpCode::Print);
+			},
+			Stmt::Expression(v) => {
+				self.expression(v)?;
+				self.emit(OpCode::Pop);
+			},
+			Stmt::Variable(t, v, _) => {
+				// the initializer still sees an outer variable of the same name
+				self.expression(v)?;
+				self.define(t)?;
+			},
+			Stmt::Function(f) => {
+				let Some(name) = &f.name else {
+					return Err(Error::fatal("function declaration without a name", None));
+				};
+				if self.state().depth > 0 {
+					// declared first so the body can call itself
+					self.add_local(name)?;
+					self.function(f)?;
+				} else {
+					self.function(f)?;
+					self.define(name)?;
+				}
+			},
+			Stmt::Block(v) => self.block(v)?,
+			Stmt::If(..) => {
+				let (arms, otherwise) = stmt.arms();
+				let mut end_jumps = Vec::new();
+				for (_, c, then) in arms {
+					self.expression(c)?;
+					let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+					self.emit(OpCode::Pop);
+					self.statement(then)?;
+
+					end_jumps.push(self.emit_jump(OpCode::Jump));
+					self.patch_jump(then_jump)?;
+					self.emit(OpCode::Pop);
+				}
+				if let Some(otherwise) = otherwise {
+					self.statement(otherwise)?;
+				}
+				for jump in end_jumps {
+					self.patch_jump(jump)?;
+				}
+			},
+			Stmt::While(c, body, incr) => self.whileloop(c, body, incr)?,
+			Stmt::Break => {
+				let jump = self.jump_out_of_loop()?;
+				self.state().loops.last_mut().unwrap().breaks.push(jump);
+			},
+			Stmt::Continue => {
+				let jump = self.jump_out_of_loop()?;
+				self.state().loops.last_mut().unwrap().continues.push(jump);
+			},
+			Stmt::Return(keyword, v) => {
+				self.expression(v)?;
+				if !self.state().tries.is_empty() {
+					// the value waits in a nameless slot while the finally blocks run
+					self.add_local(keyword)?;
+					self.leave_tries(0)?;
+					self.state().locals.pop();
+				}
+				self.emit(OpCode::Return);
+			},
+			Stmt::Import(t, path, alias) => {
+				let path = self.chunk().add_constant(Literal::String(path.clone()));
+				self.emit(OpCode::Import);
+				self.emit_u16(path, "constants")?;
+				self.emit_token(t)?;
+				match alias {
+					Some(alias) => self.define(alias)?,
+					None => self.emit(OpCode::DefineExports)
+				}
+			},
+			Stmt::Export(decl) => self.statement(decl)?,
+			Stmt::Try(_, body, catch, finally) => self.try_catch(body, catch, finally)?,
+			Stmt::Throw(t, v) => {
+				self.expression(v)?;
+				self.emit(OpCode::Throw);
+				self.emit_token(t)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn block(&mut self, stmts: &[Stmt]) -> ResUnit {
+		self.begin_scope();
+		for i in stmts {
+			self.statement(i)?;
+		}
+		self.end_scope();
+		Ok(())
+	}
+
+	// The finally block is compiled wherever the try can be left: after the body, after the
+	// catch, on the way out of an error, and before a break, continue or return.
+	fn try_catch(&mut self, body: &[Stmt], catch: &Option<(Token, Vec<Stmt>)>, finally: &Option<Rc<Vec<Stmt>>>) -> ResUnit {
+		let handler = self.guarded(catch.is_some(), finally, |compiler| compiler.block(body))?;
+		let done = self.emit_jump(OpCode::Jump);
+		self.patch_jump(handler)?;
+
+		match catch {
+			Some((name, stmts)) => {
+				// the error value is already on the stack, right where the variable goes
+				self.begin_scope();
+				self.add_local(name)?;
+				if finally.is_some() {
+					let handler = self.guarded(false, finally, |compiler| stmts.iter().try_for_each(|i| compiler.statement(i)))?;
+					let after = self.emit_jump(OpCode::Jump);
+					self.patch_jump(handler)?;
+					self.rethrow(finally)?;
+					self.patch_jump(after)?;
+				} else {
+					for i in stmts {
+						self.statement(i)?;
+					}
+				}
+				self.end_scope();
+			},
+			None => self.rethrow(finally)?
+		}
+
+		self.patch_jump(done)
+	}
+
+	// Compiles `body` with a handler set up, then leaves it normally. Gives back the jump to
+	// patch to the handler's code.
+	fn guarded(&mut self, catches: bool, finally: &Option<Rc<Vec<Stmt>>>, body: impl FnOnce(&mut Self) -> ResUnit) -> Result<usize, Error> {
+		self.emit(OpCode::Try);
+		self.emit_byte(catches as u8);
+		let handler = self.emit_jump_offset();
+
+		let loops = self.state().loops.len();
+		self.state().tries.push(Guard { loops, finally: finally.clone() });
+		body(self)?;
+		self.state().tries.pop();
+
+		self.emit(OpCode::EndTry);
+		if let Some(finally) = finally {
+			self.block(finally)?;
+		}
+		Ok(handler)
+	}
+
+	fn rethrow(&mut self, finally: &Option<Rc<Vec<Stmt>>>) -> ResUnit {
+		if let Some(finally) = finally {
+			self.block(finally)?;
+		}
+		self.emit(OpCode::Rethrow);
+		Ok(())
+	}
+
+	// Leaves every try from `keep` on, innermost first. Each finally is compiled with only
+	// the tries around it still in place.
+	fn leave_tries(&mut self, keep: usize) -> ResUnit {
+		let tries = std::mem::take(&mut self.state().tries);
+		for i in (keep..tries.len()).rev() {
+			self.state().tries = tries[..i].to_vec();
+			self.emit(OpCode::EndTry);
+			if let Some(finally) = &tries[i].finally {
+				self.block(finally)?;
+			}
+		}
+		self.state().tries = tries;
+		Ok(())
+	}
+
+	fn whileloop(&mut self, c: &Expr, body: &Stmt, incr: &Option<Expr>) -> ResUnit {
+		let start = self.chunk().code.len();
+		self.expression(c)?;
+		let exit = self.emit_jump(OpCode::JumpIfFalse);
+		self.emit(OpCode::Pop);
+
+		let depth = self.state().depth;
+		self.state().loops.push(Loop { depth, breaks: Vec::new(), continues: Vec::new() });
+		self.statement(body)?;
+		let looped = self.state().loops.pop().unwrap();
+
+		for jump in looped.continues {
+			self.patch_jump(jump)?;
+		}
+		if let Some(incr) = incr {
+			self.expression(incr)?;
+			self.emit(OpCode::Pop);
+		}
+		self.emit_loop(start)?;
+
+		self.patch_jump(exit)?;
+		self.emit(OpCode::Pop);
+		for jump in looped.breaks {
+			self.patch_jump(jump)?;
+		}
+		Ok(())
+	}
+
+	// Drops the locals of the loop body without forgetting them, the code after the jump still uses them
+	fn jump_out_of_loop(&mut self) -> Result<usize, Error> {
+		let Some(depth) = self.state().loops.last().map(|l| l.depth) else {
+			return Err(Error::fatal("break and continue are only allowed inside of a loop", None));
+		};
+		let loops = self.state().loops.len();
+		let keep = self.state().tries.iter().position(|g| g.loops >= loops).unwrap_or(self.state().tries.len());
+		self.leave_tries(keep)?;
+
+		let ops: Vec<OpCode> = self.state().locals.iter().rev()
+			.take_while(|l| l.depth > depth)
+			.map(|l| if l.captured { OpCode::CloseUpvalue } else { OpCode::Pop })
+			.collect();
+		for op in ops {
+			self.emit(op);
+		}
+
+		Ok(self.emit_jump(OpCode::Jump))
+	}
+
+	fn function(&mut self, f: &Function) -> ResUnit {
+		let name = f.name.as_ref().map(|t| t.literal.to_string());
+		self.functions.push(FunctionState::new(name, f.params.len()));
+		self.begin_scope();
+
+		for param in &f.params {
+			self.add_local(param)?;
+		}
+		for i in &f.body {
+			self.statement(i)?;
+		}
+		self.emit(OpCode::Nil);
+		self.emit(OpCode::Return);
+
+		let state = self.functions.pop().unwrap();
+		let mut proto = state.proto;
+		proto.upvalues = state.upvalues.len();
+
+		let index = self.chunk().add_function(proto);
+		self.emit(OpCode::Closure);
+		self.emit_u16(index, "functions")?;
+		for upvalue in state.upvalues {
+			self.emit_byte(upvalue.is_local as u8);
+			self.emit_byte(upvalue.index);
+		}
+		Ok(())
+	}
+
+	fn begin_scope(&mut self) {
+		self.state().depth += 1;
+	}
+
+	fn end_scope(&mut self) {
+		self.state().depth -= 1;
+
+		let depth = self.state().depth;
+		while let Some(local) = self.state().locals.last() {
+			if local.depth <= depth {
+				break;
+			}
+			let op = if local.captured { OpCode::CloseUpvalue } else { OpCode::Pop };
+			self.emit(op);
+			self.state().locals.pop();
+		}
+	}
+
+	fn define(&mut self, t: &Token) -> ResUnit {
+		if self.state().depth > 0 {
+			return self.add_local(t);
+		}
+
+		self.emit(OpCode::DefineGlobal);
+		self.emit_token(t)
+	}
+
+	fn add_local(&mut self, t: &Token) -> ResUnit {
+		if self.state().locals.len() > u8::MAX as usize {
+			return Err(Error::fatal("too many local variables in function", Some(t)));
+		}
+
+		let depth = self.state().depth;
+		self.state().locals.push(Local { name: symbol(t), depth, captured: false });
+		Ok(())
+	}
+
+	fn resolve_local(&self, function: usize, name: Symbol) -> Option<u8> {
+		let locals = &self.functions[function].locals;
+		locals.iter().rposition(|l| l.name == name).map(|i| i as u8)
+	}
+
+	fn resolve_upvalue(&mut self, function: usize, name: Symbol, t: &Token) -> Result<Option<u8>, Error> {
+		if function == 0 {
+			return Ok(None);
+		}
+
+		if let Some(local) = self.resolve_local(function - 1, name) {
+			self.functions[function - 1].locals[local as usize].captured = true;
+			return self.add_upvalue(function, local, true, t).map(Some);
+		}
+
+		match self.resolve_upvalue(function - 1, name, t)? {
+			Some(upvalue) => self.add_upvalue(function, upvalue, false, t).map(Some),
+			None => Ok(None)
+		}
+	}
+
+	fn add_upvalue(&mut self, function: usize, index: u8, is_local: bool, t: &Token) -> Result<u8, Error> {
+		let upvalues = &mut self.functions[function].upvalues;
+		if let Some(i) = upvalues.iter().position(|u| u.index == index && u.is_local == is_local) {
+			return Ok(i as u8);
+		}
+		if upvalues.len() > u8::MAX as usize {
+			return Err(Error::fatal("too many closure variables in function", Some(t)));
+		}
+
+		upvalues.push(Upvalue { index, is_local });
+		Ok((upvalues.len() - 1) as u8)
+	}
+
+	fn variable(&mut self, t: &Token, set: bool) -> ResUnit {
+		self.line = t.line;
+		let name = symbol(t);
+		let function = self.functions.len() - 1;
+
+		if let Some(slot) = self.resolve_local(function, name) {
+			self.emit(if set { OpCode::SetLocal } else { OpCode::GetLocal });
+			self.emit_byte(slot);
+		} else if let Some(upvalue) = self.resolve_upvalue(function, name, t)? {
+			self.emit(if set { OpCode::SetUpvalue } else { OpCode::GetUpvalue });
+			self.emit_byte(upvalue);
+		} else {
+			self.emit(if set { OpCode::SetGlobal } else { OpCode::GetGlobal });
+			self.emit_token(t)?;
+		}
+		Ok(())
+	}
+
+	fn expression(&mut self, expr: &Expr) -> ResUnit {
+		match expr {
+			Expr::Constant(v) => self.constant(v)?,
+			Expr::Group(v) => self.expression(v)?,
+			Expr::Variable(t) => self.variable(t, false)?,
+			Expr::Assign(t, v) => {
+				self.expression(v)?;
+				self.variable(t, true)?;
+			},
+			Expr::Binary(..) | Expr::Logical(..) | Expr::Index(..) | Expr::Call(..) => self.chain(expr)?,
+			Expr::Unary(t, v) => {
+				self.expression(v)?;
+				self.line = t.line;
+				if t.toktype == TokenType::Minus {
+					self.emit(OpCode::Negate);
+				} else {
+					self.emit(OpCode::Not);
+					self.emit_token(t)?;
+				}
+			},
+			Expr::Postfix(t, target) => self.postfix(t, target)?,
+			Expr::List(v) => {
+				for i in v {
+					self.expression(i)?;
+				}
+				self.emit(OpCode::List);
+				self.emit_u16(v.len(), "list elements")?;
+			},
+			Expr::Map(t, v) => {
+				for (key, val) in v {
+					self.expression(key)?;
+					self.expression(val)?;
+				}
+				self.emit(OpCode::Map);
+				self.emit_u16(v.len(), "map entries")?;
+				self.emit_token(t)?;
+			},
+			Expr::SetIndex(c, t, k, op, v) => {
+				self.expression(c)?;
+				self.expression(k)?;
+				self.expression(v)?;
+				match op {
+					Some(op) => {
+						let binary = Self::binary_op(op)?;
+						self.emit(OpCode::UpdateIndex);
+						self.emit_byte(binary as u8);
+					},
+					None => self.emit(OpCode::SetIndex)
+				}
+				self.emit_token(t)?;
+			},
+			Expr::Lambda(f) => self.function(f)?
+		}
+		Ok(())
+	}
+
+	fn constant(&mut self, v: &Literal) -> ResUnit {
+		match v {
+			Literal::Nil => self.emit(OpCode::Nil),
+			Literal::Bool(true) => self.emit(OpCode::True),
+			Literal::Bool(false) => self.emit(OpCode::False),
+			_ => {
+				let index = self.chunk().add_constant(v.clone());
+				self.emit(OpCode::Constant);
+				self.emit_u16(index, "constants")?;
+			}
+		}
+		Ok(())
+	}
+
+	// The innermost operand first, then every link on the value so far, in a loop
+	fn chain(&mut self, expr: &Expr) -> ResUnit {
+		let (first, links) = expr.chain();
+		self.expression(first)?;
+		for link in links {
+			match link {
+				Expr::Binary(_, t, v2) => {
+					self.expression(v2)?;
+					self.line = t.line;
+					let op = Self::binary_op(t)?;
+					self.emit(op);
+				},
+				Expr::Logical(_, t, v2) => self.logical(t, v2)?,
+				Expr::Index(_, t, k) => {
+					self.expression(k)?;
+					self.emit(OpCode::Index);
+					self.emit_token(t)?;
+				},
+				Expr::Call(_, t, args) => {
+					for i in args {
+						self.expression(i)?;
+					}
+					if args.len() > u8::MAX as usize {
+						return Err(Error::fatal("too many arguments in call", Some(t)));
+					}
+					self.line = t.line;
+					self.emit(OpCode::Call);
+					self.emit_byte(args.len() as u8);
+					self.emit_token(t)?;
+				},
+				_ => return Err(Error::fatal("unexpected link in a chain", None))
+			}
+		}
+		Ok(())
+	}
+
+	// the left side is on the stack already
+	fn logical(&mut self, t: &Token, v2: &Expr) -> ResUnit {
+		if t.toktype == TokenType::Or {
+			let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+			let end_jump = self.emit_jump(OpCode::Jump);
+			self.patch_jump(else_jump)?;
+			self.emit(OpCode::Pop);
+			self.expression(v2)?;
+			self.patch_jump(end_jump)
+		} else {
+			let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+			self.emit(OpCode::Pop);
+			self.expression(v2)?;
+			self.patch_jump(end_jump)
+		}
+	}
+
+	fn postfix(&mut self, t: &Token, target: &Expr) -> ResUnit {
+		let direction = (t.toktype == TokenType::MinusMinus) as u8;
+
+		match target {
+			Expr::Variable(name) => {
+				// the first copy is the result, the second one gets stepped and stored
+				self.variable(name, false)?;
+				self.variable(name, false)?;
+				self.emit(OpCode::Step);
+				self.emit_byte(direction);
+				self.emit_token(t)?;
+				self.variable(name, true)?;
+				self.emit(OpCode::Pop);
+			},
+			Expr::Index(c, bracket, k) => {
+				self.expression(c)?;
+				self.expression(k)?;
+				self.emit(OpCode::StepIndex);
+				self.emit_byte(direction);
+				self.emit_token(t)?;
+				self.emit_token(bracket)?;
+			},
+			_ => return Err(Error::fatal("invalid increment target", Some(t)))
+		}
+		Ok(())
+	}
+
+	fn binary_op(t: &Token) -> Result<OpCode, Error> {
+		use TokenType::*;
+		match t.toktype {
+			Plus => Ok(OpCode::Add),
+			Minus => Ok(OpCode::Sub),
+			Star => Ok(OpCode::Mul),
+			Slash => Ok(OpCode::Div),
+			Percent => Ok(OpCode::Rem),
+			EqualEqual => Ok(OpCode::Equal),
+			BangEqual => Ok(OpCode::NotEqual),
+			Greater => Ok(OpCode::Greater),
+			GreaterEqual => Ok(OpCode::GreaterEqual),
+			Less => Ok(OpCode::Less),
+			LessEqual => Ok(OpCode::LessEqual),
+			_ => Err(Error::fatal("unexpected operator in binary!", Some(t)))
+		}
+	}
+
+	fn emit(&mut self, op: OpCode) {
+		self.emit_byte(op as u8);
+	}
+
+	fn emit_byte(&mut self, byte: u8) {
+		let line = self.line;
+		self.chunk().write(byte, line);
+	}
+
+	fn emit_u16(&mut self, v: usize, what: &str) -> ResUnit {
+		if v > u16::MAX as usize {
+			return Err(Error::fatal(format!("too many {what} in one chunk").as_str(), None));
+		}
+
+		let line = self.line;
+		self.chunk().write_u16(v as u16, line);
+		Ok(())
+	}
+
+	fn emit_token(&mut self, t: &Token) -> ResUnit {
+		let index = self.chunk().add_token(t);
+		self.emit_u16(index, "tokens")
+	}
+
+	fn emit_jump(&mut self, op: OpCode) -> usize {
+		self.emit(op);
+		self.emit_jump_offset()
+	}
+
+	fn emit_jump_offset(&mut self) -> usize {
+		self.emit_byte(0xff);
+		self.emit_byte(0xff);
+		self.chunk().code.len() - 2
+	}
+
+	fn patch_jump(&mut self, offset: usize) -> ResUnit {
+		let jump = self.chunk().code.len() - offset - 2;
+		if jump > u16::MAX as usize {
+			return Err(Error::fatal("too much code to jump over", None));
+		}
+
+		let code = &mut self.chunk().code;
+		code[offset] = (jump >> 8) as u8;
+		code[offset + 1] = (jump & 0xff) as u8;
+		Ok(())
+	}
+
+	fn emit_loop(&mut self, start: usize) -> ResUnit {
+		self.emit(OpCode::Loop);
+		let offset = self.chunk().code.len() - start + 2;
+		self.emit_u16(offset, "loop body bytes")
+	}
+}
+
+fn symbol(t: &Token) -> Symbol {
+	match t.literal {
+		Literal::Identifier(name) => name,
+		_ => Symbol::intern("")
+	}
+}
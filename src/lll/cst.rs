@@ -0,0 +1,593 @@
+use super::lexer::Lexer;
+use super::token::Token;
+use super::token::TokenType;
+use super::token::Literal;
+
+// A concrete syntax tree keeps every byte of the source: whitespace and comments hang
+// off the tokens as trivia, and nodes only group tokens. It never fails to build, tokens
+// the grammar doesn't expect end up in `Error` nodes. `Node::text` gives the source back.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+	Whitespace,
+	Newline,
+	LineComment,
+	DocComment,
+	BlockComment,
+	Skipped // text the lexer rejected
+}
+
+#[derive(Debug, Clone)]
+pub struct Trivia {
+	pub kind: TriviaKind,
+	pub text: String
+}
+
+// Trailing trivia runs up to the end of the token's line, the rest leads the next token
+#[derive(Debug, Clone)]
+pub struct CstToken {
+	pub token: Token,
+	pub text: String,
+	pub leading: Vec<Trivia>,
+	pub trailing: Vec<Trivia>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+	Root,
+	VarDecl,
+	FunDecl,
+	Params,
+	Block,
+	Print,
+	If,
+	While,
+	For,
+	Jump, // break and continue
+	Return,
+	Import,
+	Export,
+	Try, // with its catch and finally
+	Throw,
+	ExprStmt,
+	Assign,
+	Binary,
+	Unary,
+	Postfix,
+	Call,
+	Args,
+	Index,
+	Property, // `u.name`
+	Group,
+	List,
+	Map,
+	Entry,
+	Lambda,
+	Arrow,
+	Literal,
+	Name,
+	Error
+}
+
+#[derive(Debug, Clone)]
+pub enum Element {
+	Node(Node),
+	Token(CstToken)
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+	pub kind: NodeKind,
+	pub children: Vec<Element>
+}
+
+impl Node {
+	fn new(kind: NodeKind) -> Self {
+		Self { kind, children: Vec::new() }
+	}
+
+	fn push(&mut self, node: Node) {
+		self.children.push(Element::Node(node));
+	}
+
+	pub fn text(&self) -> String {
+		let mut out = String::new();
+		self.write(&mut out);
+		out
+	}
+
+	fn write(&self, out: &mut String) {
+		for child in &self.children {
+			match child {
+				Element::Node(node) => node.write(out),
+				Element::Token(t) => t.write(out)
+			}
+		}
+	}
+}
+
+impl CstToken {
+	fn write(&self, out: &mut String) {
+		for trivia in &self.leading {
+			out.push_str(&trivia.text);
+		}
+		out.push_str(&self.text);
+		for trivia in &self.trailing {
+			out.push_str(&trivia.text);
+		}
+	}
+}
+
+fn trivia_kind(text: &str) -> TriviaKind {
+	if text == "\n" {
+		TriviaKind::Newline
+	} else if text.starts_with("///") {
+		TriviaKind::DocComment
+	} else if text.starts_with("//") {
+		TriviaKind::LineComment
+	} else if text.starts_with("/*") {
+		TriviaKind::BlockComment
+	} else if text.chars().all(char::is_whitespace) {
+		TriviaKind::Whitespace
+	} else {
+		TriviaKind::Skipped
+	}
+}
+
+// Runs of spaces and tabs become one piece of trivia
+fn push_trivia(list: &mut Vec<Trivia>, trivia: Trivia) {
+	if let Some(last) = list.last_mut() {
+		if last.kind == TriviaKind::Whitespace && trivia.kind == TriviaKind::Whitespace {
+			last.text.push_str(&trivia.text);
+			return;
+		}
+	}
+	list.push(trivia);
+}
+
+// Tokens of the source with their trivia, the last one is always Eof
+pub fn tokens(source: &str) -> Vec<CstToken> {
+	let pieces = Lexer::new(source.to_string()).scan_lossless();
+	let mut tokens: Vec<CstToken> = Vec::new();
+	let mut leading = Vec::new();
+
+	for (token, range) in pieces {
+		let text = source[range].to_string();
+		match token {
+			Some(token) => tokens.push(CstToken { token, text, leading: std::mem::take(&mut leading), trailing: Vec::new() }),
+			None => {
+				let trivia = Trivia { kind: trivia_kind(&text), text };
+				match tokens.last_mut() {
+					Some(last) if leading.is_empty() && trivia.kind != TriviaKind::Newline => push_trivia(&mut last.trailing, trivia),
+					_ => push_trivia(&mut leading, trivia)
+				}
+			}
+		}
+	}
+
+	let line = source.matches('\n').count();
+	let eof = Token { literal: Literal::Nil, toktype: TokenType::Eof, place: source.len(), line };
+	tokens.push(CstToken { token: eof, text: String::new(), leading, trailing: Vec::new() });
+	tokens
+}
+
+pub fn parse(source: &str) -> Node {
+	let mut tokens = tokens(source);
+	tokens.reverse();
+	let mut builder = Builder { tokens };
+
+	let mut root = Node::new(NodeKind::Root);
+	while !builder.at(TokenType::Eof) {
+		let decl = builder.declaration();
+		root.push(decl);
+	}
+	builder.bump(&mut root);
+	root
+}
+
+// Mirrors `Parser`, but only groups tokens and never gives up
+struct Builder {
+	tokens: Vec<CstToken> // reversed, the next token is the last one
+}
+
+impl Builder {
+	fn nth(&self, n: usize) -> TokenType {
+		match self.tokens.len().checked_sub(n + 1) {
+			Some(i) => self.tokens[i].token.toktype,
+			None => TokenType::Eof
+		}
+	}
+
+	fn at(&self, toktype: TokenType) -> bool {
+		self.nth(0) == toktype
+	}
+
+	fn at_any(&self, toktypes: &[TokenType]) -> bool {
+		toktypes.contains(&self.nth(0))
+	}
+
+	// Eof stays in the list so it can be taken by the root
+	fn bump(&mut self, node: &mut Node) {
+		if let Some(t) = self.tokens.pop() {
+			node.children.push(Element::Token(t));
+		}
+	}
+
+	fn eat(&mut self, toktype: TokenType, node: &mut Node) -> bool {
+		if self.at(toktype) && toktype != TokenType::Eof {
+			self.bump(node);
+			return true;
+		}
+		false
+	}
+
+	fn declaration(&mut self) -> Node {
+		let before = self.tokens.len();
+		let node = if self.at(TokenType::Export) {
+			let mut node = Node::new(NodeKind::Export);
+			self.bump(&mut node);
+			node.push(self.declaration());
+			node
+		} else if self.at(TokenType::New) {
+			self.var_declaration()
+		} else if self.at(TokenType::Fun) && self.nth(1) == TokenType::Identifier {
+			let mut node = Node::new(NodeKind::FunDecl);
+			self.bump(&mut node);
+			self.bump(&mut node);
+			node.push(self.params());
+			node.push(self.block());
+			node
+		} else {
+			self.statement()
+		};
+
+		if self.tokens.len() == before && !self.at(TokenType::Eof) {
+			let mut node = Node::new(NodeKind::Error);
+			self.bump(&mut node);
+			return node;
+		}
+		node
+	}
+
+	fn var_declaration(&mut self) -> Node {
+		let mut node = Node::new(NodeKind::VarDecl);
+		self.bump(&mut node);
+		self.eat(TokenType::Identifier, &mut node);
+		if self.eat(TokenType::Equal, &mut node) {
+			node.push(self.expression());
+		}
+		self.eat(TokenType::Semicolon, &mut node);
+		node
+	}
+
+	fn params(&mut self) -> Node {
+		let mut node = Node::new(NodeKind::Params);
+		self.eat(TokenType::LeftParen, &mut node);
+		while self.eat(TokenType::Identifier, &mut node) {
+			if !self.eat(TokenType::Comma, &mut node) {
+				break;
+			}
+		}
+		self.eat(TokenType::RightParen, &mut node);
+		node
+	}
+
+	fn block(&mut self) -> Node {
+		let mut node = Node::new(NodeKind::Block);
+		self.eat(TokenType::LeftBrace, &mut node);
+		while !self.at_any(&[TokenType::RightBrace, TokenType::Eof]) {
+			let decl = self.declaration();
+			node.push(decl);
+		}
+		self.eat(TokenType::RightBrace, &mut node);
+		node
+	}
+
+	fn statement(&mut self) -> Node {
+		use TokenType::*;
+		match self.nth(0) {
+			Print => {
+				let mut node = Node::new(NodeKind::Print);
+				self.bump(&mut node);
+				node.push(self.expression());
+				self.eat(Semicolon, &mut node);
+				node
+			},
+			If => {
+				let mut node = Node::new(NodeKind::If);
+				self.bump(&mut node);
+				self.condition(&mut node);
+				node.push(self.statement());
+				// `else if` goes on in the same node, the chain can be as long as the code is
+				while self.eat(Else, &mut node) {
+					if !self.eat(If, &mut node) {
+						node.push(self.statement());
+						break;
+					}
+					self.condition(&mut node);
+					node.push(self.statement());
+				}
+				node
+			},
+			While => {
+				let mut node = Node::new(NodeKind::While);
+				self.bump(&mut node);
+				self.condition(&mut node);
+				node.push(self.statement());
+				node
+			},
+			For => self.for_statement(),
+			Break | Continue => {
+				let mut node = Node::new(NodeKind::Jump);
+				self.bump(&mut node);
+				self.eat(Semicolon, &mut node);
+				node
+			},
+			Return => {
+				let mut node = Node::new(NodeKind::Return);
+				self.bump(&mut node);
+				if !self.at(Semicolon) {
+					node.push(self.expression());
+				}
+				self.eat(Semicolon, &mut node);
+				node
+			},
+			LeftBrace => self.block(),
+			Try => {
+				let mut node = Node::new(NodeKind::Try);
+				self.bump(&mut node);
+				node.push(self.block());
+				if self.eat(Catch, &mut node) {
+					self.eat(LeftParen, &mut node);
+					self.eat(Identifier, &mut node);
+					self.eat(RightParen, &mut node);
+					node.push(self.block());
+				}
+				if self.eat(Finally, &mut node) {
+					node.push(self.block());
+				}
+				node
+			},
+			Throw => {
+				let mut node = Node::new(NodeKind::Throw);
+				self.bump(&mut node);
+				node.push(self.expression());
+				self.eat(Semicolon, &mut node);
+				node
+			},
+			Import => {
+				let mut node = Node::new(NodeKind::Import);
+				self.bump(&mut node);
+				self.eat(String, &mut node);
+				if self.eat(As, &mut node) {
+					self.eat(Identifier, &mut node);
+				}
+				self.eat(Semicolon, &mut node);
+				node
+			},
+			_ => {
+				let mut node = Node::new(NodeKind::ExprStmt);
+				node.push(self.expression());
+				self.eat(Semicolon, &mut node);
+				node
+			}
+		}
+	}
+
+	fn condition(&mut self, node: &mut Node) {
+		self.eat(TokenType::LeftParen, node);
+		node.push(self.expression());
+		self.eat(TokenType::RightParen, node);
+	}
+
+	fn for_statement(&mut self) -> Node {
+		use TokenType::*;
+		let mut node = Node::new(NodeKind::For);
+		self.bump(&mut node);
+		self.eat(LeftParen, &mut node);
+
+		if !self.eat(Semicolon, &mut node) {
+			let init = if self.at(New) {
+				self.var_declaration()
+			} else {
+				let mut init = Node::new(NodeKind::ExprStmt);
+				init.push(self.expression());
+				self.eat(Semicolon, &mut init);
+				init
+			};
+			node.push(init);
+		}
+		if !self.at(Semicolon) {
+			node.push(self.expression());
+		}
+		self.eat(Semicolon, &mut node);
+		if !self.at(RightParen) {
+			node.push(self.expression());
+		}
+		self.eat(RightParen, &mut node);
+
+		node.push(self.statement());
+		node
+	}
+
+	fn expression(&mut self) -> Node {
+		self.binary(0)
+	}
+
+	// Levels from the loosest, assignment is the only right associative one
+	fn binary(&mut self, level: usize) -> Node {
+		use TokenType::*;
+		const LEVELS: [&[TokenType]; 7] = [
+			&[Equal, PlusEqual, MinusEqual, StarEqual, SlashEqual, PercentEqual],
+			&[Or],
+			&[And],
+			&[BangEqual, EqualEqual],
+			&[Less, LessEqual, Greater, GreaterEqual],
+			&[Minus, Plus],
+			&[Slash, Star, Percent]
+		];
+
+		if level == LEVELS.len() {
+			return self.unary();
+		}
+
+		let mut left = self.binary(level + 1);
+		if level == 0 {
+			while self.at_any(LEVELS[0]) {
+				let mut node = Node::new(NodeKind::Assign);
+				node.push(left);
+				self.bump(&mut node);
+				node.push(self.binary(0));
+				left = node;
+			}
+			return left;
+		}
+
+		// `a + b - c` is one node with the operands side by side, as long as the code is
+		if !self.at_any(LEVELS[level]) {
+			return left;
+		}
+		let mut node = Node::new(NodeKind::Binary);
+		node.push(left);
+		while self.at_any(LEVELS[level]) {
+			self.bump(&mut node);
+			node.push(self.binary(level + 1));
+		}
+		node
+	}
+
+	fn unary(&mut self) -> Node {
+		if self.at_any(&[TokenType::Minus, TokenType::Bang, TokenType::PlusPlus, TokenType::MinusMinus]) {
+			let mut node = Node::new(NodeKind::Unary);
+			self.bump(&mut node);
+			node.push(self.unary());
+			return node;
+		}
+
+		let expr = self.call();
+		if self.at_any(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+			let mut node = Node::new(NodeKind::Postfix);
+			node.push(expr);
+			self.bump(&mut node);
+			return node;
+		}
+		expr
+	}
+
+	// `f(x)[0].y` is one node of the kind of its last link, the links go on as long as the code does
+	fn call(&mut self) -> Node {
+		let first = self.primary();
+		if !self.at_any(&[TokenType::LeftParen, TokenType::LeftBracket, TokenType::Dot]) {
+			return first;
+		}
+		let mut node = Node::new(NodeKind::Call);
+		node.push(first);
+
+		loop {
+			if self.at(TokenType::LeftParen) {
+				node.kind = NodeKind::Call;
+				node.push(self.arguments(NodeKind::Args, TokenType::RightParen));
+			} else if self.at(TokenType::LeftBracket) {
+				node.kind = NodeKind::Index;
+				self.bump(&mut node);
+				node.push(self.expression());
+				self.eat(TokenType::RightBracket, &mut node);
+			} else if self.at(TokenType::Dot) {
+				node.kind = NodeKind::Property;
+				self.bump(&mut node);
+				self.eat(TokenType::Identifier, &mut node);
+			} else {
+				break;
+			}
+		}
+		node
+	}
+
+	// The opening token, comma separated expressions and the closing one
+	fn arguments(&mut self, kind: NodeKind, close: TokenType) -> Node {
+		let mut node = Node::new(kind);
+		self.bump(&mut node);
+		while !self.at_any(&[close, TokenType::Eof]) {
+			node.push(self.expression());
+			if !self.eat(TokenType::Comma, &mut node) {
+				break;
+			}
+		}
+		self.eat(close, &mut node);
+		node
+	}
+
+	fn primary(&mut self) -> Node {
+		use TokenType::*;
+		match self.nth(0) {
+			True | False | Nil | Number | String => {
+				let mut node = Node::new(NodeKind::Literal);
+				self.bump(&mut node);
+				node
+			},
+			Identifier => {
+				let mut node = Node::new(NodeKind::Name);
+				self.bump(&mut node);
+				node
+			},
+			Fun => {
+				let mut node = Node::new(NodeKind::Lambda);
+				self.bump(&mut node);
+				node.push(self.params());
+				node.push(self.block());
+				node
+			},
+			LeftParen if self.is_arrow() => {
+				let mut node = Node::new(NodeKind::Arrow);
+				node.push(self.params());
+				self.eat(Arrow, &mut node);
+				node.push(self.expression());
+				node
+			},
+			LeftParen => {
+				let mut node = Node::new(NodeKind::Group);
+				self.condition(&mut node);
+				node
+			},
+			LeftBracket => self.arguments(NodeKind::List, RightBracket),
+			LeftBrace => self.map(),
+			// nothing is consumed, the statement around it turns the token into an error
+			_ => Node::new(NodeKind::Error)
+		}
+	}
+
+	fn is_arrow(&self) -> bool {
+		let mut i = 1;
+		loop {
+			match self.nth(i) {
+				TokenType::RightParen => return self.nth(i + 1) == TokenType::Arrow,
+				TokenType::Identifier => {
+					i += 1;
+					match self.nth(i) {
+						TokenType::Comma => i += 1,
+						TokenType::RightParen => (),
+						_ => return false
+					}
+				},
+				_ => return false
+			}
+		}
+	}
+
+	fn map(&mut self) -> Node {
+		let mut node = Node::new(NodeKind::Map);
+		self.bump(&mut node);
+		while !self.at_any(&[TokenType::RightBrace, TokenType::Eof]) {
+			let mut entry = Node::new(NodeKind::Entry);
+			entry.push(self.expression());
+			self.eat(TokenType::Colon, &mut entry);
+			entry.push(self.expression());
+			node.push(entry);
+			if !self.eat(TokenType::Comma, &mut node) {
+				break;
+			}
+		}
+		self.eat(TokenType::RightBrace, &mut node);
+		node
+	}
+}
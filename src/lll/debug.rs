@@ -0,0 +1,280 @@
+use super::token::Token;
+use super::token::Literal;
+use super::parse::Stmt;
+use super::parse::Expr;
+use super::parse::Function;
+use super::chunk::Chunk;
+use super::chunk::OpCode;
+use super::chunk::Proto;
+
+pub fn dump_tokens(tokens: &[Token]) {
+	for i in tokens {
+		println!("{i}");
+	}
+}
+
+// The tree is printed as S-expressions, one statement per line
+pub fn dump_ast(stmts: &[Stmt]) {
+	print!("{}", ast(stmts));
+}
+
+pub fn ast(stmts: &[Stmt]) -> String {
+	stmts.iter().map(|i| stmt(i, 0) + "\n").collect()
+}
+
+fn indent(depth: usize) -> String {
+	"\t".repeat(depth)
+}
+
+fn quoted(v: &Literal) -> String {
+	match v {
+		Literal::String(s) => format!("\"{s}\""),
+		_ => format!("{v}")
+	}
+}
+
+fn name(t: &Token) -> String {
+	match &t.literal {
+		Literal::Identifier(name) => name.to_string(),
+		_ => t.toktype.lexeme().to_string()
+	}
+}
+
+fn stmt(s: &Stmt, depth: usize) -> String {
+	let pad = indent(depth);
+	match s {
+		Stmt::Print(e) => format!("{pad}(print {})", expr(e, depth)),
+		Stmt::Variable(t, e, doc) => format!("{}{pad}(new {} {})", doc_comment(doc, depth), name(t), expr(e, depth)),
+		Stmt::Function(f) => format!("{}{pad}{}", doc_comment(&f.doc, depth), function(f, depth)),
+		Stmt::Expression(e) => format!("{pad}{}", expr(e, depth)),
+		Stmt::Block(stmts) => format!("{pad}(block{})", body(stmts, depth)),
+		Stmt::If(..) => {
+			// an `else if` is the same `if` one level further in, but it's not indented
+			// further, the chain can be long
+			let (arms, otherwise) = s.arms();
+			let inner = indent(depth + 1);
+			let mut out = String::new();
+			for (i, (_, cond, then)) in arms.iter().enumerate() {
+				let pad = if i == 0 { &pad } else { &inner };
+				let sep = if i == 0 { "" } else { "\n" };
+				out += &format!("{sep}{pad}(if {}\n{}", expr(cond, depth), stmt(then, depth + 1));
+			}
+			if let Some(otherwise) = otherwise {
+				out += &format!("\n{}", stmt(otherwise, depth + 1));
+			}
+			out + &")".repeat(arms.len())
+		},
+		Stmt::While(cond, stmts, incr) => {
+			let incr = match incr {
+				Some(e) => format!(" {}", expr(e, depth)),
+				None => String::new()
+			};
+			format!("{pad}(while {}{incr}\n{})", expr(cond, depth), stmt(stmts, depth + 1))
+		},
+		Stmt::Break => format!("{pad}(break)"),
+		Stmt::Continue => format!("{pad}(continue)"),
+		Stmt::Return(_, e) => format!("{pad}(return {})", expr(e, depth)),
+		Stmt::Import(_, path, Some(alias)) => format!("{pad}(import \"{path}\" {})", name(alias)),
+		Stmt::Import(_, path, None) => format!("{pad}(import \"{path}\")"),
+		Stmt::Export(decl) => format!("{pad}(export\n{})", stmt(decl, depth + 1)),
+		Stmt::Try(_, stmts, catch, finally) => {
+			let inner = indent(depth + 1);
+			let mut out = format!("{pad}(try{}", body(stmts, depth));
+			if let Some((e, stmts)) = catch {
+				out += &format!("\n{inner}(catch {}{})", name(e), body(stmts, depth + 1));
+			}
+			if let Some(stmts) = finally {
+				out += &format!("\n{inner}(finally{})", body(stmts, depth + 1));
+			}
+			out + ")"
+		},
+		Stmt::Throw(_, e) => format!("{pad}(throw {})", expr(e, depth))
+	}
+}
+
+fn doc_comment(doc: &Option<String>, depth: usize) -> String {
+	match doc {
+		Some(doc) => format!("{}(doc {:?})\n", indent(depth), doc),
+		None => String::new()
+	}
+}
+
+fn body(stmts: &[Stmt], depth: usize) -> String {
+	stmts.iter().map(|s| format!("\n{}", stmt(s, depth + 1))).collect()
+}
+
+fn function(f: &Function, depth: usize) -> String {
+	let params: Vec<String> = f.params.iter().map(name).collect();
+	match &f.name {
+		Some(t) => format!("(fun {} ({}){})", name(t), params.join(" "), body(&f.body, depth)),
+		None => format!("(fun ({}){})", params.join(" "), body(&f.body, depth))
+	}
+}
+
+fn list(exprs: &[Expr], depth: usize) -> String {
+	exprs.iter().map(|e| format!(" {}", expr(e, depth))).collect()
+}
+
+fn expr(e: &Expr, depth: usize) -> String {
+	match e {
+		Expr::Binary(..) | Expr::Logical(..) | Expr::Index(..) | Expr::Call(..) => chain(e, depth),
+		Expr::Unary(op, a) => format!("({} {})", op.toktype.lexeme(), expr(a, depth)),
+		Expr::Group(a) => format!("(group {})", expr(a, depth)),
+		Expr::Variable(t) => name(t),
+		Expr::Assign(t, a) => format!("(= {} {})", name(t), expr(a, depth)),
+		Expr::Postfix(op, a) => format!("(postfix{} {})", op.toktype.lexeme(), expr(a, depth)),
+		Expr::List(items) => format!("(list{})", list(items, depth)),
+		Expr::Map(_, entries) => {
+			let entries: String = entries.iter()
+				.map(|(k, v)| format!(" ({} {})", expr(k, depth), expr(v, depth)))
+				.collect();
+			format!("(map{entries})")
+		},
+		Expr::SetIndex(c, _, k, op, v) => {
+			let op = op.as_ref().map_or("=", |op| op.toktype.lexeme());
+			format!("(set-index {op} {} {} {})", expr(c, depth), expr(k, depth), expr(v, depth))
+		},
+		Expr::Lambda(f) => function(f, depth),
+		Expr::Constant(v) => quoted(v)
+	}
+}
+
+// `(+ (+ a b) c)` written from the outside in, without going down the left side
+fn chain(e: &Expr, depth: usize) -> String {
+	let (first, links) = e.chain();
+	let mut out: String = links.iter().rev().map(|link| match link {
+		Expr::Binary(_, op, _) | Expr::Logical(_, op, _) => format!("({} ", op.toktype.lexeme()),
+		Expr::Index(..) => "(index ".to_string(),
+		_ => "(call ".to_string()
+	}).collect();
+	out += &expr(first, depth);
+	for link in links {
+		out += &match link {
+			Expr::Binary(_, _, b) | Expr::Logical(_, _, b) | Expr::Index(_, _, b) => format!(" {})", expr(b, depth)),
+			Expr::Call(_, _, args) => format!("{})", list(args, depth)),
+			_ => ")".to_string()
+		};
+	}
+	out
+}
+
+// clox style listing, nested functions follow the chunk that creates them
+pub fn disassemble(script: &Proto) {
+	listing(script, "<script>");
+}
+
+fn listing(proto: &Proto, fallback: &str) {
+	let name = proto.name.as_deref().unwrap_or(fallback);
+	println!("== {name} ==");
+	let chunk = &proto.chunk;
+	let mut offset = 0;
+	while offset < chunk.code.len() {
+		offset = instruction(chunk, offset);
+	}
+	for f in &chunk.functions {
+		println!();
+		listing(f, "<fn>");
+	}
+}
+
+fn instruction(chunk: &Chunk, offset: usize) -> usize {
+	let line = if offset > 0 && chunk.lines[offset] == chunk.lines[offset - 1] {
+		"   |".to_string()
+	} else {
+		format!("{:4}", chunk.lines[offset] + 1)
+	};
+	let prefix = format!("{offset:04} {line} ");
+
+	let Some(op) = OpCode::from_byte(chunk.code[offset]) else {
+		println!("{prefix}unknown opcode {}", chunk.code[offset]);
+		return offset + 1;
+	};
+	let op_name = format!("{op:?}");
+	let byte = |i: usize| chunk.code[offset + i];
+	let tok = |i: usize| name(&chunk.tokens[chunk.read_u16(offset + i) as usize]);
+
+	use OpCode::*;
+	match op {
+		Constant => {
+			let index = chunk.read_u16(offset + 1) as usize;
+			println!("{prefix}{op_name:<16} {index:4} '{}'", quoted(&chunk.constants[index]));
+			offset + 3
+		},
+		GetLocal | SetLocal | GetUpvalue | SetUpvalue => {
+			println!("{prefix}{op_name:<16} {:4}", byte(1));
+			offset + 2
+		},
+		DefineGlobal | GetGlobal | SetGlobal => {
+			println!("{prefix}{op_name:<16} {:4} '{}'", chunk.read_u16(offset + 1), tok(1));
+			offset + 3
+		},
+		Import => {
+			let index = chunk.read_u16(offset + 1) as usize;
+			println!("{prefix}{op_name:<16} {index:4} '{}'", quoted(&chunk.constants[index]));
+			offset + 5
+		},
+		Not | Index | SetIndex | Throw => {
+			println!("{prefix}{op_name}");
+			offset + 3
+		},
+		Step => {
+			let dir = if byte(1) == 0 { "++" } else { "--" };
+			println!("{prefix}{op_name:<16} {dir}");
+			offset + 4
+		},
+		StepIndex => {
+			let dir = if byte(1) == 0 { "++" } else { "--" };
+			println!("{prefix}{op_name:<16} {dir}");
+			offset + 6
+		},
+		UpdateIndex => {
+			let binop = OpCode::from_byte(byte(1)).map_or("?".to_string(), |op| format!("{op:?}"));
+			println!("{prefix}{op_name:<16} {binop}");
+			offset + 4
+		},
+		Jump | JumpIfFalse => {
+			let target = offset + 3 + chunk.read_u16(offset + 1) as usize;
+			println!("{prefix}{op_name:<16} {offset:4} -> {target}");
+			offset + 3
+		},
+		Try => {
+			let handler = if byte(1) == 1 { "catch" } else { "finally" };
+			let target = offset + 4 + chunk.read_u16(offset + 2) as usize;
+			println!("{prefix}{op_name:<16} {offset:4} -> {target} {handler}");
+			offset + 4
+		},
+		Loop => {
+			let target = offset + 3 - chunk.read_u16(offset + 1) as usize;
+			println!("{prefix}{op_name:<16} {offset:4} -> {target}");
+			offset + 3
+		},
+		Call => {
+			println!("{prefix}{op_name:<16} {:4} args", byte(1));
+			offset + 4
+		},
+		List => {
+			println!("{prefix}{op_name:<16} {:4} items", chunk.read_u16(offset + 1));
+			offset + 3
+		},
+		Map => {
+			println!("{prefix}{op_name:<16} {:4} entries", chunk.read_u16(offset + 1));
+			offset + 5
+		},
+		Closure => {
+			let index = chunk.read_u16(offset + 1) as usize;
+			let f = &chunk.functions[index];
+			println!("{prefix}{op_name:<16} {index:4} '{}'", f.name.as_deref().unwrap_or("<fn>"));
+			let mut offset = offset + 3;
+			for _ in 0..f.upvalues {
+				let kind = if chunk.code[offset] == 1 { "local" } else { "upvalue" };
+				println!("{offset:04}    |                     {kind} {}", chunk.code[offset + 1]);
+				offset += 2;
+			}
+			offset
+		},
+		_ => {
+			println!("{prefix}{op_name}");
+			offset + 1
+		}
+	}
+}
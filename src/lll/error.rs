@@ -1,4 +1,7 @@
 
+use super::builtins;
+use super::map::Map;
+use super::token::Literal;
 use super::token::Token;
 	
 #[derive(Debug)]
@@ -7,41 +10,190 @@ enum ErrorType {
 	Fatal
 }
 
+// What went wrong at runtime, a caught error carries it as `kind`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+	Runtime,
+	Type,
+	Name,
+	Index,
+	Key,
+	Arity,
+	Division,
+	Import,
+	Io,
+	Recursion,
+	// a built-in that needs a capability the script wasn't given
+	Denied,
+	// a budget ran out, nothing in the script can catch it
+	Limit,
+	// `exit(code)`, ends the run like a limit does
+	Exit(i32)
+}
+
+impl ErrorKind {
+	// Whether `try` gets to handle it, otherwise it ends the whole run
+	pub fn catchable(&self) -> bool {
+		!matches!(self, ErrorKind::Limit | ErrorKind::Exit(_))
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			ErrorKind::Runtime => "runtime",
+			ErrorKind::Type => "type",
+			ErrorKind::Name => "name",
+			ErrorKind::Index => "index",
+			ErrorKind::Key => "key",
+			ErrorKind::Arity => "arity",
+			ErrorKind::Division => "division",
+			ErrorKind::Import => "import",
+			ErrorKind::Io => "io",
+			ErrorKind::Recursion => "recursion",
+			ErrorKind::Denied => "denied",
+			ErrorKind::Limit => "limit",
+			ErrorKind::Exit(_) => "exit"
+		}
+	}
+}
+
 #[derive(Debug)]
 pub struct Error {
-	token: Option<Token>,
+	token: Option<Box<Token>>,
+	// byte offset for errors that have no token, like the lexer's
+	place: Option<usize>,
+	// for errors that have no token, the line the running code was on
+	line: Option<usize>,
 	msg: String,
-	typing: ErrorType
+	typing: ErrorType,
+	kind: ErrorKind,
+	// the value of a `throw`, caught as it is
+	thrown: Option<Box<Literal>>,
+	// the calls it came out of, innermost first, with the line each was called on
+	trace: Vec<(String, usize)>
 }
 
 impl std::fmt::Display for Error {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		let buf;
-		match &self.token {
-			Some(token) => buf = format!("{}, line {}, token {}", self.msg, token.line + 1, token.toktype),
-			None => buf = format!("{}", self.msg)
+		let buf = match &self.token {
+			Some(token) => format!("{}, line {}, token {}", self.msg, token.line + 1, token.toktype),
+			None => match self.line {
+				Some(line) => format!("{}, line {}", self.msg, line + 1),
+				None => self.msg.to_string()
+			}
 		};
 		match &self.typing {
 			ErrorType::Warn => write!(f, "WARN: {buf}"),
 			ErrorType::Fatal => {
-				write!(f, "FATAL: {buf}")
+				write!(f, "FATAL: {buf}")?;
+				// deep recursion would print the same frame over and over
+				let mut i = 0;
+				while i < self.trace.len() {
+					let (function, line) = &self.trace[i];
+					let repeats = self.trace[i..].iter().take_while(|frame| frame == &&self.trace[i]).count();
+					write!(f, "\n    in {function} called on line {}", line + 1)?;
+					if repeats > 1 {
+						write!(f, "\n    ... {} more of the same", repeats - 1)?;
+					}
+					i += repeats;
+				}
+				Ok(())
 			},
 		}
 	}
 }
 
 impl Error {
-	pub fn fatal(msg: &str, token: Option<&Token>) -> Self {
-		match token {
-			Some(v) => Self { token: Some(v.clone()), msg: msg.to_string(), typing: ErrorType::Fatal },
-			None => Self { token: None, msg: msg.to_string(), typing: ErrorType::Fatal }
+	fn new(msg: &str, token: Option<&Token>, typing: ErrorType) -> Self {
+		Self {
+			token: token.map(|t| Box::new(t.clone())),
+			place: None,
+			line: None,
+			msg: msg.to_string(),
+			typing,
+			kind: ErrorKind::Runtime,
+			thrown: None,
+			trace: Vec::new()
 		}
 	}
 
+	pub fn fatal(msg: &str, token: Option<&Token>) -> Self {
+		Self::new(msg, token, ErrorType::Fatal)
+	}
+
 	pub fn warn(msg: &str, token: Option<&Token>) -> Self {
-		match token {
-			Some(v) => Self { token: Some(v.clone()), msg: msg.to_string(), typing: ErrorType::Warn },
-			None => Self { token: None, msg: msg.to_string(), typing: ErrorType::Warn }
+		Self::new(msg, token, ErrorType::Warn)
+	}
+
+	// `throw value;`, uncaught it reads like any other error
+	pub fn throw(value: Literal, token: &Token) -> Self {
+		let mut e = Self::fatal(format!("uncaught {value}").as_str(), Some(token));
+		e.thrown = Some(Box::new(value));
+		e
+	}
+
+	pub fn of(mut self, kind: ErrorKind) -> Self {
+		self.kind = kind;
+		self
+	}
+
+	// Fills in the line for errors raised without a token, like the ones of operators
+	pub fn on_line(mut self, line: usize) -> Self {
+		if self.line.is_none() {
+			self.line = Some(line);
+		}
+		self
+	}
+
+	pub fn at(mut self, place: usize) -> Self {
+		self.place = Some(place);
+		self
+	}
+
+	pub fn message(&self) -> &str {
+		&self.msg
+	}
+
+	pub fn token(&self) -> Option<&Token> {
+		self.token.as_deref()
+	}
+
+	// Where in the source the error points, if anywhere
+	pub fn place(&self) -> Option<usize> {
+		self.token.as_ref().map(|t| t.place).or(self.place)
+	}
+
+	pub fn is_warning(&self) -> bool {
+		matches!(self.typing, ErrorType::Warn)
+	}
+
+	// Called while unwinding out of `function`, which was called on `line`
+	pub fn traced(mut self, function: String, line: usize) -> Self {
+		self.trace.push((function, line));
+		self
+	}
+
+	pub fn trace(&self) -> &[(String, usize)] {
+		&self.trace
+	}
+
+	pub fn kind(&self) -> ErrorKind {
+		self.kind
+	}
+
+	pub fn line(&self) -> Option<usize> {
+		self.token.as_ref().map(|t| t.line).or(self.line)
+	}
+
+	// What `catch (e)` binds: the thrown value, or a map describing a runtime error
+	pub fn value(&self) -> Literal {
+		if let Some(v) = &self.thrown {
+			return v.as_ref().clone();
 		}
+
+		let mut map = Map::new();
+		map.insert(Literal::String("message".to_string()), Literal::String(self.msg.clone()));
+		map.insert(Literal::String("line".to_string()), self.line().map_or(Literal::Nil, |line| Literal::Float((line + 1) as f64)));
+		map.insert(Literal::String("kind".to_string()), Literal::String(self.kind.name().to_string()));
+		builtins::new_map(map)
 	}
 }
@@ -0,0 +1,315 @@
+use super::cst;
+use super::cst::CstToken;
+use super::cst::Element;
+use super::cst::Node;
+use super::cst::NodeKind;
+use super::cst::Trivia;
+use super::cst::TriviaKind;
+use super::debug;
+use super::error::Error;
+use super::lexer::Lexer;
+use super::parse::Parser;
+use super::token::Literal;
+use super::token::Token;
+use super::token::TokenType;
+
+// Formats a whole file. Sources that don't parse are refused, and so is a result that
+// would parse into a different program than the input did.
+pub fn format(source: &str) -> Result<String, Error> {
+	let before = syntax(source)?;
+
+	let mut printer = Printer::new();
+	printer.root(&cst::parse(source));
+
+	match syntax(&printer.out) {
+		Ok(after) if after == before => Ok(printer.out),
+		_ => Err(Error::fatal("formatting would change the program, the file was left as it is", None))
+	}
+}
+
+// The program as S-expressions, or its first error
+fn syntax(source: &str) -> Result<String, Error> {
+	let (mut tokens, errors) = Lexer::new(source.to_string()).scan_with_errors();
+	if let Some(e) = errors.into_iter().next() {
+		return Err(e);
+	}
+
+	let line = tokens.last().map_or(0, |t| t.line);
+	tokens.push(Token { literal: Literal::Nil, toktype: TokenType::Eof, place: source.len(), line });
+	let (stmts, errors) = Parser::new(tokens).parse_with_errors();
+	match errors.into_iter().next() {
+		Some(e) => Err(e),
+		None => Ok(debug::ast(&stmts))
+	}
+}
+
+fn first_token(e: &Element) -> Option<&CstToken> {
+	match e {
+		Element::Token(t) => Some(t),
+		Element::Node(n) => n.children.first().and_then(first_token)
+	}
+}
+
+fn toktype(e: &Element) -> Option<TokenType> {
+	first_token(e).map(|t| t.token.toktype)
+}
+
+fn is_comment(t: &Trivia) -> bool {
+	!matches!(t.kind, TriviaKind::Whitespace | TriviaKind::Newline)
+}
+
+// Writes tokens and comments, all whitespace comes from here. A line comment in the
+// middle of a statement breaks it, the rest of it goes one tab further in.
+struct Printer {
+	out: String,
+	indent: usize,
+	continuation: bool,
+	line_start: bool,
+	space: bool,
+	must_break: bool,
+	// right after an opening brace or at the top of the file, no blank line goes here
+	fresh: bool,
+	// a block comment started the line, so it keeps the line to itself
+	own_line: bool
+}
+
+impl Printer {
+	fn new() -> Self {
+		Self { out: String::new(), indent: 0, continuation: false, line_start: true, space: false, must_break: false, fresh: true, own_line: false }
+	}
+
+	fn write(&mut self, text: &str) {
+		if self.must_break {
+			self.line_break();
+			self.continuation = true;
+		}
+		if self.line_start {
+			let tabs = self.indent + self.continuation as usize;
+			self.out.extend(std::iter::repeat_n('\t', tabs));
+			self.line_start = false;
+		} else if self.space {
+			self.out.push(' ');
+		}
+
+		self.out.push_str(text);
+		self.space = false;
+		self.fresh = false;
+		self.own_line = false;
+	}
+
+	fn line_break(&mut self) {
+		if !self.line_start {
+			self.out.push('\n');
+			self.line_start = true;
+		}
+		self.must_break = false;
+		self.own_line = false;
+	}
+
+	// Ends a statement
+	fn newline(&mut self) {
+		self.line_break();
+		self.continuation = false;
+		self.space = false;
+	}
+
+	fn blank_line(&mut self, newlines: usize) {
+		if self.line_start && newlines >= 2 && !self.fresh && !self.continuation {
+			self.out.push('\n');
+		}
+	}
+
+	fn trivia(&mut self, trivia: &[Trivia]) -> usize {
+		let mut newlines = 0;
+		for t in trivia {
+			match t.kind {
+				TriviaKind::Whitespace => (),
+				TriviaKind::Newline => {
+					newlines += 1;
+					if self.own_line {
+						self.line_break();
+					}
+				},
+				TriviaKind::LineComment | TriviaKind::DocComment => {
+					if self.line_start && !self.must_break {
+						self.blank_line(newlines);
+						self.write(&t.text);
+						self.line_break();
+					} else {
+						self.space = true;
+						self.write(&t.text);
+						self.must_break = true;
+					}
+					newlines = 0;
+				},
+				TriviaKind::BlockComment | TriviaKind::Skipped => {
+					let own_line = self.line_start && !self.must_break;
+					self.blank_line(newlines);
+					self.space = true;
+					self.write(&t.text);
+					self.space = true;
+					self.own_line = own_line;
+					newlines = 0;
+				}
+			}
+		}
+		newlines
+	}
+
+	fn token(&mut self, t: &CstToken) {
+		let newlines = self.trivia(&t.leading);
+		self.blank_line(newlines);
+		self.write(&t.text);
+		self.trivia(&t.trailing);
+	}
+
+	fn root(&mut self, root: &Node) {
+		for child in &root.children {
+			match child {
+				Element::Node(n) => {
+					self.statement(n);
+					self.newline();
+				},
+				Element::Token(eof) => {
+					self.trivia(&eof.leading);
+					self.newline();
+				}
+			}
+		}
+	}
+
+	fn statement(&mut self, n: &Node) {
+		match n.kind {
+			NodeKind::Block => self.block(n),
+			NodeKind::If => {
+				let mut prev: Option<&Element> = None;
+				for child in &n.children {
+					if toktype(child) == Some(TokenType::Else) {
+						// `} else` stays on the line of the brace, after a single statement it gets its own line
+						match prev {
+							Some(Element::Node(Node { kind: NodeKind::Block, .. })) => self.space = true,
+							_ => self.newline()
+						}
+					} else if prev.is_some() {
+						self.space = !matches!(prev.and_then(toktype), Some(TokenType::LeftParen)) && toktype(child) != Some(TokenType::RightParen);
+					}
+					self.element(child, n.kind);
+					prev = Some(child);
+				}
+			},
+			_ => self.children(n)
+		}
+	}
+
+	fn block(&mut self, n: &Node) {
+		let Some((Element::Token(open), rest)) = n.children.split_first() else {
+			return self.children(n);
+		};
+		let Some((Element::Token(close), stmts)) = rest.split_last() else {
+			return self.children(n);
+		};
+
+		self.space = true;
+		self.token(open);
+		let empty = stmts.is_empty() && !close.leading.iter().any(is_comment) && !open.trailing.iter().any(is_comment);
+		if empty {
+			self.write(&close.text);
+			self.trivia(&close.trailing);
+			return;
+		}
+
+		let (indent, continuation) = (self.indent, self.continuation);
+		self.indent += 1 + continuation as usize;
+		self.newline();
+		self.fresh = true;
+		for stmt in stmts {
+			match stmt {
+				Element::Node(n) => self.statement(n),
+				Element::Token(t) => self.token(t)
+			}
+			self.newline();
+		}
+		self.trivia(&close.leading);
+		self.newline();
+
+		self.indent = indent;
+		self.write(&close.text);
+		self.continuation = continuation;
+		self.trivia(&close.trailing);
+	}
+
+	fn element(&mut self, e: &Element, parent: NodeKind) {
+		match e {
+			Element::Token(t) => self.token(t),
+			Element::Node(n) if n.kind == NodeKind::Block => self.block(n),
+			Element::Node(n) if parent == NodeKind::If || parent == NodeKind::While || parent == NodeKind::For => {
+				if is_statement(n.kind) {
+					self.statement(n);
+				} else {
+					self.children(n);
+				}
+			},
+			Element::Node(n) => self.children(n)
+		}
+	}
+
+	// Everything but blocks and `if` goes on one line, spaced by what's around each element
+	fn children(&mut self, n: &Node) {
+		let mut prev: Option<&Element> = None;
+		for (i, child) in n.children.iter().enumerate() {
+			let next = n.children.get(i + 1);
+			if trailing_comma(child, next) {
+				continue;
+			}
+			if let Some(prev) = prev {
+				self.space = spaced(n.kind, prev, child);
+			}
+			self.element(child, n.kind);
+			prev = Some(child);
+		}
+	}
+}
+
+fn is_statement(kind: NodeKind) -> bool {
+	use NodeKind::*;
+	matches!(kind, VarDecl | FunDecl | Block | Print | If | While | For | Jump | Return | Import | Export | Try | Throw | ExprStmt)
+}
+
+// `[1, 2,]` loses the last comma, unless a comment hangs off of it
+fn trailing_comma(e: &Element, next: Option<&Element>) -> bool {
+	let Element::Token(t) = e else {
+		return false;
+	};
+	let closing = next.and_then(toktype).is_some_and(|t| matches!(t, TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace));
+	t.token.toktype == TokenType::Comma && closing && !t.leading.iter().chain(t.trailing.iter()).any(is_comment)
+}
+
+fn last_toktype(e: &Element) -> Option<TokenType> {
+	match e {
+		Element::Token(t) => Some(t.token.toktype),
+		Element::Node(n) => n.children.last().and_then(last_toktype)
+	}
+}
+
+fn spaced(parent: NodeKind, prev: &Element, cur: &Element) -> bool {
+	use TokenType::*;
+	let (Some(before), Some(after)) = (last_toktype(prev), toktype(cur)) else {
+		return true;
+	};
+
+	if matches!(after, Comma | Semicolon | RightParen | RightBracket | Colon) {
+		return false;
+	}
+	if matches!(before, LeftParen | LeftBracket) {
+		return false;
+	}
+
+	match parent {
+		NodeKind::Map => !matches!(before, LeftBrace) && !matches!(after, RightBrace),
+		NodeKind::Call | NodeKind::Index | NodeKind::Property | NodeKind::Postfix => false,
+		NodeKind::FunDecl => !matches!(cur, Element::Node(Node { kind: NodeKind::Params, .. })),
+		// `- -a` must not turn into `--a`
+		NodeKind::Unary => before == Minus && matches!(after, Minus | MinusMinus),
+		_ => true
+	}
+}
@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use super::builtins;
+use super::builtins::Caller;
+use super::builtins::Native;
+use super::capability::Capability;
+use super::error::Error;
+use super::error::ErrorKind;
+use super::token::Literal;
+use super::token::Token;
+
+// `import "fs";`, files and directories. Relative paths are relative to where lll was
+// started, not to the script.
+static NATIVES: &[Native] = &[
+	Native { name: "read_text", arity: 1, func: read_text, needs: Some(Capability::FsRead) },
+	Native { name: "read_lines", arity: 1, func: read_lines, needs: Some(Capability::FsRead) },
+	Native { name: "exists", arity: 1, func: exists, needs: Some(Capability::FsRead) },
+	Native { name: "list_dir", arity: 1, func: list_dir, needs: Some(Capability::FsRead) },
+	Native { name: "write_text", arity: 2, func: write_text, needs: Some(Capability::FsWrite) },
+	Native { name: "append_text", arity: 2, func: append_text, needs: Some(Capability::FsWrite) },
+	// not `remove`, a bare import would hide the one for lists and maps
+	Native { name: "remove_path", arity: 1, func: remove_path, needs: Some(Capability::FsWrite) },
+	Native { name: "mkdir", arity: 1, func: mkdir, needs: Some(Capability::FsWrite) }
+];
+
+pub fn natives() -> &'static [Native] {
+	NATIVES
+}
+
+fn string_arg<'a>(name: &str, v: &'a Literal, t: &Token) -> Result<&'a str, Error> {
+	match v {
+		Literal::String(s) => Ok(s),
+		_ => Err(Error::fatal(format!("{name} expects a string").as_str(), Some(t)).of(ErrorKind::Type))
+	}
+}
+
+// The os error number says nothing to a script
+fn io_error(name: &str, path: &str, e: std::io::Error, t: &Token) -> Error {
+	let e = e.to_string();
+	let reason = e.split(" (os error").next().unwrap_or(&e);
+	Error::fatal(format!("{name} {path}: {reason}").as_str(), Some(t)).of(ErrorKind::Io)
+}
+
+fn read_text(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let path = string_arg("read_text", &args[0], t)?;
+	std::fs::read_to_string(path).map(Literal::String).map_err(|e| io_error("read_text", path, e, t))
+}
+
+// Without the line endings, `\r\n` included
+fn read_lines(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let path = string_arg("read_lines", &args[0], t)?;
+	let text = std::fs::read_to_string(path).map_err(|e| io_error("read_lines", path, e, t))?;
+	Ok(builtins::new_list(text.lines().map(|line| Literal::String(line.to_string())).collect()))
+}
+
+fn exists(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	Ok(Literal::Bool(Path::new(string_arg("exists", &args[0], t)?).exists()))
+}
+
+// Names of what's in the directory, sorted so every run sees the same order
+fn list_dir(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let path = string_arg("list_dir", &args[0], t)?;
+	let entries = std::fs::read_dir(path).map_err(|e| io_error("list_dir", path, e, t))?;
+	let mut names = Vec::new();
+	for entry in entries {
+		let entry = entry.map_err(|e| io_error("list_dir", path, e, t))?;
+		names.push(entry.file_name().to_string_lossy().to_string());
+	}
+	names.sort();
+	Ok(builtins::new_list(names.into_iter().map(Literal::String).collect()))
+}
+
+// Writes the text as it is, `\n` in a string literal is a line break already
+fn write_text(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let path = string_arg("write_text", &args[0], t)?;
+	let text = string_arg("write_text", &args[1], t)?;
+	std::fs::write(path, text).map(|_| Literal::Nil).map_err(|e| io_error("write_text", path, e, t))
+}
+
+// Makes the file if it isn't there yet
+fn append_text(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let path = string_arg("append_text", &args[0], t)?;
+	let text = string_arg("append_text", &args[1], t)?;
+	std::fs::OpenOptions::new().append(true).create(true).open(path)
+		.and_then(|mut file| std::io::Write::write_all(&mut file, text.as_bytes()))
+		.map(|_| Literal::Nil)
+		.map_err(|e| io_error("append_text", path, e, t))
+}
+
+// A file or an empty directory, anything more is one call per file on purpose
+fn remove_path(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let path = string_arg("remove_path", &args[0], t)?;
+	let res = if Path::new(path).is_dir() { std::fs::remove_dir(path) } else { std::fs::remove_file(path) };
+	res.map(|_| Literal::Nil).map_err(|e| io_error("remove_path", path, e, t))
+}
+
+// Along with any parents that are missing, a directory that is already there is fine
+fn mkdir(_: &mut dyn Caller, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+	let path = string_arg("mkdir", &args[0], t)?;
+	std::fs::create_dir_all(path).map(|_| Literal::Nil).map_err(|e| io_error("mkdir", path, e, t))
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::rc::Weak;
+use std::cell::Cell;
+use std::cell::RefCell;
+
+use super::token::Literal;
+use super::token::Items;
+use super::token::ListRef;
+use super::token::MapRef;
+use super::map::Map;
+use super::interpreter;
+use super::interpreter::EnvRef;
+use super::interpreter::Environment;
+use super::vm;
+use super::vm::Upvalue;
+use super::vm::UpvalueRef;
+
+// Values are still reference counted, the collector only exists to break the cycles
+// counting can't free. Every heap object is registered here when it's made and a
+// collection marks from the roots a backend hands in, plus every object something
+// outside of the heap (a native's local, the vm stack, ...) still holds a reference to.
+// Whatever is left unmarked gets emptied, which drops the references keeping it alive.
+
+#[derive(Clone)]
+pub enum Object {
+	List(ListRef),
+	Map(MapRef),
+	Env(EnvRef),
+	Function(Rc<interpreter::Closure>),
+	Compiled(Rc<vm::Closure>),
+	Upvalue(UpvalueRef)
+}
+
+enum WeakObject {
+	List(Weak<RefCell<Items>>),
+	Map(Weak<RefCell<Map>>),
+	Env(Weak<RefCell<Environment>>),
+	Function(Weak<interpreter::Closure>),
+	Compiled(Weak<vm::Closure>),
+	Upvalue(Weak<RefCell<Upvalue>>)
+}
+
+impl Object {
+	pub fn of(v: &Literal) -> Option<Object> {
+		match v {
+			Literal::List(l) => Some(Object::List(l.clone())),
+			Literal::Map(m) => Some(Object::Map(m.clone())),
+			Literal::Function(f) => Some(Object::Function(f.clone())),
+			Literal::Compiled(f) => Some(Object::Compiled(f.clone())),
+			_ => None
+		}
+	}
+
+	fn addr(&self) -> usize {
+		match self {
+			Object::List(o) => Rc::as_ptr(o) as *const u8 as usize,
+			Object::Map(o) => Rc::as_ptr(o) as *const u8 as usize,
+			Object::Env(o) => Rc::as_ptr(o) as *const u8 as usize,
+			Object::Function(o) => Rc::as_ptr(o) as *const u8 as usize,
+			Object::Compiled(o) => Rc::as_ptr(o) as *const u8 as usize,
+			Object::Upvalue(o) => Rc::as_ptr(o) as *const u8 as usize
+		}
+	}
+
+	fn strong_count(&self) -> usize {
+		match self {
+			Object::List(o) => Rc::strong_count(o),
+			Object::Map(o) => Rc::strong_count(o),
+			Object::Env(o) => Rc::strong_count(o),
+			Object::Function(o) => Rc::strong_count(o),
+			Object::Compiled(o) => Rc::strong_count(o),
+			Object::Upvalue(o) => Rc::strong_count(o)
+		}
+	}
+
+	fn downgrade(&self) -> WeakObject {
+		match self {
+			Object::List(o) => WeakObject::List(Rc::downgrade(o)),
+			Object::Map(o) => WeakObject::Map(Rc::downgrade(o)),
+			Object::Env(o) => WeakObject::Env(Rc::downgrade(o)),
+			Object::Function(o) => WeakObject::Function(Rc::downgrade(o)),
+			Object::Compiled(o) => WeakObject::Compiled(Rc::downgrade(o)),
+			Object::Upvalue(o) => WeakObject::Upvalue(Rc::downgrade(o))
+		}
+	}
+
+	// Returns false when the object is borrowed right now and can't be looked into
+	fn references(&self, out: &mut Vec<Object>) -> bool {
+		match self {
+			Object::List(l) => {
+				let Ok(l) = l.try_borrow() else { return false };
+				out.extend(l.iter().filter_map(Object::of));
+			},
+			Object::Map(m) => {
+				let Ok(m) = m.try_borrow() else { return false };
+				out.extend(m.iter().filter_map(|(_, v)| Object::of(v)));
+			},
+			Object::Env(env) => {
+				let Ok(env) = env.try_borrow() else { return false };
+				env.references(out);
+			},
+			Object::Function(f) => f.references(out),
+			Object::Compiled(f) => out.extend(f.upvalues.iter().map(|u| Object::Upvalue(u.clone()))),
+			Object::Upvalue(u) => {
+				let Ok(u) = u.try_borrow() else { return false };
+				if let Upvalue::Closed(v) = &*u {
+					out.extend(Object::of(v));
+				}
+			}
+		}
+		true
+	}
+
+	// Drops everything an unreachable object refers to, contents are moved out
+	// first so nothing gets freed while a borrow is held
+	fn clear(&self) {
+		match self {
+			Object::List(l) => {
+				let vals = l.try_borrow_mut().map(|mut l| std::mem::take(&mut *l));
+				drop(vals);
+			},
+			Object::Map(m) => {
+				let map = m.try_borrow_mut().map(|mut m| std::mem::replace(&mut *m, Map::new()));
+				drop(map);
+			},
+			Object::Env(env) => {
+				let vals = env.try_borrow_mut().map(|mut env| env.clear());
+				drop(vals);
+			},
+			Object::Upvalue(u) => {
+				let v = u.try_borrow_mut().map(|mut u| std::mem::replace(&mut *u, Upvalue::Closed(Literal::Nil)));
+				drop(v);
+			},
+			// their environment or upvalues get cleared on their own
+			Object::Function(_) | Object::Compiled(_) => ()
+		}
+	}
+}
+
+impl WeakObject {
+	fn upgrade(&self) -> Option<Object> {
+		match self {
+			WeakObject::List(o) => o.upgrade().map(Object::List),
+			WeakObject::Map(o) => o.upgrade().map(Object::Map),
+			WeakObject::Env(o) => o.upgrade().map(Object::Env),
+			WeakObject::Function(o) => o.upgrade().map(Object::Function),
+			WeakObject::Compiled(o) => o.upgrade().map(Object::Compiled),
+			WeakObject::Upvalue(o) => o.upgrade().map(Object::Upvalue)
+		}
+	}
+}
+
+const MIN_THRESHOLD: usize = 1024;
+
+struct Heap {
+	objects: Vec<WeakObject>,
+	allocated: usize,
+	threshold: usize,
+	stress: bool
+}
+
+thread_local! {
+	static HEAP: RefCell<Heap> = const { RefCell::new(Heap { objects: Vec::new(), allocated: 0, threshold: MIN_THRESHOLD, stress: false }) };
+	static PENDING: Cell<bool> = const { Cell::new(false) };
+}
+
+// Collect on every allocation, meant for shaking out objects the collector misses
+pub fn set_stress(stress: bool) {
+	HEAP.with_borrow_mut(|heap| heap.stress = stress);
+}
+
+pub fn track(o: Object) {
+	HEAP.with_borrow_mut(|heap| {
+		heap.objects.push(o.downgrade());
+		heap.allocated += 1;
+		if heap.stress || heap.allocated >= heap.threshold {
+			PENDING.set(true);
+		}
+	});
+}
+
+// Backends check this between instructions or statements, where no object is half built
+pub fn pending() -> bool {
+	PENDING.get()
+}
+
+pub fn collect(roots: Vec<Object>) {
+	PENDING.set(false);
+	let objects: Vec<Object> = HEAP.with_borrow_mut(|heap| heap.objects.drain(..).filter_map(|o| o.upgrade()).collect());
+	let index: HashMap<usize, usize> = objects.iter().enumerate().map(|(i, o)| (o.addr(), i)).collect();
+
+	let mut gray: Vec<usize> = roots.iter().filter_map(|o| index.get(&o.addr()).copied()).collect();
+	drop(roots);
+
+	let mut edges = Vec::with_capacity(objects.len());
+	let mut internal = vec![0; objects.len()];
+	for (i, o) in objects.iter().enumerate() {
+		let mut refs = Vec::new();
+		if !o.references(&mut refs) {
+			gray.push(i);
+		}
+		let refs: Vec<usize> = refs.iter().filter_map(|r| index.get(&r.addr()).copied()).collect();
+		for r in &refs {
+			internal[*r] += 1;
+		}
+		edges.push(refs);
+	}
+
+	// one reference is held by `objects`, anything past the ones from inside of the heap is a root
+	for (i, o) in objects.iter().enumerate() {
+		if o.strong_count() > internal[i] + 1 {
+			gray.push(i);
+		}
+	}
+
+	let mut marked = vec![false; objects.len()];
+	while let Some(i) = gray.pop() {
+		if !marked[i] {
+			marked[i] = true;
+			gray.extend(edges[i].iter().copied());
+		}
+	}
+
+	let mut live = Vec::new();
+	for (i, o) in objects.iter().enumerate() {
+		if marked[i] {
+			live.push(o.downgrade());
+		} else {
+			o.clear();
+		}
+	}
+
+	HEAP.with_borrow_mut(|heap| {
+		heap.objects = live;
+		heap.allocated = 0;
+		heap.threshold = MIN_THRESHOLD.max(heap.objects.len() * 2);
+	});
+}
@@ -1,60 +1,86 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::cell::RefCell;
 
+use super::budget::Budget;
+use super::budget::Limits;
+use super::builtins;
+use super::capability::Capability;
+use super::capability::Capabilities;
+use super::gc;
+use super::gc::Object;
 use super::error::Error;
+use super::error::ErrorKind;
 use super::parse::Stmt;
 use super::parse::Expr;
+use super::parse::Function;
+use super::builtins::Caller;
 use super::token::Token;
 use super::token::Literal;
+use super::map::Map;
+use super::module;
+use super::module::Modules;
+use super::module::ModulesRef;
+use super::symbol::Symbol;
 use super::token::TokenType;
 
-struct Environment {
-	enclosing: Option<Box<Environment>>,
-	vals: HashMap<String, Literal>
-}
+pub type EnvRef = Rc<RefCell<Environment>>;
 
-impl Clone for Environment {
-	fn clone(&self) -> Self {
-		Self { enclosing: self.enclosing.clone(), vals: self.vals.clone() }
-	}
+pub struct Environment {
+	enclosing: Option<EnvRef>,
+	vals: HashMap<Symbol, Literal>
 }
 
 impl Environment {
-	pub fn new(enc: Option<Box<Environment>>) -> Self {
-		Self { vals: HashMap::new(), enclosing: enc }
+	pub fn new(enc: Option<EnvRef>) -> EnvRef {
+		let env = Rc::new(RefCell::new(Self { vals: HashMap::new(), enclosing: enc }));
+		gc::track(Object::Env(env.clone()));
+		env
+	}
+
+	pub fn references(&self, out: &mut Vec<Object>) {
+		out.extend(self.vals.values().filter_map(Object::of));
+		out.extend(self.enclosing.clone().map(Object::Env));
+	}
+
+	// Hands back what it held so the caller decides when it gets dropped
+	pub fn clear(&mut self) -> (HashMap<Symbol, Literal>, Option<EnvRef>) {
+		(std::mem::take(&mut self.vals), self.enclosing.take())
 	}
 	
-	pub fn get(&self, name: &Token) -> Result<Literal, Error> {
+	fn get(&self, name: &Token) -> Result<Literal, Error> {
 		match &name.literal {
 			Literal::Identifier(v) => {
 				match self.vals.get(v) {
 					Some(v) => Ok(v.clone()),
 					None => {
 						let Some(env) = &self.enclosing else {
-							return Err(Error::fatal("variable identifier not found", Some(&name)))
+							return Err(Error::fatal("variable identifier not found", Some(name)).of(ErrorKind::Name))
 						};
-						env.get(name)
+						env.borrow().get(name)
 					}
 				}
 			},
 			_ => {
-				Err(Error::fatal("variable identifier was literal", Some(&name)))
+				Err(Error::fatal("variable identifier was literal", Some(name)))
 			}
 		}
 	}
 	
-	pub fn define(&mut self, name: String, val: Literal) {
+	fn define(&mut self, name: Symbol, val: Literal) {
 		self.vals.insert(name, val);
 	}
 
-	pub fn assign(&mut self, name: &Token, val: &Literal) -> Result<(), ()> {
+	fn assign(&mut self, name: &Token, val: &Literal) -> Result<(), ()> {
 		match &name.literal {
 			Literal::Identifier(v) => {
 				let Some(key_val) = self.vals.get_mut(v) else {
-					let Some(env) = &mut self.enclosing else { // kinda messy
+					let Some(env) = &self.enclosing else { // kinda messy
 						return Err(())
 					};
 					
-					return env.assign(name, val);
+					return env.borrow_mut().assign(name, val);
 				};
 				*key_val = val.clone();
 				Ok(())
@@ -64,157 +90,527 @@ impl Environment {
 	}
 }
 
+// Signals that unwind statements up to the enclosing loop
+enum Flow {
+	Next,
+	Break,
+	Continue,
+	Return(Literal)
+}
+
+// A function together with the environment it was created in
+pub struct Closure {
+	decl: Rc<Function>,
+	env: EnvRef
+}
+
+impl Closure {
+	fn new(decl: Rc<Function>, env: EnvRef) -> Rc<Closure> {
+		let closure = Rc::new(Closure { decl, env });
+		gc::track(Object::Function(closure.clone()));
+		closure
+	}
+
+	pub fn references(&self, out: &mut Vec<Object>) {
+		out.push(Object::Env(self.env.clone()));
+	}
+}
+
+impl std::fmt::Debug for Closure {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.decl.name {
+			Some(name) => write!(f, "<fn {}>", name.literal),
+			None => write!(f, "<fn>")
+		}
+	}
+}
+
+// Recurses on the native stack for every call and nested expression, so it has to run
+// on a stack sized for its `max_depth`: go through `lang::run` or `Engine` for that,
+// on a thread of your own it can overflow long before `max_depth` is reached.
 pub struct Interpreter {
-	env: Environment
+	env: EnvRef,
+	modules: ModulesRef,
+	// the file being run, imports are relative to it
+	path: Option<PathBuf>,
+	// calls being run right now and how many are allowed
+	depth: usize,
+	max_depth: usize,
+	budget: Option<Budget>,
+	capabilities: Capabilities,
+	// the `args` global, modules get the same list
+	args: Literal,
+	// a top-level statement ended in an error
+	failed: bool
+}
+
+impl Default for Interpreter {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl Interpreter {
 	pub fn new() -> Self {
-		Self { env: Environment::new(None) }
+		Self::with_modules(Modules::new(None, Vec::new()), None)
+	}
+
+	pub fn with_modules(modules: ModulesRef, path: Option<PathBuf>) -> Self {
+		let env = Environment::new(None);
+		for native in builtins::natives() {
+			env.borrow_mut().define(Symbol::intern(native.name), Literal::Native(native));
+		}
+
+		let mut interpreter = Self {
+			env,
+			modules,
+			path,
+			depth: 0,
+			max_depth: builtins::MAX_DEPTH,
+			budget: None,
+			capabilities: Capabilities::default(),
+			args: Literal::Nil,
+			failed: false
+		};
+		interpreter.define_args(builtins::new_list(Vec::new()));
+		interpreter
+	}
+
+	pub fn set_max_depth(&mut self, max_depth: usize) {
+		self.max_depth = max_depth;
+	}
+
+	pub fn set_limits(&mut self, limits: Limits) -> Result<(), Error> {
+		self.budget = Some(Budget::new(limits)?);
+		Ok(())
+	}
+
+	pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+		self.capabilities = capabilities;
+	}
+
+	// Whether any statement `interpret` ran ended in an error, the run still went on
+	pub fn failed(&self) -> bool {
+		self.failed
+	}
+
+	// What the script sees as `args`
+	pub fn set_args(&mut self, args: Vec<String>) {
+		self.define_args(builtins::new_list(args.into_iter().map(Literal::String).collect()));
+	}
+
+	fn define_args(&mut self, args: Literal) {
+		self.env.borrow_mut().define(Symbol::intern("args"), args.clone());
+		self.args = args;
+	}
+
+	// Runs an imported module, the first error stops it
+	pub fn run_module(&mut self, stmts: &[Stmt]) -> Result<HashMap<Symbol, Literal>, Error> {
+		for i in stmts {
+			self.execute_stmt(i)?;
+		}
+
+		Ok(self.env.borrow().vals.clone())
 	}
 	
-	pub fn interpret(&mut self, stmts: &mut Vec<Stmt>) -> Result<(), Error> {	
+	pub fn interpret(&mut self, stmts: &[Stmt]) -> Result<(), Error> {
 		for i in stmts {
 			match self.execute_stmt(i) {
-				Ok(()) => (),
-				Err(e) => println!("{e}")
+				Err(e) if !e.kind().catchable() => return Err(e),
+				Err(e) => {
+					println!("{e}");
+					self.failed = true;
+				},
+				Ok(_) => ()
 			}
 		}
 
 		Ok(())
 	}
 
-	fn execute_stmt(&mut self, stmt: &mut Stmt) -> Result<(), Error> {
+	fn execute_stmt(&mut self, stmt: &Stmt) -> Result<Flow, Error> {
+		if gc::pending() {
+			gc::collect(vec![Object::Env(self.env.clone())]);
+		}
+		if let Some(budget) = &mut self.budget {
+			budget.step()?;
+		}
+
 		match stmt {
-			Stmt::Variable(t, v) => self.var(&t, &v),
-			Stmt::Print(v) => self.print(&v),
+			Stmt::Variable(t, v, _) => self.var(t, v),
+			Stmt::Function(f) => self.function(f),
+			Stmt::Print(v) => self.print(v),
 			Stmt::Expression(v) => {
 				self.execute_expr(v)?;
-				Ok(())
+				Ok(Flow::Next)
 			},
 			Stmt::Block(v) => self.block(v),
-			Stmt::If(s, v, o) => self.ifcond(s, v, o)
+			Stmt::If(..) => self.ifcond(stmt),
+			Stmt::While(s, v, o) => self.whileloop(s, v, o),
+			Stmt::Break => Ok(Flow::Break),
+			Stmt::Continue => Ok(Flow::Continue),
+			Stmt::Return(_, v) => Ok(Flow::Return(self.execute_expr(v)?)),
+			Stmt::Import(t, path, alias) => self.import(t, path, alias),
+			Stmt::Export(decl) => self.execute_stmt(decl),
+			Stmt::Try(_, body, catch, finally) => self.try_catch(body, catch, finally),
+			Stmt::Throw(t, v) => Err(Error::throw(self.execute_expr(v)?, t))
 		}
 	}
 
-	fn ifcond(&mut self, expr: &Expr, stmt: &mut Stmt, opt: &mut Option<Box<Stmt>>) -> Result<(), Error> {
-		if Literal::is_true_val(self.execute_expr(expr)?)? {
-			self.execute_stmt(stmt)?;
-		} else if let Some(opt) = opt {
-			self.execute_stmt(opt)?;
+	// A `finally` that breaks or returns wins over whatever was happening before it
+	fn try_catch(&mut self, body: &[Stmt], catch: &Option<(Token, Vec<Stmt>)>, finally: &Option<Rc<Vec<Stmt>>>) -> Result<Flow, Error> {
+		let mut res = self.block(body);
+		if let (Err(e), Some((Token { literal: Literal::Identifier(name), .. }, handler))) = (&res, catch) {
+			if e.kind().catchable() {
+				let env = Environment::new(Some(self.env.clone()));
+				env.borrow_mut().define(*name, e.value());
+				res = self.execute_block(handler, env);
+			}
 		}
-		Ok(())
+		// an error that ends the run skips every finally on the way out
+		if res.as_ref().is_err_and(|e| !e.kind().catchable()) {
+			return res;
+		}
+
+		if let Some(finally) = finally {
+			match self.block(finally)? {
+				Flow::Next => (),
+				flow => return Ok(flow)
+			}
+		}
+		res
+	}
+
+	fn import(&mut self, t: &Token, path: &str, alias: &Option<Token>) -> Result<Flow, Error> {
+		let (modules, from) = (self.modules.clone(), self.path.clone());
+		let exports = module::load(&modules, from.as_deref(), self.capabilities, t, path, |path, stmts| self.run_imported(path, stmts))?;
+
+		self.local_scope();
+		module::bind(exports, alias, |name, v| self.env.borrow_mut().define(name, v));
+		Ok(Flow::Next)
+	}
+
+	// On an interpreter of its own, set up like this one and spending from the same budget
+	fn run_imported(&mut self, path: PathBuf, stmts: &[Stmt]) -> Result<HashMap<Symbol, Literal>, Error> {
+		let mut interpreter = Interpreter::with_modules(self.modules.clone(), Some(path));
+		interpreter.max_depth = self.max_depth;
+		interpreter.capabilities = self.capabilities;
+		interpreter.define_args(self.args.clone());
+		interpreter.budget = self.budget.take();
+		let res = interpreter.run_module(stmts);
+		self.budget = interpreter.budget.take();
+		res
+	}
+
+	fn ifcond(&mut self, stmt: &Stmt) -> Result<Flow, Error> {
+		let (arms, otherwise) = stmt.arms();
+		for (_, condition, then) in arms {
+			if Literal::is_true_val(self.execute_expr(condition)?)? {
+				return self.execute_stmt(then);
+			}
+		}
+		match otherwise {
+			Some(otherwise) => self.execute_stmt(otherwise),
+			None => Ok(Flow::Next)
+		}
+	}
+
+	fn whileloop(&mut self, expr: &Expr, stmt: &Stmt, incr: &Option<Expr>) -> Result<Flow, Error> {
+		while Literal::is_true_val(self.execute_expr(expr)?)? {
+			match self.execute_stmt(stmt)? {
+				Flow::Break => break,
+				Flow::Return(v) => return Ok(Flow::Return(v)),
+				Flow::Next | Flow::Continue => ()
+			}
+
+			// reached on `continue` too, so the `for` increment always runs
+			if let Some(incr) = incr {
+				self.execute_expr(incr)?;
+			}
+		}
+		Ok(Flow::Next)
 	}
 
-	fn block(&mut self, stmts: &mut Vec<Stmt>) -> Result<(), Error> {
-		let prev = self.env.clone();
-		self.env = Environment::new(Some(Box::new(prev.clone())));
-		
+	fn block(&mut self, stmts: &[Stmt]) -> Result<Flow, Error> {
+		let env = Environment::new(Some(self.env.clone()));
+		self.execute_block(stmts, env)
+	}
+
+	fn execute_block(&mut self, stmts: &[Stmt], env: EnvRef) -> Result<Flow, Error> {
+		let prev = std::mem::replace(&mut self.env, env);
+
+		let mut res = Ok(Flow::Next);
 		for i in stmts {
-			self.execute_stmt(i)?;
+			res = self.execute_stmt(i);
+			if !matches!(res, Ok(Flow::Next)) {
+				break;
+			}
 		}
 
 		self.env = prev;
 
-		Ok(())
+		res
+	}
+
+	fn function(&mut self, decl: &Rc<Function>) -> Result<Flow, Error> {
+		let Some(Token { literal: Literal::Identifier(name), .. }) = &decl.name else {
+			return Err(Error::fatal("function declaration without a name", None));
+		};
+
+		// in its own scope already, so it can call itself
+		self.local_scope();
+		let closure = Closure::new(decl.clone(), self.env.clone());
+		self.env.borrow_mut().define(*name, Literal::Function(closure));
+		Ok(Flow::Next)
 	}
 
-	fn var(&mut self, t: &Token, v: &Expr) -> Result<(), Error> {
+	// A local is seen only by what comes after it, the way the resolver and the compiler
+	// see it: every declaration in a block starts a scope for the rest of it, and closures
+	// made before keep the one they had. Globals are all in one scope and found at runtime.
+	fn local_scope(&mut self) {
+		if self.env.borrow().enclosing.is_some() {
+			self.env = Environment::new(Some(self.env.clone()));
+		}
+	}
+
+	fn var(&mut self, t: &Token, v: &Expr) -> Result<Flow, Error> {
 		match &t.literal {
 			Literal::Identifier(name) => {
-				let expr = self.execute_expr(&v)?;
-				self.env.define(name.clone(), expr);
-				return Ok(())
+				let expr = self.execute_expr(v)?;
+				self.local_scope();
+				self.env.borrow_mut().define(*name, expr);
+				Ok(Flow::Next)
 			},
-			_ => return Err(Error::fatal("wrong the hell literal", Some(&t))),
+			_ => Err(Error::fatal("wrong the hell literal", Some(t))),
 		}
 	}
 	
-	fn print(&mut self, v: &Expr) -> Result<(), Error> {
-		use Literal::*;
-		
-		match self.execute_expr(&v)? {
-			String(v) => {
-				print!("{}", v.replace("\\n", "\n"));
-			},
-			Float(v) => print!("{v}"),
-			Bool(v) => print!("{v}"),
-			Nil => print!("nil"),
-			Identifier(_) => print!("identifier"),
-		}
-
-		Ok(())
+	fn print(&mut self, v: &Expr) -> Result<Flow, Error> {
+		builtins::print(&self.execute_expr(v)?);
+		Ok(Flow::Next)
 	}
 
 	fn execute_expr(&mut self, expr: &Expr) -> Result<Literal, Error> {
+		if let Some(budget) = &mut self.budget {
+			budget.step()?;
+		}
+
 		use Expr::*;
 		match expr {
-			Binary(v1, t, v2) => self.binary(v1, t, v2),
-			Logical(v1, t, v2) => self.logical(v1, t, v2),
+			Binary(..) | Logical(..) | Index(..) | Call(..) => self.chain(expr),
 			Unary(t, v) => self.unary(t, v),
-			Group(v) => self.execute_expr(&v),
-			Variable(t) => self.env.get(t),
-			Assign(t, v) => self.assign(&t, &v),
+			Group(v) => self.execute_expr(v),
+			Variable(t) => self.env.borrow().get(t),
+			Assign(t, v) => self.assign(t, v),
+			Postfix(t, v) => self.postfix(t, v),
+			List(v) => self.list(v),
+			Map(t, v) => self.map(t, v),
+			SetIndex(l, t, i, op, v) => self.set_index(l, t, i, op, v),
+			Lambda(f) => Ok(Literal::Function(Closure::new(f.clone(), self.env.clone()))),
 			Constant(v) => Ok(v.clone())
 		}
 	}
 
+	fn list(&mut self, exprs: &[Expr]) -> Result<Literal, Error> {
+		let mut vals = Vec::with_capacity(exprs.len());
+		for i in exprs {
+			vals.push(self.execute_expr(i)?);
+		}
+
+		Ok(builtins::new_list(vals))
+	}
+
+	fn map(&mut self, t: &Token, exprs: &[(Expr, Expr)]) -> Result<Literal, Error> {
+		let mut map = Map::new();
+		for (k, v) in exprs {
+			let key = self.execute_expr(k)?;
+			let val = self.execute_expr(v)?;
+			if !map.insert(key, val) {
+				return Err(Error::fatal("map keys must be strings or numbers", Some(t)).of(ErrorKind::Type));
+			}
+		}
+
+		Ok(builtins::new_map(map))
+	}
+
+	// Each link gets the value of the ones inside of it
+	fn chain(&mut self, expr: &Expr) -> Result<Literal, Error> {
+		let (first, links) = expr.chain();
+		let outermost = links.len() - 1;
+		let mut value = self.execute_expr(first)?;
+		for (i, link) in links.into_iter().enumerate() {
+			// the outermost one was counted on the way in
+			if let (true, Some(budget)) = (i < outermost, &mut self.budget) {
+				budget.step()?;
+			}
+
+			value = match link {
+				Expr::Binary(_, t, right) => self.binary(value, t, right)?,
+				Expr::Logical(_, t, right) => self.logical(value, t, right)?,
+				Expr::Index(_, t, key) => self.index(value, t, key)?,
+				Expr::Call(_, t, exprs) => self.call(value, t, exprs)?,
+				_ => return Err(Error::fatal("unexpected link in a chain", None))
+			};
+		}
+		Ok(value)
+	}
+
+	fn index(&mut self, container: Literal, t: &Token, key: &Expr) -> Result<Literal, Error> {
+		let key = self.execute_expr(key)?;
+		builtins::get_item(&container, &key, t)
+	}
+
+	fn set_index(&mut self, container: &Expr, t: &Token, key: &Expr, op: &Option<Token>, v: &Expr) -> Result<Literal, Error> {
+		let container = self.execute_expr(container)?;
+		let key = self.execute_expr(key)?;
+		let mut val = self.execute_expr(v)?;
+
+		if let Some(op) = op {
+			let current = builtins::get_item(&container, &key, t)?;
+			val = Self::operate(op, current, val).map_err(|e| e.on_line(op.line))?;
+		}
+
+		builtins::set_item(&container, key, val.clone(), t)?;
+		Ok(val)
+	}
+
+	fn call(&mut self, callee: Literal, t: &Token, exprs: &[Expr]) -> Result<Literal, Error> {
+		let mut args = Vec::with_capacity(exprs.len());
+		for i in exprs {
+			args.push(self.execute_expr(i)?);
+		}
+
+		self.call_value(&callee, t, args)
+	}
+
 	fn assign(&mut self, t: &Token, expr: &Expr) -> Result<Literal, Error> {
 		let val = self.execute_expr(expr)?;
-		match self.env.assign(&t, &val) {
+		match self.env.borrow_mut().assign(t, &val) {
 			Ok(()) => {
 				Ok(val)
 			}
 			Err(()) => {
-				return Err(Error::fatal("trying to change non-existing variable", Some(&t)))
+				Err(Error::fatal("trying to change non-existing variable", Some(t)).of(ErrorKind::Name))
 			}
 		}
 	}
 
-	fn logical(&mut self, v1: &Box<Expr>, t: &Token, v2: &Box<Expr>) -> Result<Literal, Error> {
-		let left = self.execute_expr(v1);
+	fn postfix(&mut self, t: &Token, target: &Expr) -> Result<Literal, Error> {
+		match target {
+			Expr::Variable(name) => {
+				let old = self.env.borrow().get(name)?;
+				let new = Self::step(t, &old)?;
+				match self.env.borrow_mut().assign(name, &new) {
+					Ok(()) => Ok(old),
+					Err(()) => Err(Error::fatal("trying to change non-existing variable", Some(name)).of(ErrorKind::Name))
+				}
+			},
+			Expr::Index(container, bracket, key) => {
+				let container = self.execute_expr(container)?;
+				let key = self.execute_expr(key)?;
+				let old = builtins::get_item(&container, &key, bracket)?;
+				builtins::set_item(&container, key, Self::step(t, &old)?, bracket)?;
+				Ok(old)
+			},
+			_ => Err(Error::fatal("invalid increment target", Some(t)))
+		}
+	}
+
+	fn step(t: &Token, v: &Literal) -> Result<Literal, Error> {
+		let Literal::Float(v) = v else {
+			return Err(Error::fatal("cannot increment non-number", Some(t)).of(ErrorKind::Type))
+		};
 
+		match t.toktype {
+			TokenType::PlusPlus => Ok(Literal::Float(v + 1.0)),
+			_ => Ok(Literal::Float(v - 1.0))
+		}
+	}
+
+	fn logical(&mut self, left: Literal, t: &Token, v2: &Expr) -> Result<Literal, Error> {
 		if t.toktype == TokenType::Or {
-			if Literal::is_true_val(self.execute_expr(v1)?)? {
-				return left
-			}
-		} else {
-			if !Literal::is_true_val(self.execute_expr(v1)?)? {
-				return left
+			if Literal::is_true_val(left.clone())? {
+				return Ok(left)
 			}
+		} else if !Literal::is_true_val(left.clone())? {
+			return Ok(left)
 		}
 
 		self.execute_expr(v2)
 	}
 	
-	fn binary(&mut self, v1: &Box<Expr>, t: &Token, v2: &Box<Expr>) -> Result<Literal, Error> {
+	fn binary(&mut self, left: Literal, t: &Token, v2: &Expr) -> Result<Literal, Error> {
+		let right = self.execute_expr(v2)?;
+		Self::operate(t, left, right).map_err(|e| e.on_line(t.line))
+	}
+
+	fn operate(t: &Token, v1: Literal, v2: Literal) -> Result<Literal, Error> {
 		use TokenType::*;
 		match &t.toktype {
-			Plus => Literal::sum(self.execute_expr(v1)?, self.execute_expr(v2)?),
-			Minus => Literal::sub(self.execute_expr(v1)?, self.execute_expr(v2)?),
-			Star => Literal::mul(self.execute_expr(v1)?, self.execute_expr(v2)?),
-			Slash => Literal::div(self.execute_expr(v1)?, self.execute_expr(v2)?),
-			EqualEqual => Literal::eq(self.execute_expr(v1)?, self.execute_expr(v2)?),
-			Greater => Literal::gt(self.execute_expr(v1)?, self.execute_expr(v2)?),
-			GreaterEqual => Literal::egt(self.execute_expr(v1)?, self.execute_expr(v2)?),
-			Less => Literal::lt(self.execute_expr(v1)?, self.execute_expr(v2)?),
-			LessEqual => Literal::elt(self.execute_expr(v1)?, self.execute_expr(v2)?),
-			_ => Err(Error::fatal("unexpected operator in binary!", Some(&t)))
+			Plus => Literal::sum(v1, v2),
+			Minus => Literal::sub(v1, v2),
+			Star => Literal::mul(v1, v2),
+			Slash => Literal::div(v1, v2),
+			Percent => Literal::rem(v1, v2),
+			EqualEqual => Literal::eq(v1, v2),
+			BangEqual => Ok(Literal::Bool(!Literal::is_true_val(Literal::eq(v1, v2)?)?)),
+			Greater => Literal::gt(v1, v2),
+			GreaterEqual => Literal::egt(v1, v2),
+			Less => Literal::lt(v1, v2),
+			LessEqual => Literal::elt(v1, v2),
+			_ => Err(Error::fatal("unexpected operator in binary!", Some(t)))
 		}
 	}
 
-	fn unary(&mut self, t: &Token, v: &Box<Expr>) -> Result<Literal, Error> {
+	fn unary(&mut self, t: &Token, v: &Expr) -> Result<Literal, Error> {
 		use TokenType::*;
 		match &t.toktype {
-			Minus => Literal::sub(Literal::Float(0.0), self.execute_expr(&v)?),
+			Minus => Literal::sub(Literal::Float(0.0), self.execute_expr(v)?).map_err(|e| e.on_line(t.line)),
 			Bang => {
 				match self.execute_expr(v)? {
 					Literal::Bool(b) => Ok(Literal::Bool(!b)),
-					_ => Err(Error::fatal("unexpected operator in unary!", Some(&t)))
+					_ => Err(Error::fatal("unexpected operator in unary!", Some(t)).of(ErrorKind::Type))
 				}
 			},
-			_ => Err(Error::fatal("unexpected operator in unary!", Some(&t)))
+			_ => Err(Error::fatal("unexpected operator in unary!", Some(t)))
 		}
 	}
 
 }
+
+impl Caller for Interpreter {
+	fn call_value(&mut self, callee: &Literal, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+		match callee {
+			Literal::Native(native) => builtins::call_native(self, native, t, args),
+			Literal::Function(f) => {
+				if args.len() != f.decl.params.len() {
+					return Err(Error::fatal(format!("{f:?} expects {} arguments, got {}", f.decl.params.len(), args.len()).as_str(), Some(t)).of(ErrorKind::Arity));
+				}
+
+				let env = Environment::new(Some(f.env.clone()));
+				for (param, arg) in f.decl.params.iter().zip(args) {
+					if let Literal::Identifier(name) = &param.literal {
+						env.borrow_mut().define(*name, arg);
+					}
+				}
+
+				if self.depth >= self.max_depth {
+					return Err(builtins::stack_overflow(t, self.max_depth));
+				}
+				self.depth += 1;
+				let res = self.execute_block(&f.decl.body, env);
+				self.depth -= 1;
+
+				match res.map_err(|e| e.traced(format!("{f:?}"), t.line))? {
+					Flow::Return(v) => Ok(v),
+					_ => Ok(Literal::Nil)
+				}
+			},
+			_ => Err(Error::fatal("can only call functions", Some(t)).of(ErrorKind::Type))
+		}
+	}
+
+	fn allows(&self, capability: Capability) -> bool {
+		self.capabilities.allows(capability)
+	}
+}
@@ -0,0 +1,285 @@
+// Just enough JSON for the language server, objects keep their key order
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+	Null,
+	Bool(bool),
+	Number(f64),
+	String(String),
+	Array(Vec<Json>),
+	Object(Vec<(String, Json)>)
+}
+
+impl Json {
+	pub fn object(pairs: Vec<(&str, Json)>) -> Json {
+		Json::Object(pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+	}
+
+	pub fn get(&self, key: &str) -> Option<&Json> {
+		match self {
+			Json::Object(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+			_ => None
+		}
+	}
+
+	// `get` through several keys, `doc.at(&["position", "line"])`
+	pub fn at(&self, path: &[&str]) -> Option<&Json> {
+		path.iter().try_fold(self, |v, key| v.get(key))
+	}
+
+	pub fn as_str(&self) -> Option<&str> {
+		match self {
+			Json::String(s) => Some(s),
+			_ => None
+		}
+	}
+
+	pub fn as_f64(&self) -> Option<f64> {
+		match self {
+			Json::Number(v) => Some(*v),
+			_ => None
+		}
+	}
+
+	pub fn as_array(&self) -> Option<&[Json]> {
+		match self {
+			Json::Array(v) => Some(v),
+			_ => None
+		}
+	}
+
+	pub fn parse(text: &str) -> Result<Json, String> {
+		let mut parser = JsonParser { chars: text.chars().collect(), pos: 0, depth: 0 };
+		let v = parser.value()?;
+		parser.skip_whitespace();
+		if parser.pos != parser.chars.len() {
+			return Err(format!("unexpected trailing characters at {}", parser.pos));
+		}
+		Ok(v)
+	}
+}
+
+impl From<&str> for Json {
+	fn from(v: &str) -> Json {
+		Json::String(v.to_string())
+	}
+}
+
+impl From<String> for Json {
+	fn from(v: String) -> Json {
+		Json::String(v)
+	}
+}
+
+impl From<usize> for Json {
+	fn from(v: usize) -> Json {
+		Json::Number(v as f64)
+	}
+}
+
+impl From<bool> for Json {
+	fn from(v: bool) -> Json {
+		Json::Bool(v)
+	}
+}
+
+fn write_string(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+	write!(f, "\"")?;
+	for c in s.chars() {
+		match c {
+			'"' => write!(f, "\\\"")?,
+			'\\' => write!(f, "\\\\")?,
+			'\n' => write!(f, "\\n")?,
+			'\r' => write!(f, "\\r")?,
+			'\t' => write!(f, "\\t")?,
+			c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+			c => write!(f, "{c}")?
+		}
+	}
+	write!(f, "\"")
+}
+
+impl std::fmt::Display for Json {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Json::Null => write!(f, "null"),
+			Json::Bool(v) => write!(f, "{v}"),
+			Json::Number(v) if v.is_finite() => write!(f, "{v}"),
+			Json::Number(_) => write!(f, "null"),
+			Json::String(s) => write_string(f, s),
+			Json::Array(items) => {
+				write!(f, "[")?;
+				for (i, item) in items.iter().enumerate() {
+					if i > 0 {
+						write!(f, ",")?;
+					}
+					write!(f, "{item}")?;
+				}
+				write!(f, "]")
+			},
+			Json::Object(pairs) => {
+				write!(f, "{{")?;
+				for (i, (k, v)) in pairs.iter().enumerate() {
+					if i > 0 {
+						write!(f, ",")?;
+					}
+					write_string(f, k)?;
+					write!(f, ":{v}")?;
+				}
+				write!(f, "}}")
+			}
+		}
+	}
+}
+
+struct JsonParser {
+	chars: Vec<char>,
+	pos: usize,
+	// arrays and objects inside each other right now
+	depth: usize
+}
+
+// Deeper than this is an error, not the end of the stack
+const MAX_DEPTH: usize = 128;
+
+impl JsonParser {
+	fn skip_whitespace(&mut self) {
+		while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+			self.pos += 1;
+		}
+	}
+
+	fn next(&mut self) -> Result<char, String> {
+		let c = self.chars.get(self.pos).copied().ok_or("unexpected end of json")?;
+		self.pos += 1;
+		Ok(c)
+	}
+
+	fn expect(&mut self, word: &str) -> Result<(), String> {
+		for c in word.chars() {
+			if self.next()? != c {
+				return Err(format!("expected {word} at {}", self.pos));
+			}
+		}
+		Ok(())
+	}
+
+	fn value(&mut self) -> Result<Json, String> {
+		self.skip_whitespace();
+		match self.chars.get(self.pos) {
+			Some('n') => self.expect("null").map(|_| Json::Null),
+			Some('t') => self.expect("true").map(|_| Json::Bool(true)),
+			Some('f') => self.expect("false").map(|_| Json::Bool(false)),
+			Some('"') => self.string().map(Json::String),
+			Some('[') => self.nested(Self::array),
+			Some('{') => self.nested(Self::object),
+			Some(c) if *c == '-' || c.is_ascii_digit() => self.number(),
+			_ => Err(format!("unexpected character at {}", self.pos))
+		}
+	}
+
+	fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Result<Json, String>) -> Result<Json, String> {
+		if self.depth >= MAX_DEPTH {
+			return Err(format!("nested more than {MAX_DEPTH} levels deep at {}", self.pos));
+		}
+
+		self.depth += 1;
+		let res = parse(self);
+		self.depth -= 1;
+		res
+	}
+
+	fn number(&mut self) -> Result<Json, String> {
+		let start = self.pos;
+		while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+			self.pos += 1;
+		}
+		let text: String = self.chars[start..self.pos].iter().collect();
+		text.parse().map(Json::Number).map_err(|_| format!("invalid number {text}"))
+	}
+
+	fn hex(&mut self) -> Result<u32, String> {
+		let mut v = 0;
+		for _ in 0..4 {
+			let digit = self.next()?.to_digit(16).ok_or("invalid unicode escape")?;
+			v = v * 16 + digit;
+		}
+		Ok(v)
+	}
+
+	fn string(&mut self) -> Result<String, String> {
+		self.pos += 1;
+		let mut s = String::new();
+		loop {
+			match self.next()? {
+				'"' => return Ok(s),
+				'\\' => match self.next()? {
+					'n' => s.push('\n'),
+					'r' => s.push('\r'),
+					't' => s.push('\t'),
+					'b' => s.push('\u{8}'),
+					'f' => s.push('\u{c}'),
+					'u' => {
+						let mut code = self.hex()?;
+						// a pair of utf-16 surrogates makes up one char
+						if (0xd800..0xdc00).contains(&code) && self.chars.get(self.pos) == Some(&'\\') {
+							self.pos += 1;
+							self.expect("u")?;
+							let low = self.hex()?;
+							code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+						}
+						s.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+					},
+					c => s.push(c)
+				},
+				c => s.push(c)
+			}
+		}
+	}
+
+	fn array(&mut self) -> Result<Json, String> {
+		self.pos += 1;
+		let mut items = Vec::new();
+		self.skip_whitespace();
+		if self.chars.get(self.pos) == Some(&']') {
+			self.pos += 1;
+			return Ok(Json::Array(items));
+		}
+		loop {
+			items.push(self.value()?);
+			self.skip_whitespace();
+			match self.next()? {
+				',' => (),
+				']' => return Ok(Json::Array(items)),
+				_ => return Err(format!("expected , or ] at {}", self.pos))
+			}
+		}
+	}
+
+	fn object(&mut self) -> Result<Json, String> {
+		self.pos += 1;
+		let mut pairs = Vec::new();
+		self.skip_whitespace();
+		if self.chars.get(self.pos) == Some(&'}') {
+			self.pos += 1;
+			return Ok(Json::Object(pairs));
+		}
+		loop {
+			self.skip_whitespace();
+			if self.chars.get(self.pos) != Some(&'"') {
+				return Err(format!("expected key at {}", self.pos));
+			}
+			let key = self.string()?;
+			self.skip_whitespace();
+			if self.next()? != ':' {
+				return Err(format!("expected : at {}", self.pos));
+			}
+			pairs.push((key, self.value()?));
+			self.skip_whitespace();
+			match self.next()? {
+				',' => (),
+				'}' => return Ok(Json::Object(pairs)),
+				_ => return Err(format!("expected , or }} at {}", self.pos))
+			}
+		}
+	}
+}
@@ -2,8 +2,10 @@
 use crate::lll::token::TokenType;
 use crate::lll::token::Literal;
 use crate::lll::token::Token;
+use crate::lll::symbol::Symbol;
 use crate::lll::error::Error;
 
+// Offsets are in bytes, so slicing always lands on a char boundary
 struct Stringstream {
 	text: String,
 	offset: usize,
@@ -15,20 +17,17 @@ impl Stringstream {
 	}
 	
 	fn advance(&mut self) -> Option<char> {
-		let c = self.text.chars().nth(self.offset);
-		self.offset += 1;
-		c
+		let c = self.peek()?;
+		self.offset += c.len_utf8();
+		Some(c)
 	}
 
-	fn substring(&self, start: usize, end: usize) -> Option<String> {
-		if start > self.text.len() || end > self.text.len() {
-			return None
-		}
-		Some(self.text[start..end].to_string())
+	fn substring(&self, start: usize, end: usize) -> Option<&str> {
+		self.text.get(start..end)
 	}
 	
 	fn peek(&self) -> Option<char> {
-		self.text.chars().nth(self.offset)
+		self.text[self.offset..].chars().next()
 	}
 }
 
@@ -37,25 +36,54 @@ pub struct Lexer {
 	line: usize,
 	place: usize,
 	prev_place: usize,
+	// comments stay out of the token list, see `scan_lossless`
+	lossless: bool,
 	
 	tokens: Vec<Token>
 }
 
 impl Lexer {
 	pub fn new(source: String) -> Self {
-		Self { ss: Stringstream::new(source), line: 0, place: 0, prev_place: 0, tokens: vec![] }
+		Self { ss: Stringstream::new(source), line: 0, place: 0, prev_place: 0, lossless: false, tokens: vec![] }
 	}
 
 	pub fn scan_tokens(&mut self) -> Vec<Token> {
+		let (tokens, errors) = self.scan_with_errors();
+		for v in errors {
+			println!("{v}");
+		}
+
+		tokens
+	}
+
+	// Same as `scan_tokens`, but the errors are handed back instead of printed
+	pub fn scan_with_errors(&mut self) -> (Vec<Token>, Vec<Error>) {
+		let mut errors = Vec::new();
 		while let Some(c) = self.advance() {
-			match self.scan_token(c) {
-				Ok(()) => (),
-				Err(v) => println!("{v}")
+			if let Err(v) = self.scan_token(c) {
+				errors.push(v);
 			}
 			self.prev_place = self.place;
 		}
 
-		self.tokens.clone()
+		(std::mem::take(&mut self.tokens), errors)
+	}
+
+	// Splits the whole source into byte ranges, each one either a token or trivia
+	// (whitespace, comments and anything that failed to lex) when there's no token
+	pub fn scan_lossless(&mut self) -> Vec<(Option<Token>, std::ops::Range<usize>)> {
+		self.lossless = true;
+		let mut pieces = Vec::new();
+		while let Some(c) = self.advance() {
+			let before = self.tokens.len();
+			// a piece that failed to lex simply stays trivia
+			let _ = self.scan_token(c);
+			let token = if self.tokens.len() > before { self.tokens.pop() } else { None };
+			pieces.push((token, self.prev_place..self.place));
+			self.prev_place = self.place;
+		}
+
+		pieces
 	}
 
 	fn scan_token(&mut self, c: char) -> Result<(), Error> {
@@ -65,11 +93,43 @@ impl Lexer {
 			')' => self.add_primitive_token(RightParen),
 			'{' => self.add_primitive_token(LeftBrace),
 			'}' => self.add_primitive_token(RightBrace),
+			'[' => self.add_primitive_token(LeftBracket),
+			']' => self.add_primitive_token(RightBracket),
 			',' => self.add_primitive_token(Comma),
+			':' => self.add_primitive_token(Colon),
 			'.' => self.add_primitive_token(Dot),
-			'+' => self.add_primitive_token(Plus),
-			'-' => self.add_primitive_token(Minus),
-			'*' => self.add_primitive_token(Star),
+			'+' => {
+				if self.is('=') {
+					self.add_primitive_token(PlusEqual);
+				} else if self.is('+') {
+					self.add_primitive_token(PlusPlus);
+				} else {
+					self.add_primitive_token(Plus);
+				}
+			},
+			'-' => {
+				if self.is('=') {
+					self.add_primitive_token(MinusEqual);
+				} else if self.is('-') {
+					self.add_primitive_token(MinusMinus);
+				} else {
+					self.add_primitive_token(Minus);
+				}
+			},
+			'*' => {
+				if self.is('=') {
+					self.add_primitive_token(StarEqual);
+				} else {
+					self.add_primitive_token(Star);
+				}
+			},
+			'%' => {
+				if self.is('=') {
+					self.add_primitive_token(PercentEqual);
+				} else {
+					self.add_primitive_token(Percent);
+				}
+			},
 			';' => self.add_primitive_token(Semicolon),
 			'\r' | '\t' | ' ' | '\n' => {},
 			'!' => {
@@ -82,6 +142,8 @@ impl Lexer {
 			'=' => {
 				if self.is('=') {
 					self.add_primitive_token(EqualEqual);
+				} else if self.is('>') {
+					self.add_primitive_token(Arrow);
 				} else {
 					self.add_primitive_token(Equal);
 				}
@@ -102,24 +164,68 @@ impl Lexer {
 			},
 			'/' => {
 				if self.is('/') {
+					if self.is('/') {
+						self.doc_comment();
+						return Ok(());
+					}
 					while let Some(c) = self.ss.peek() {
 						if c == '\n' { break; }
 						self.advance();
 					}
+				} else if self.is('*') {
+					self.block_comment()?;
+				} else if self.is('=') {
+					self.add_primitive_token(SlashEqual);
 				} else {
 					self.add_primitive_token(Slash);
 				}
 			},
-			'"' => self.string(),
-			c if c.is_digit(10) => self.number(),
+			'"' => self.string()?,
+			c if c.is_ascii_digit() => self.number(),
 			c if c.is_alphabetic() => self.indentifier(),
 			_ => {
-				return Err(Error::fatal(format!("unexpected character, char {c}, line {}, place {}", self.line, self.place).as_str(), None));
+				return Err(Error::fatal(format!("unexpected character, char {c}, line {}, place {}", self.line, self.place).as_str(), None).at(self.prev_place));
+			}
+		}
+		Ok(())
+	}
+
+	// Block comments nest, so commenting out code that has one inside still works
+	fn block_comment(&mut self) -> Result<(), Error> {
+		let line = self.line;
+		let mut depth = 1;
+		while depth > 0 {
+			match self.advance() {
+				Some('*') if self.is('/') => depth -= 1,
+				Some('/') if self.is('*') => depth += 1,
+				Some(_) => (),
+				None => return Err(Error::fatal(format!("unterminated comment, line {}", line + 1).as_str(), None).at(self.prev_place))
 			}
 		}
 		Ok(())
 	}
 
+	// Consecutive doc lines are joined into one token
+	fn doc_comment(&mut self) {
+		let start = self.place;
+		while let Some(c) = self.ss.peek() {
+			if c == '\n' { break; }
+			self.advance();
+		}
+		if self.lossless {
+			return;
+		}
+
+		let line = self.substring(Some(start), Some(self.place)).unwrap();
+		let line = line.strip_prefix(' ').unwrap_or(line).trim_end().to_string();
+		if let Some(Token { toktype: TokenType::Doc, literal: Literal::String(doc), .. }) = self.tokens.last_mut() {
+			doc.push('\n');
+			doc.push_str(&line);
+			return;
+		}
+		self.add_token(Literal::String(line), TokenType::Doc, self.prev_place, self.line);
+	}
+
 	fn number(&mut self) {		
 		let mut flag_dot = false;
 		while let Some(peeker) = self.ss.peek() {
@@ -128,7 +234,7 @@ impl Lexer {
 				self.advance();
 				continue;
 			}
-			if !(peeker.is_digit(10)) {
+			if !peeker.is_ascii_digit() {
 				break;
 			}
 
@@ -139,7 +245,8 @@ impl Lexer {
 		self.add_token(Literal::Float(floatlit.parse::<f64>().unwrap()), TokenType::Number, self.prev_place, self.line);
 	}
 	
-	fn string(&mut self) {
+	fn string(&mut self) -> Result<(), Error> {
+		let line = self.line;
 		let mut flag_terminated = false;
 		while let Some(peeker) = self.ss.peek() {
 			if peeker == '"' {
@@ -152,17 +259,18 @@ impl Lexer {
 		}
 
 		if !flag_terminated {
-			eprintln!("FATAL: unterminated string at {}", self.line);
-			std::process::exit(69);
+			return Err(Error::fatal(format!("unterminated string, line {}", line + 1).as_str(), None).at(self.prev_place));
 		}
 		
-		let strlit = self.substring(Some(self.prev_place + 1), Some(self.place - 1)).unwrap();
+		// `\n` is the one escape there is
+		let strlit = self.substring(Some(self.prev_place + 1), Some(self.place - 1)).unwrap().replace("\\n", "\n");
 		self.add_token(Literal::String(strlit), TokenType::String, self.prev_place, self.line);
+		Ok(())
 	}
 	
 	fn indentifier(&mut self) {
 		while let Some(peeker) = self.ss.peek() {
-			if !(peeker.is_alphabetic() || peeker == '_' || peeker.is_digit(10)) {
+			if !(peeker.is_alphabetic() || peeker == '_' || peeker.is_ascii_digit()) {
 				break
 			}
 
@@ -170,8 +278,8 @@ impl Lexer {
 		}
 
 		let iden = self.substring(None, None).unwrap();
-		let Some(keyword) = self.keyword_map(&iden) else {
-			self.add_token(Literal::Identifier(iden), TokenType::Identifier, self.prev_place, self.line);
+		let Some(keyword) = self.keyword_map(iden) else {
+			self.add_token(Literal::Identifier(Symbol::intern(iden)), TokenType::Identifier, self.prev_place, self.line);
 			return;
 		};
 
@@ -186,7 +294,7 @@ impl Lexer {
 		self.add_token(Literal::Nil, keyword, self.prev_place, self.line);
 	}
 
-	fn keyword_map(&self, word: &String) -> Option<TokenType> {
+	fn keyword_map(&self, word: &str) -> Option<TokenType> {
 		use TokenType::*;
 		
 		if word == "and" {
@@ -221,6 +329,24 @@ impl Lexer {
 			return Some(New)
 		} else if word == "while" {
 			return Some(While)
+		} else if word == "break" {
+			return Some(Break)
+		} else if word == "continue" {
+			return Some(Continue)
+		} else if word == "import" {
+			return Some(Import)
+		} else if word == "export" {
+			return Some(Export)
+		} else if word == "as" {
+			return Some(As)
+		} else if word == "try" {
+			return Some(Try)
+		} else if word == "catch" {
+			return Some(Catch)
+		} else if word == "finally" {
+			return Some(Finally)
+		} else if word == "throw" {
+			return Some(Throw)
 		}
 
 		None
@@ -239,10 +365,10 @@ impl Lexer {
 			return true;
 		}
 
-		return false;
+		false
 	}
 
-	fn substring(&self, start: Option<usize>, end: Option<usize>) -> Option<String> {
+	fn substring(&self, start: Option<usize>, end: Option<usize>) -> Option<&str> {
 		let (Some(start), Some(end)) = (start, end) else {
 			return self.ss.substring(self.prev_place, self.place);
 		};
@@ -253,7 +379,7 @@ impl Lexer {
 	fn advance(&mut self) -> Option<char> {
 		match self.ss.advance() {
 			Some(v) => {
-				self.place += 1;
+				self.place += v.len_utf8();
 				if v == '\n' { self.line += 1; }
 				Some(v)
 			},
@@ -0,0 +1,312 @@
+use super::error::Error;
+use super::parse::Expr;
+use super::parse::Stmt;
+use super::resolve;
+use super::resolve::DeclKind;
+use super::token::Literal;
+use super::token::Token;
+use super::token::TokenType;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lint {
+	UnusedVariable,
+	ShadowedVariable,
+	UnreachableCode,
+	ConstantCondition,
+	SelfAssignment,
+	MismatchedComparison
+}
+
+impl Lint {
+	pub const ALL: [Lint; 6] = [
+		Lint::UnusedVariable,
+		Lint::ShadowedVariable,
+		Lint::UnreachableCode,
+		Lint::ConstantCondition,
+		Lint::SelfAssignment,
+		Lint::MismatchedComparison
+	];
+
+	// What `--deny` and `--allow` take
+	pub fn name(&self) -> &'static str {
+		match self {
+			Lint::UnusedVariable => "unused-variable",
+			Lint::ShadowedVariable => "shadowed-variable",
+			Lint::UnreachableCode => "unreachable-code",
+			Lint::ConstantCondition => "constant-condition",
+			Lint::SelfAssignment => "self-assignment",
+			Lint::MismatchedComparison => "mismatched-comparison"
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Lint> {
+		Lint::ALL.into_iter().find(|lint| lint.name() == name)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+	Allow,
+	Warn,
+	Deny
+}
+
+// Every lint warns unless told otherwise
+#[derive(Debug, Clone, Copy)]
+pub struct Lints {
+	levels: [Level; Lint::ALL.len()]
+}
+
+impl Default for Lints {
+	fn default() -> Self {
+		Self { levels: [Level::Warn; Lint::ALL.len()] }
+	}
+}
+
+impl Lints {
+	pub fn set(&mut self, lint: Lint, level: Level) {
+		self.levels[lint as usize] = level;
+	}
+
+	pub fn set_all(&mut self, level: Level) {
+		self.levels = [level; Lint::ALL.len()];
+	}
+
+	pub fn level(&self, lint: Lint) -> Level {
+		self.levels[lint as usize]
+	}
+}
+
+// Looks for code that runs but can't be what was meant. Denied lints come back as
+// fatal errors, the rest as warnings.
+pub fn lint(stmts: &[Stmt], lints: &Lints) -> Vec<Error> {
+	let mut linter = Linter { lints, out: Vec::new() };
+
+	let resolution = resolve::resolve(stmts);
+	for (i, d) in resolution.declarations.iter().enumerate() {
+		let name = match &d.name.literal {
+			Literal::Identifier(name) => name.as_str(),
+			_ => continue
+		};
+		if d.kind != DeclKind::Variable || d.exported {
+			continue;
+		}
+		let read = resolution.uses.iter().any(|u| u.declaration == Some(i) && !u.write);
+		if !read {
+			linter.report(Lint::UnusedVariable, &format!("variable {name} is never read"), Some(&d.name));
+		}
+		if d.shadows.is_some() {
+			linter.report(Lint::ShadowedVariable, &format!("{name} shadows a variable of an outer scope"), Some(&d.name));
+		}
+	}
+
+	// an error only stops the top level statement it happens in, so nothing there is unreachable
+	for stmt in stmts {
+		linter.stmt(stmt);
+	}
+	linter.out.sort_by_key(|e| e.place());
+	linter.out
+}
+
+struct Linter<'a> {
+	lints: &'a Lints,
+	out: Vec<Error>
+}
+
+impl Linter<'_> {
+	fn report(&mut self, lint: Lint, msg: &str, token: Option<&Token>) {
+		let msg = format!("{msg} [{}]", lint.name());
+		match self.lints.level(lint) {
+			Level::Allow => (),
+			Level::Warn => self.out.push(Error::warn(&msg, token)),
+			Level::Deny => self.out.push(Error::fatal(&msg, token))
+		}
+	}
+
+	fn stmts(&mut self, stmts: &[Stmt]) {
+		let mut reported = false;
+		for (i, stmt) in stmts.iter().enumerate() {
+			// one warning for the whole dead tail is enough
+			if !reported && i > 0 && diverges(&stmts[i - 1]) {
+				match stmt_token(stmt) {
+					Some(t) => self.report(Lint::UnreachableCode, "unreachable code", Some(t)),
+					None => self.report(Lint::UnreachableCode, "code after this never runs", stmt_token(&stmts[i - 1]))
+				}
+				reported = true;
+			}
+			self.stmt(stmt);
+		}
+	}
+
+	fn stmt(&mut self, stmt: &Stmt) {
+		match stmt {
+			Stmt::Print(e) | Stmt::Expression(e) | Stmt::Return(_, e) | Stmt::Throw(_, e) | Stmt::Variable(_, e, _) => self.expr(e),
+			Stmt::Function(f) => self.stmts(&f.body),
+			Stmt::Block(stmts) => self.stmts(stmts),
+			Stmt::If(..) => {
+				let (arms, otherwise) = stmt.arms();
+				for (keyword, condition, then) in arms {
+					// a bare `true` or `false` is there on purpose, to switch code on or off
+					let deliberate = matches!(condition, Expr::Constant(Literal::Bool(_)));
+					if is_constant(condition) && !deliberate {
+						self.report(Lint::ConstantCondition, "condition is always the same", Some(keyword));
+					}
+					self.expr(condition);
+					self.stmt(then);
+				}
+				if let Some(otherwise) = otherwise {
+					self.stmt(otherwise);
+				}
+			},
+			Stmt::While(condition, body, increment) => {
+				self.expr(condition);
+				self.stmt(body);
+				if let Some(increment) = increment {
+					self.expr(increment);
+				}
+			},
+			Stmt::Export(decl) => self.stmt(decl),
+			Stmt::Try(_, body, catch, finally) => {
+				self.stmts(body);
+				if let Some((_, stmts)) = catch {
+					self.stmts(stmts);
+				}
+				if let Some(stmts) = finally {
+					self.stmts(stmts);
+				}
+			},
+			Stmt::Break | Stmt::Continue | Stmt::Import(..) => ()
+		}
+	}
+
+	fn expr(&mut self, expr: &Expr) {
+		match expr {
+			Expr::Binary(..) | Expr::Logical(..) | Expr::Index(..) | Expr::Call(..) => {
+				let (first, links) = expr.chain();
+				self.expr(first);
+				for link in links {
+					match link {
+						Expr::Binary(l, op, r) => {
+							self.comparison(l, op, r);
+							self.expr(r);
+						},
+						Expr::Logical(_, _, r) | Expr::Index(_, _, r) => self.expr(r),
+						Expr::Call(_, _, args) => args.iter().for_each(|e| self.expr(e)),
+						_ => ()
+					}
+				}
+			},
+			Expr::Unary(_, e) | Expr::Group(e) | Expr::Postfix(_, e) => self.expr(e),
+			Expr::Assign(name, value) => {
+				if let Expr::Variable(other) = value.as_ref() {
+					if name.literal.equals(&other.literal) {
+						self.report(Lint::SelfAssignment, "variable is assigned to itself", Some(name));
+					}
+				}
+				self.expr(value);
+			},
+			Expr::List(items) => items.iter().for_each(|e| self.expr(e)),
+			Expr::Map(_, entries) => {
+				for (k, v) in entries {
+					self.expr(k);
+					self.expr(v);
+				}
+			},
+			Expr::SetIndex(container, _, key, _, value) => {
+				self.expr(container);
+				self.expr(key);
+				self.expr(value);
+			},
+			Expr::Lambda(f) => self.stmts(&f.body),
+			Expr::Variable(_) | Expr::Constant(_) => ()
+		}
+	}
+
+	fn comparison(&mut self, l: &Expr, op: &Token, r: &Expr) {
+		if let (Some(left), Some(right)) = (kind(l), kind(r)) {
+			let comparison = matches!(op.toktype, TokenType::EqualEqual | TokenType::BangEqual | TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual);
+			if comparison && (left != right || left == "nil") {
+				self.report(Lint::MismatchedComparison, &format!("comparing {left} with {right} is a type error"), Some(op));
+			}
+		}
+	}
+}
+
+// Control never gets past it
+fn diverges(stmt: &Stmt) -> bool {
+	match stmt {
+		Stmt::Return(..) | Stmt::Throw(..) | Stmt::Break | Stmt::Continue => true,
+		Stmt::Block(stmts) => stmts.iter().any(diverges),
+		Stmt::If(..) => {
+			let (arms, otherwise) = stmt.arms();
+			arms.iter().all(|(_, _, then)| diverges(then)) && otherwise.is_some_and(diverges)
+		},
+		_ => false
+	}
+}
+
+// Known without running anything, lists, maps and functions are always truthy
+fn is_constant(expr: &Expr) -> bool {
+	match expr {
+		Expr::Constant(_) | Expr::List(_) | Expr::Map(..) | Expr::Lambda(_) => true,
+		Expr::Group(e) | Expr::Unary(_, e) => is_constant(e),
+		Expr::Binary(..) | Expr::Logical(..) => {
+			let (first, links) = expr.chain();
+			is_constant(first) && links.iter().all(|link| match link {
+				Expr::Binary(_, _, r) | Expr::Logical(_, _, r) => is_constant(r),
+				_ => false
+			})
+		},
+		_ => false
+	}
+}
+
+// The type of a literal written in the source
+fn kind(expr: &Expr) -> Option<&'static str> {
+	match expr {
+		Expr::Constant(Literal::Float(_)) => Some("number"),
+		Expr::Constant(Literal::String(_)) => Some("string"),
+		Expr::Constant(Literal::Bool(_)) => Some("bool"),
+		Expr::Constant(Literal::Nil) => Some("nil"),
+		Expr::List(_) => Some("list"),
+		Expr::Map(..) => Some("map"),
+		Expr::Lambda(_) => Some("function"),
+		Expr::Group(e) => kind(e),
+		_ => None
+	}
+}
+
+// Somewhere to point a warning at, not every node keeps a token
+fn stmt_token(stmt: &Stmt) -> Option<&Token> {
+	match stmt {
+		Stmt::Print(e) | Stmt::Expression(e) => expr_token(e),
+		Stmt::Return(keyword, _) | Stmt::If(keyword, ..) | Stmt::Try(keyword, ..) | Stmt::Throw(keyword, _) => Some(keyword),
+		Stmt::Variable(name, _, _) => Some(name),
+		Stmt::Function(f) => f.name.as_ref(),
+		Stmt::Block(stmts) => stmts.iter().find_map(stmt_token),
+		Stmt::While(condition, _, _) => expr_token(condition),
+		Stmt::Import(keyword, ..) => Some(keyword),
+		Stmt::Export(decl) => stmt_token(decl),
+		Stmt::Break | Stmt::Continue => None
+	}
+}
+
+fn expr_token(expr: &Expr) -> Option<&Token> {
+	match expr {
+		Expr::Variable(t) | Expr::Assign(t, _) | Expr::Map(t, _) => Some(t),
+		Expr::Binary(..) | Expr::Logical(..) | Expr::Index(..) | Expr::Call(..) => {
+			// the innermost operand, or the first operator after it
+			let (first, links) = expr.chain();
+			expr_token(first).or(match links.first() {
+				Some(Expr::Binary(_, t, _) | Expr::Logical(_, t, _) | Expr::Index(_, t, _) | Expr::Call(_, t, _)) => Some(t),
+				_ => None
+			})
+		},
+		Expr::SetIndex(l, t, ..) => expr_token(l).or(Some(t)),
+		Expr::Unary(t, _) | Expr::Postfix(t, _) => Some(t),
+		Expr::Group(e) => expr_token(e),
+		Expr::List(items) => items.iter().find_map(expr_token),
+		Expr::Lambda(f) => f.params.first().or_else(|| f.body.iter().find_map(stmt_token)),
+		Expr::Constant(_) => None
+	}
+}
@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::io::Write;
+
+use super::builtins;
+use super::error::Error;
+use super::json::Json;
+use super::lexer::Lexer;
+use super::lint;
+use super::lint::Lints;
+use super::parse::Parser;
+use super::resolve;
+use super::resolve::DeclKind;
+use super::resolve::Resolution;
+use super::token::Literal;
+use super::token::Token;
+use super::token::TokenType;
+
+// Language server over stdio. Documents are synced whole and analysed again on every
+// change, lll files are small enough that nothing incremental is needed.
+pub fn serve(input: &mut impl BufRead, output: &mut impl Write) -> bool {
+	let mut server = Server { documents: HashMap::new(), shutdown: false, output };
+	while let Some(message) = read_message(input) {
+		let message = match message {
+			Ok(v) => v,
+			Err(e) => {
+				server.send(Json::object(vec![("jsonrpc", "2.0".into()), ("id", Json::Null), ("error", error(-32700, &e))]));
+				continue;
+			}
+		};
+		if message.get("method").and_then(Json::as_str) == Some("exit") {
+			return server.shutdown;
+		}
+		server.handle(&message);
+	}
+
+	false
+}
+
+// `Content-Length` framed, the other headers are ignored
+fn read_message(input: &mut impl BufRead) -> Option<Result<Json, String>> {
+	let mut length = None;
+	loop {
+		let mut line = String::new();
+		if input.read_line(&mut line).ok()? == 0 {
+			return None;
+		}
+		let line = line.trim_end();
+		if line.is_empty() {
+			break;
+		}
+		if let Some((name, value)) = line.split_once(':') {
+			if name.eq_ignore_ascii_case("content-length") {
+				length = value.trim().parse::<usize>().ok();
+			}
+		}
+	}
+
+	let mut body = vec![0; length?];
+	input.read_exact(&mut body).ok()?;
+	Some(String::from_utf8(body).map_err(|_| "not valid utf-8".to_string()).and_then(|text| Json::parse(&text)))
+}
+
+fn error(code: i32, msg: &str) -> Json {
+	Json::object(vec![("code", Json::Number(code as f64)), ("message", msg.into())])
+}
+
+const KEYWORDS: &[TokenType] = {
+	use TokenType::*;
+	&[And, As, Break, Catch, Continue, Else, Export, False, Finally, For, Fun, If, Import, New, Nil, Or, Print, Return, Throw, True, Try, While]
+};
+
+struct Server<'a, W: Write> {
+	documents: HashMap<String, String>,
+	shutdown: bool,
+	output: &'a mut W
+}
+
+impl<W: Write> Server<'_, W> {
+	fn send(&mut self, message: Json) {
+		let body = message.to_string();
+		let _ = write!(self.output, "Content-Length: {}\r\n\r\n{body}", body.len());
+		let _ = self.output.flush();
+	}
+
+	fn handle(&mut self, message: &Json) {
+		let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+		let params = message.get("params").unwrap_or(&Json::Null);
+		let Some(id) = message.get("id").cloned() else {
+			return self.notification(method, params);
+		};
+
+		let result = if self.shutdown {
+			Err(error(-32600, "the server is shutting down"))
+		} else {
+			self.request(method, params)
+		};
+		let reply = match result {
+			Ok(v) => ("result", v),
+			Err(e) => ("error", e)
+		};
+		self.send(Json::object(vec![("jsonrpc", "2.0".into()), ("id", id), reply]));
+	}
+
+	fn request(&mut self, method: &str, params: &Json) -> Result<Json, Json> {
+		let document = params.at(&["textDocument", "uri"]).and_then(Json::as_str)
+			.and_then(|uri| Some((uri, self.documents.get(uri)?)));
+		let place = |text: &str| {
+			let line = params.at(&["position", "line"]).and_then(Json::as_f64).unwrap_or(0.0) as usize;
+			let character = params.at(&["position", "character"]).and_then(Json::as_f64).unwrap_or(0.0) as usize;
+			offset(text, line, character)
+		};
+
+		match (method, document) {
+			("initialize", _) => Ok(Json::object(vec![
+				("capabilities", Json::object(vec![
+					("textDocumentSync", 1usize.into()),
+					("definitionProvider", true.into()),
+					("hoverProvider", true.into()),
+					("documentSymbolProvider", true.into()),
+					("completionProvider", Json::object(vec![]))
+				])),
+				("serverInfo", Json::object(vec![("name", "lll".into())]))
+			])),
+			("shutdown", _) => {
+				self.shutdown = true;
+				Ok(Json::Null)
+			},
+			("textDocument/definition", Some((uri, text))) => {
+				let analysis = Analysis::new(text);
+				Ok(analysis.declaration_at(place(text)).map_or(Json::Null, |i| analysis.location(uri, &analysis.resolution.declarations[i].name)))
+			},
+			("textDocument/hover", Some((_, text))) => Ok(Analysis::new(text).hover(place(text))),
+			("textDocument/documentSymbol", Some((uri, text))) => Ok(Analysis::new(text).symbols(uri)),
+			("textDocument/completion", Some((_, text))) => Ok(Analysis::new(text).completions()),
+			("textDocument/definition" | "textDocument/hover" | "textDocument/documentSymbol" | "textDocument/completion", None) => Ok(Json::Null),
+			_ => Err(error(-32601, &format!("unknown method {method}")))
+		}
+	}
+
+	fn notification(&mut self, method: &str, params: &Json) {
+		let Some(uri) = params.at(&["textDocument", "uri"]).and_then(Json::as_str) else {
+			return;
+		};
+		let uri = uri.to_string();
+		match method {
+			"textDocument/didOpen" => {
+				let text = params.at(&["textDocument", "text"]).and_then(Json::as_str).unwrap_or("");
+				self.documents.insert(uri.clone(), text.to_string());
+			},
+			"textDocument/didChange" => {
+				// full sync, the last change holds the whole text
+				let changes = params.get("contentChanges").and_then(Json::as_array).unwrap_or(&[]);
+				if let Some(text) = changes.last().and_then(|c| c.get("text")).and_then(Json::as_str) {
+					self.documents.insert(uri.clone(), text.to_string());
+				}
+			},
+			"textDocument/didClose" => {
+				self.documents.remove(&uri);
+			},
+			_ => return
+		}
+
+		let diagnostics = self.documents.get(&uri).map_or(Vec::new(), |text| Analysis::new(text).diagnostics());
+		self.send(Json::object(vec![
+			("jsonrpc", "2.0".into()),
+			("method", "textDocument/publishDiagnostics".into()),
+			("params", Json::object(vec![("uri", uri.into()), ("diagnostics", Json::Array(diagnostics))]))
+		]));
+	}
+}
+
+struct Analysis<'a> {
+	text: &'a str,
+	errors: Vec<Error>,
+	resolution: Resolution
+}
+
+impl<'a> Analysis<'a> {
+	fn new(text: &'a str) -> Self {
+		let (mut tokens, mut errors) = Lexer::new(text.to_string()).scan_with_errors();
+		let line = tokens.last().map_or(0, |t| t.line);
+		tokens.push(Token { literal: Literal::Nil, toktype: TokenType::Eof, place: text.len(), line });
+
+		let (stmts, parse_errors) = Parser::new(tokens).parse_with_errors();
+		errors.extend(parse_errors);
+
+		let mut resolution = resolve::resolve(&stmts);
+		// names declared in code that didn't parse would all show up as undefined
+		if errors.is_empty() {
+			errors.append(&mut resolution.errors);
+			errors.extend(lint::lint(&stmts, &Lints::default()));
+		}
+
+		Self { text, errors, resolution }
+	}
+
+	fn range(&self, start: usize, end: usize) -> Json {
+		Json::object(vec![("start", position(self.text, start)), ("end", position(self.text, end))])
+	}
+
+	fn location(&self, uri: &str, t: &Token) -> Json {
+		Json::object(vec![("uri", uri.into()), ("range", self.range(t.place, token_end(self.text, t)))])
+	}
+
+	fn diagnostics(&self) -> Vec<Json> {
+		self.errors.iter().map(|e| {
+			let start = e.place().unwrap_or(0).min(self.text.len());
+			let end = match e.token() {
+				Some(t) => token_end(self.text, t),
+				None => start + self.text[start..].chars().next().map_or(0, char::len_utf8)
+			};
+			Json::object(vec![
+				("range", self.range(start, end)),
+				("severity", Json::Number(if e.is_warning() { 2.0 } else { 1.0 })),
+				("source", "lll".into()),
+				("message", e.message().into())
+			])
+		}).collect()
+	}
+
+	// The name under the cursor, and the declaration it refers to if any
+	fn name_at(&self, place: usize) -> Option<(&Token, Option<usize>)> {
+		let contains = |t: &Token| t.place <= place && place <= token_end(self.text, t);
+		let declared = self.resolution.declarations.iter().enumerate()
+			.find(|(_, d)| contains(&d.name))
+			.map(|(i, d)| (&d.name, Some(i)));
+		declared.or_else(|| self.resolution.uses.iter().find(|u| contains(&u.name)).map(|u| (&u.name, u.declaration)))
+	}
+
+	fn declaration_at(&self, place: usize) -> Option<usize> {
+		self.name_at(place)?.1
+	}
+
+	fn hover(&self, place: usize) -> Json {
+		let Some((t, declaration)) = self.name_at(place) else {
+			return Json::Null;
+		};
+		let name = name(t);
+		let (signature, doc) = match declaration.map(|i| &self.resolution.declarations[i]) {
+			Some(d) => match d.kind {
+				DeclKind::Function => {
+					let params: Vec<_> = d.params.iter().map(|p| p.as_str()).collect();
+					(format!("fun {name}({})", params.join(", ")), d.doc.clone())
+				},
+				DeclKind::Variable => (format!("new {name}"), d.doc.clone()),
+				DeclKind::Parameter => (format!("(parameter) {name}"), None),
+				DeclKind::Import => (format!("(module) {name}"), None)
+			},
+			None => match builtins::natives().iter().find(|n| n.name == name) {
+				Some(native) => (format!("(native) {name}"), Some(format!("takes {} argument{}", native.arity, if native.arity == 1 { "" } else { "s" }))),
+				None => return Json::Null
+			}
+		};
+
+		let mut value = format!("```lll\n{signature}\n```");
+		if let Some(doc) = doc {
+			value = format!("{value}\n\n{doc}");
+		}
+		Json::object(vec![
+			("contents", Json::object(vec![("kind", "markdown".into()), ("value", value.into())])),
+			("range", self.range(t.place, token_end(self.text, t)))
+		])
+	}
+
+	fn symbols(&self, uri: &str) -> Json {
+		Json::Array(self.resolution.declarations.iter().filter(|d| d.kind != DeclKind::Parameter).map(|d| {
+			let kind = match d.kind {
+				DeclKind::Function => 12.0,
+				DeclKind::Import => 2.0,
+				_ => 13.0
+			};
+			Json::object(vec![("name", name(&d.name).into()), ("kind", Json::Number(kind)), ("location", self.location(uri, &d.name))])
+		}).collect())
+	}
+
+	fn completions(&self) -> Json {
+		let item = |label: &str, kind: f64| Json::object(vec![("label", label.into()), ("kind", Json::Number(kind))]);
+		let mut items: Vec<Json> = KEYWORDS.iter().map(|k| item(k.lexeme(), 14.0)).collect();
+		items.extend(builtins::natives().iter().map(|n| item(n.name, 3.0)));
+		items.extend(builtins::GLOBALS.iter().map(|g| item(g, 6.0)));
+
+		let mut seen = Vec::new();
+		for d in &self.resolution.declarations {
+			let name = name(&d.name);
+			if !seen.contains(&name) {
+				let kind = match d.kind {
+					DeclKind::Function => 3.0,
+					DeclKind::Import => 9.0,
+					_ => 6.0
+				};
+				items.push(item(&name, kind));
+				seen.push(name);
+			}
+		}
+		Json::Array(items)
+	}
+}
+
+fn name(t: &Token) -> String {
+	match &t.literal {
+		Literal::Identifier(name) => name.to_string(),
+		_ => String::new()
+	}
+}
+
+// Tokens only know where they start
+fn token_end(text: &str, t: &Token) -> usize {
+	let rest = text.get(t.place..).unwrap_or("");
+	let len = match t.toktype {
+		TokenType::Identifier => name(t).len(),
+		TokenType::String => rest[1..].find('"').map_or(rest.len(), |i| i + 2),
+		TokenType::Number => rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len()),
+		toktype => toktype.lexeme().len()
+	};
+	(t.place + len).min(text.len())
+}
+
+// LSP positions count lines and utf-16 code units
+fn position(text: &str, offset: usize) -> Json {
+	let before = &text[..offset];
+	let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+	let line = before.matches('\n').count();
+	let character = before[line_start..].encode_utf16().count();
+	Json::object(vec![("line", line.into()), ("character", character.into())])
+}
+
+fn offset(text: &str, line: usize, character: usize) -> usize {
+	let line_start = match line {
+		0 => 0,
+		_ => text.match_indices('\n').nth(line - 1).map_or(text.len(), |(i, _)| i + 1)
+	};
+	let mut units = 0;
+	for (i, c) in text[line_start..].char_indices() {
+		if units >= character || c == '\n' {
+			return line_start + i;
+		}
+		units += c.len_utf16();
+	}
+	text.len()
+}
@@ -0,0 +1,75 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use super::error::Error;
+
+pub const FILE: &str = "lll.toml";
+
+// `lll.toml`, the root of a project. Only a small piece of toml is understood:
+//
+//     # comment
+//     entry = "src/main.lll"
+//     roots = ["src", "lib"]
+//
+// Paths are relative to the directory of the manifest.
+#[derive(Debug)]
+pub struct Manifest {
+	pub entry: PathBuf,
+	// searched for imports, after the importing file's own directory
+	pub roots: Vec<PathBuf>
+}
+
+impl Manifest {
+	// The manifest in `dir` or the closest parent that has one
+	pub fn find(dir: &Path) -> Option<PathBuf> {
+		dir.ancestors().map(|dir| dir.join(FILE)).find(|path| path.is_file())
+	}
+
+	pub fn load(path: &Path) -> Result<Manifest, Error> {
+		let Ok(text) = std::fs::read_to_string(path) else {
+			return Err(Error::fatal(format!("cannot read {}", path.display()).as_str(), None));
+		};
+		let dir = path.parent().unwrap_or(Path::new(""));
+		Manifest::parse(&text, dir).map_err(|(line, msg)| {
+			let line = line.map_or(String::new(), |line| format!(":{line}"));
+			Error::fatal(format!("{msg} in {}{line}", path.display()).as_str(), None)
+		})
+	}
+
+	// Errors come with the line they are on, if there is one
+	pub fn parse(text: &str, dir: &Path) -> Result<Manifest, (Option<usize>, String)> {
+		let mut entry = None;
+		let mut roots = Vec::new();
+
+		for (i, line) in text.lines().enumerate() {
+			let line = line.split('#').next().unwrap_or("").trim();
+			if line.is_empty() {
+				continue;
+			}
+			let error = |msg: &str| (Some(i + 1), msg.to_string());
+			let Some((key, value)) = line.split_once('=') else {
+				return Err(error("expected key = value"));
+			};
+			match key.trim() {
+				"entry" => entry = Some(dir.join(string(value.trim()).ok_or(error("entry must be a string"))?)),
+				"roots" => {
+					let value = value.trim();
+					let items = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')).ok_or(error("roots must be a list of strings"))?;
+					for item in items.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+						roots.push(dir.join(string(item).ok_or(error("roots must be a list of strings"))?));
+					}
+				},
+				key => return Err(error(&format!("unknown key {key}")))
+			}
+		}
+
+		match entry {
+			Some(entry) => Ok(Manifest { entry, roots }),
+			None => Err((None, "missing entry".to_string()))
+		}
+	}
+}
+
+fn string(value: &str) -> Option<&str> {
+	value.strip_prefix('"')?.strip_suffix('"')
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use super::token;
+use super::token::Literal;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+	String(String),
+	Number(u64)
+}
+
+impl Key {
+	fn new(v: &Literal) -> Option<Self> {
+		match v {
+			Literal::String(s) => Some(Key::String(s.clone())),
+			// 0.0 and -0.0 are the same key
+			Literal::Float(f) if *f == 0.0 => Some(Key::Number(0.0f64.to_bits())),
+			Literal::Float(f) => Some(Key::Number(f.to_bits())),
+			_ => None
+		}
+	}
+}
+
+// Hash map that remembers insertion order, keys are strings or numbers
+#[derive(Debug, Clone, Default)]
+pub struct Map {
+	index: HashMap<Key, usize>,
+	entries: Vec<(Literal, Literal)>
+}
+
+impl Map {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	pub fn get(&self, key: &Literal) -> Option<&Literal> {
+		let i = self.index.get(&Key::new(key)?)?;
+		Some(&self.entries[*i].1)
+	}
+
+	// Returns false when the key is not a string or a number
+	pub fn insert(&mut self, key: Literal, val: Literal) -> bool {
+		let Some(k) = Key::new(&key) else {
+			return false;
+		};
+
+		match self.index.get(&k) {
+			Some(i) => self.entries[*i].1 = val,
+			None => {
+				self.index.insert(k, self.entries.len());
+				self.entries.push((key, val));
+			}
+		}
+		true
+	}
+
+	pub fn remove(&mut self, key: &Literal) -> Option<Literal> {
+		let i = self.index.remove(&Key::new(key)?)?;
+		let (_, val) = self.entries.remove(i);
+		for pos in self.index.values_mut() {
+			if *pos > i {
+				*pos -= 1;
+			}
+		}
+		Some(val)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &(Literal, Literal)> {
+		self.entries.iter()
+	}
+
+	// Empties the map, keys are only strings and numbers so the values are all it held
+	pub fn take_values(&mut self) -> Vec<Literal> {
+		self.index.clear();
+		std::mem::take(&mut self.entries).into_iter().map(|(_, v)| v).collect()
+	}
+}
+
+impl Drop for Map {
+	fn drop(&mut self) {
+		token::drop_values(self.take_values());
+	}
+}
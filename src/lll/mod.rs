@@ -3,3 +3,22 @@ pub mod lexer;
 pub mod token;
 pub mod parse;
 pub mod error;
+pub mod builtins;
+pub mod map;
+pub mod chunk;
+pub mod compiler;
+pub mod vm;
+pub mod debug;
+pub mod gc;
+pub mod symbol;
+pub mod cst;
+pub mod fmt;
+pub mod json;
+pub mod resolve;
+pub mod lsp;
+pub mod lint;
+pub mod module;
+pub mod manifest;
+pub mod budget;
+pub mod capability;
+pub mod fs;
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use super::builtins;
+use super::builtins::Native;
+use super::capability::Capabilities;
+use super::capability::Capability;
+use super::error::Error;
+use super::error::ErrorKind;
+use super::fs;
+use super::lexer::Lexer;
+use super::map::Map;
+use super::parse::Parser;
+use super::parse::Stmt;
+use super::symbol::Symbol;
+use super::token::Literal;
+use super::token::Token;
+use super::token::TokenType;
+
+// Every module of a run, shared by the interpreters that run them. A module runs once,
+// later imports get the same exports map.
+pub struct Modules {
+	cache: HashMap<PathBuf, Literal>,
+	// the import chain being run right now, as written, to report cycles
+	loading: Vec<(PathBuf, String)>,
+	// library directories, tried in order after the importing file's own directory
+	search: Vec<PathBuf>
+}
+
+pub type ModulesRef = Rc<RefCell<Modules>>;
+
+impl Modules {
+	pub fn new(root: Option<&Path>, search: Vec<PathBuf>) -> ModulesRef {
+		let loading = root.map(|path| (canonical(path), path.display().to_string())).into_iter().collect();
+		Rc::new(RefCell::new(Self { cache: HashMap::new(), loading, search }))
+	}
+
+	// Every place `name` could be, in the order they are tried
+	fn candidates(&self, from: Option<&Path>, name: &str) -> Vec<PathBuf> {
+		if Path::new(name).is_absolute() {
+			return vec![PathBuf::from(name)];
+		}
+		let dir = from.and_then(Path::parent).unwrap_or(Path::new(""));
+		std::iter::once(dir).chain(self.search.iter().map(PathBuf::as_path)).map(|dir| dir.join(name)).collect()
+	}
+}
+
+// Modules that come with lll, they win over files of the same name
+fn builtin(name: &str) -> Option<&'static [Native]> {
+	match name {
+		"fs" => Some(fs::natives()),
+		_ => None
+	}
+}
+
+fn canonical(path: &Path) -> PathBuf {
+	path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Names a module declares with `export`
+pub fn exports(stmts: &[Stmt]) -> Vec<Symbol> {
+	stmts.iter().filter_map(|stmt| {
+		let Stmt::Export(decl) = stmt else {
+			return None;
+		};
+		let name = match decl.as_ref() {
+			Stmt::Variable(name, ..) => name,
+			Stmt::Function(f) => f.name.as_ref()?,
+			_ => return None
+		};
+		match name.literal {
+			Literal::Identifier(name) => Some(name),
+			_ => None
+		}
+	}).collect()
+}
+
+// Finds, parses and runs a module, then hands back its exports as a map. `from` is the
+// importing file, paths are relative to it and then to the search path. `run` executes the module on a fresh global
+// environment and returns its globals. Reading a file needs `FsRead`, the built-in modules don't.
+pub fn load(
+	modules: &ModulesRef,
+	from: Option<&Path>,
+	capabilities: Capabilities,
+	t: &Token,
+	name: &str,
+	run: impl FnOnce(PathBuf, &[Stmt]) -> Result<HashMap<Symbol, Literal>, Error>
+) -> Result<Literal, Error> {
+	if let Some(natives) = builtin(name) {
+		if let Some(exports) = modules.borrow().cache.get(Path::new(name)) {
+			return Ok(exports.clone());
+		}
+		let mut map = Map::new();
+		for native in natives {
+			map.insert(Literal::String(native.name.to_string()), Literal::Native(native));
+		}
+		let exports = builtins::new_map(map);
+		// files are cached by their canonical path, which is absolute, so the bare name can't clash
+		modules.borrow_mut().cache.insert(PathBuf::from(name), exports.clone());
+		return Ok(exports);
+	}
+
+	if !capabilities.allows(Capability::FsRead) {
+		let msg = format!("import of {name} needs the {} capability, which is denied", Capability::FsRead.name());
+		return Err(Error::fatal(msg.as_str(), Some(t)).of(ErrorKind::Denied));
+	}
+
+	let candidates = modules.borrow().candidates(from, name);
+	let Some(path) = candidates.iter().find(|path| path.is_file()) else {
+		let tried: Vec<_> = candidates.iter().map(|path| path.display().to_string()).collect();
+		return Err(Error::fatal(format!("cannot find module {name}, tried {}", tried.join(", ")).as_str(), Some(t)).of(ErrorKind::Import));
+	};
+	let path = canonical(path);
+
+	if let Some(exports) = modules.borrow().cache.get(&path) {
+		return Ok(exports.clone());
+	}
+	if let Some(start) = modules.borrow().loading.iter().position(|(p, _)| *p == path) {
+		let chain: Vec<_> = modules.borrow().loading[start..].iter().map(|(_, shown)| shown.clone()).collect();
+		return Err(Error::fatal(format!("import cycle: {} -> {name}", chain.join(" -> ")).as_str(), Some(t)).of(ErrorKind::Import));
+	}
+
+	let Ok(source) = std::fs::read_to_string(&path) else {
+		return Err(Error::fatal(format!("cannot read module {name}").as_str(), Some(t)).of(ErrorKind::Import));
+	};
+	let stmts = parse(&source).map_err(|e| inside(e, name, t))?;
+
+	modules.borrow_mut().loading.push((path.clone(), name.to_string()));
+	let globals = run(path.clone(), &stmts);
+	modules.borrow_mut().loading.pop();
+	let globals = globals.map_err(|e| inside(e, name, t))?;
+
+	let mut map = Map::new();
+	for name in exports(&stmts) {
+		if let Some(v) = globals.get(&name) {
+			map.insert(Literal::String(name.to_string()), v.clone());
+		}
+	}
+	let exports = builtins::new_map(map);
+	modules.borrow_mut().cache.insert(path, exports.clone());
+	Ok(exports)
+}
+
+fn parse(source: &str) -> Result<Vec<Stmt>, Error> {
+	let (mut tokens, errors) = Lexer::new(source.to_string()).scan_with_errors();
+	if let Some(e) = errors.into_iter().next() {
+		return Err(e);
+	}
+
+	let line = tokens.last().map_or(0, |t| t.line);
+	tokens.push(Token { literal: Literal::Nil, toktype: TokenType::Eof, place: source.len(), line });
+	let (stmts, errors) = Parser::new(tokens).parse_with_errors();
+	match errors.into_iter().next() {
+		Some(e) => Err(e),
+		None => Ok(stmts)
+	}
+}
+
+// An error from inside a module, reported at the import that ran it. One that ends the
+// run goes on as it is.
+fn inside(e: Error, name: &str, t: &Token) -> Error {
+	if !e.kind().catchable() {
+		return e;
+	}
+	let line = e.token().map_or(String::new(), |t| format!(":{}", t.line + 1));
+	Error::fatal(format!("{} in {name}{line}", e.message()).as_str(), Some(t)).of(ErrorKind::Import)
+}
+
+// `import "m.lll" as m;` binds the exports map, a bare import defines each export
+pub fn bind(exports: Literal, alias: &Option<Token>, mut define: impl FnMut(Symbol, Literal)) {
+	match alias {
+		Some(Token { literal: Literal::Identifier(name), .. }) => define(*name, exports),
+		_ => {
+			let Literal::Map(map) = exports else {
+				return;
+			};
+			for (k, v) in map.borrow().iter() {
+				define(Symbol::intern(&k.to_string()), v.clone());
+			}
+		}
+	}
+}
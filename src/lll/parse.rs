@@ -1,63 +1,269 @@
+use std::rc::Rc;
+
 use super::token::Token;
 use super::token::TokenType;
 use super::token::Literal;
 use super::error::Error;
 
-pub enum Stmt { // Print, Variable, Expression
+pub enum Stmt { // Print, Variable, Function, Expression, Block, If, While, Break, Continue, Return, Import, Export, Try, Throw
 	Print(Expr),
-	Variable(Token, Expr),
+	Variable(Token, Expr, Option<String>), // name, initializer, doc comment
+	Function(Rc<Function>),
 	Expression(Expr),
 	Block(Vec<Stmt>),
-	If(Expr, Box<Stmt>, Option<Box<Stmt>>)
+	If(Token, Expr, Box<Stmt>, Option<Box<Stmt>>), // keyword, condition, then, else
+	While(Expr, Box<Stmt>, Option<Expr>), // condition, body, increment of a desugared `for`
+	Break,
+	Continue,
+	Return(Token, Expr), // keyword, or the arrow of a lambda
+	Import(Token, String, Option<Token>), // keyword, path as written, name after `as`
+	Export(Box<Stmt>), // a `new` or `fun` declaration
+	// keyword, body, catch variable and body, finally body, shared since the vm compiles it at every exit
+	Try(Token, Vec<Stmt>, Option<(Token, Vec<Stmt>)>, Option<Rc<Vec<Stmt>>>),
+	Throw(Token, Expr) // keyword, thrown value
+}
+
+// Shared between the declaration and every closure made from it
+pub struct Function {
+	pub name: Option<Token>,
+	pub doc: Option<String>,
+	pub params: Vec<Token>,
+	pub body: Vec<Stmt>
 }
 
-pub enum Expr { // Binary, Group, Unary, Variable, Constant, Assign
+pub enum Expr { // Binary, Group, Unary, Variable, Constant, Assign, Postfix, List, Map, Index, SetIndex, Call, Lambda
 	Binary(Box<Expr>, Token, Box<Expr>),
 	Logical(Box<Expr>, Token, Box<Expr>),
 	Unary(Token, Box<Expr>),
 	Group(Box<Expr>),
 	Variable(Token),
 	Assign(Token, Box<Expr>),
+	Postfix(Token, Box<Expr>),
+	List(Vec<Expr>),
+	Map(Token, Vec<(Expr, Expr)>),
+	Index(Box<Expr>, Token, Box<Expr>),
+	SetIndex(Box<Expr>, Token, Box<Expr>, Option<Token>, Box<Expr>), // container, bracket, key, compound operator, value
+	Call(Box<Expr>, Token, Vec<Expr>),
+	Lambda(Rc<Function>),
 	Constant(Literal)
 }
 
+impl Stmt {
+	// An `if` with its `else if`s as one list of arms, and the `else` after all of them.
+	// The chain is as long as the code is, so it's walked in a loop.
+	pub fn arms(&self) -> (Vec<(&Token, &Expr, &Stmt)>, Option<&Stmt>) {
+		let mut arms = Vec::new();
+		let mut stmt = self;
+		while let Stmt::If(keyword, condition, then, after) = stmt {
+			arms.push((keyword, condition, then.as_ref()));
+			match after {
+				Some(after) => stmt = after,
+				None => return (arms, None)
+			}
+		}
+		(arms, Some(stmt))
+	}
+}
+
+impl Expr {
+	// `a + b - c` and `f(x)[0].y` nest to the left as deep as they are long. The innermost
+	// operand, and the links around it from the inside out, for walking one in a loop.
+	pub fn chain(&self) -> (&Expr, Vec<&Expr>) {
+		let mut links = Vec::new();
+		let mut expr = self;
+		while let Expr::Binary(inner, ..) | Expr::Logical(inner, ..) | Expr::Index(inner, ..) | Expr::Call(inner, ..) = expr {
+			links.push(expr);
+			expr = inner;
+		}
+		links.reverse();
+		(expr, links)
+	}
+}
+
+// Dropping a long chain would go one call deeper for every link, so they are taken apart in
+// a loop, and every link dropped once nothing hangs off its left side anymore
+impl Drop for Stmt {
+	fn drop(&mut self) {
+		let Stmt::If(_, _, _, after) = self else { return };
+		let mut next = after.take();
+		while let Some(mut stmt) = next {
+			next = match stmt.as_mut() {
+				Stmt::If(_, _, _, after) => after.take(),
+				_ => None
+			};
+		}
+	}
+}
+
+impl Drop for Expr {
+	fn drop(&mut self) {
+		let left = |expr: &mut Expr| match expr {
+			Expr::Binary(l, ..) | Expr::Logical(l, ..) | Expr::Index(l, ..) | Expr::Call(l, ..) => Some(*take(l)),
+			_ => None
+		};
+		let mut next = left(self);
+		while let Some(mut expr) = next {
+			next = left(&mut expr);
+		}
+	}
+}
+
+// An operand moved out of an expression, which can't be destructured since it has a `Drop`
+fn take(expr: &mut Box<Expr>) -> Box<Expr> {
+	std::mem::replace(expr, Box::new(Expr::Constant(Literal::Nil)))
+}
+
 pub struct Parser {
 	tokens: Vec<Token>,
 	current: usize,
+	loops: usize,
+	functions: usize,
+	// statements and expressions inside each other right now
+	nesting: usize
 }
 
+// Everything that walks the tree recurses as deep as it is nested, deeper than this is an error
+pub const MAX_NESTING: usize = 256;
+
 type ResExpr = Result<Expr, Error>;
 type ResStmt = Result<Stmt, Error>;
 impl Parser {
 	pub fn new(tokens: Vec<Token>) -> Self {
-		Self { tokens, current: 0 }
+		// doc comments only mean something right before a declaration, anywhere else they are dropped
+		let documents = |mut i: usize| {
+			if tokens.get(i + 1).is_some_and(|t| t.toktype == TokenType::Export) {
+				i += 1;
+			}
+			match tokens.get(i + 1).map(|t| t.toktype) {
+				Some(TokenType::New | TokenType::Class) => true,
+				Some(TokenType::Fun) => tokens.get(i + 2).is_some_and(|t| t.toktype == TokenType::Identifier),
+				_ => false
+			}
+		};
+		let keep: Vec<bool> = (0..tokens.len()).map(|i| tokens[i].toktype != TokenType::Doc || documents(i)).collect();
+		let tokens = tokens.into_iter().zip(keep).filter_map(|(t, keep)| keep.then_some(t)).collect();
+
+		Self { tokens, current: 0, loops: 0, functions: 0, nesting: 0 }
 	}
 
 	pub fn parse(&mut self) -> Option<Vec<Stmt>> {
-		
+		let (stmts, errors) = self.parse_with_errors();
+		for v in errors {
+			println!("{v}");
+		}
+
+		Some(stmts)
+	}
+
+	// Same as `parse`, but the errors are handed back instead of printed
+	pub fn parse_with_errors(&mut self) -> (Vec<Stmt>, Vec<Error>) {
 		let mut stmts: Vec<Stmt> = Vec::new();
+		let mut errors = Vec::new();
 		while !self.is_at_end() {
-			match self.declaration() {
+			match self.top_level() {
 				Ok(v) => stmts.push(v),
 				Err(v) => {
-					println!("{v}");
+					errors.push(v);
 					self.synchronize();
 				}
 			}
 		}
 
-		Some(stmts)
+		(stmts, errors)
+	}
+
+	// Imports and exports only make sense for the module as a whole
+	fn top_level(&mut self) -> ResStmt {
+		if self.select(&[TokenType::Import]) {
+			return self.import_statement();
+		}
+
+		let doc = self.doc_comment();
+		if !self.select(&[TokenType::Export]) {
+			return self.documented(doc);
+		}
+
+		let export = self.tokens[self.current - 1].clone();
+		let named_fun = self.tokens[self.current].toktype == TokenType::Fun && self.tokens[self.current + 1].toktype == TokenType::Identifier;
+		if self.tokens[self.current].toktype != TokenType::New && !named_fun {
+			return Err(Error::fatal("only new and fun declarations can be exported", Some(&export)));
+		}
+		Ok(Stmt::Export(Box::new(self.documented(doc)?)))
+	}
+
+	fn import_statement(&mut self) -> ResStmt {
+		let keyword = self.tokens[self.current - 1].clone();
+		let path = match self.consume(TokenType::String)?.literal {
+			Literal::String(path) => path,
+			_ => String::new()
+		};
+
+		let mut alias = None;
+		if self.select(&[TokenType::As]) {
+			alias = Some(self.consume(TokenType::Identifier)?);
+		}
+		self.consume(TokenType::Semicolon)?;
+
+		Ok(Stmt::Import(keyword, path, alias))
+	}
+
+	fn doc_comment(&mut self) -> Option<String> {
+		let Token { toktype: TokenType::Doc, literal: Literal::String(text), .. } = &self.tokens[self.current] else {
+			return None;
+		};
+		let doc = text.clone();
+		self.current += 1;
+		Some(doc)
 	}
 
 	fn declaration(&mut self) -> ResStmt {
+		let doc = self.doc_comment();
+		self.documented(doc)
+	}
+
+	fn documented(&mut self, doc: Option<String>) -> ResStmt {
 		if self.select(&[TokenType::New]) {
-			return self.var_declaration();
+			return self.var_declaration(doc);
+		} else if self.tokens[self.current].toktype == TokenType::Fun && self.tokens[self.current + 1].toktype == TokenType::Identifier {
+			// `fun (` without a name is a lambda and is left to the expression statement
+			self.current += 1;
+			let name = self.consume(TokenType::Identifier)?;
+			return Ok(Stmt::Function(Rc::new(self.function(Some(name), doc)?)));
 		}
 
 		self.statement()
 	}
 
-	fn var_declaration(&mut self) -> ResStmt {
+	fn function(&mut self, name: Option<Token>, doc: Option<String>) -> Result<Function, Error> {
+		self.consume(TokenType::LeftParen)?;
+		let mut params = Vec::new();
+		while !self.is_at_end() && self.tokens[self.current].toktype != TokenType::RightParen {
+			params.push(self.consume(TokenType::Identifier)?);
+			if !self.select(&[TokenType::Comma]) {
+				break;
+			}
+		}
+		self.consume(TokenType::RightParen)?;
+		self.consume(TokenType::LeftBrace)?;
+
+		let body = self.function_body(|parser| parser.block_statement())?;
+		Ok(Function { name, doc, params, body })
+	}
+
+	// Loops don't reach into function bodies, so `break` there is an error again
+	fn function_body(&mut self, parse: impl FnOnce(&mut Self) -> Result<Vec<Stmt>, Error>) -> Result<Vec<Stmt>, Error> {
+		let loops = self.loops;
+		self.loops = 0;
+		self.functions += 1;
+
+		let body = parse(self);
+
+		self.functions -= 1;
+		self.loops = loops;
+		body
+	}
+
+	fn var_declaration(&mut self, doc: Option<String>) -> ResStmt {
 		let name = self.consume(TokenType::Identifier);
 		
 		let mut init: ResExpr = Ok(Expr::Constant(Literal::Nil));
@@ -65,41 +271,185 @@ impl Parser {
 			init = self.expression();
 		}
 
+		let (name, init) = (name?, init?);
 		self.consume(TokenType::Semicolon)?;
-		Ok(Stmt::Variable(name?, init?))
+		Ok(Stmt::Variable(name, init, doc))
 	}
 	
 	fn statement(&mut self) -> ResStmt {
+		self.nested(Self::any_statement)
+	}
+
+	fn any_statement(&mut self) -> ResStmt {
 		if self.select(&[TokenType::Print]) {
 			return self.print_statement();
-		} if self.select(&[TokenType::If]) {
+		} else if self.select(&[TokenType::If]) {
 			return self.if_statement();
+		} else if self.select(&[TokenType::While]) {
+			return self.while_statement();
+		} else if self.select(&[TokenType::For]) {
+			return self.for_statement();
+		} else if self.select(&[TokenType::Break, TokenType::Continue]) {
+			return self.jump_statement();
+		} else if self.select(&[TokenType::Return]) {
+			return self.return_statement();
+		} else if self.select(&[TokenType::Try]) {
+			return self.try_statement();
+		} else if self.select(&[TokenType::Throw]) {
+			let keyword = self.tokens[self.current - 1].clone();
+			let value = self.expression()?;
+			self.consume(TokenType::Semicolon)?;
+			return Ok(Stmt::Throw(keyword, value));
 		} else if self.select(&[TokenType::LeftBrace]) {
 			return Ok(Stmt::Block(self.block_statement()?));
+		} else if self.select(&[TokenType::Import, TokenType::Export]) {
+			let keyword = &self.tokens[self.current - 1];
+			return Err(Error::fatal(format!("{} is only allowed at the top level", keyword.toktype.lexeme()).as_str(), Some(keyword)));
 		}
 
 		self.expression_statement()
 	}
 
-	fn if_statement(&mut self) -> ResStmt {
+	fn while_statement(&mut self) -> ResStmt {
 		self.consume(TokenType::LeftParen)?;
 		let condition = self.expression()?;
 		self.consume(TokenType::RightParen)?;
 
-		let then = Box::new(self.statement()?);
+		let body = self.loop_body()?;
+		Ok(Stmt::While(condition, Box::new(body), None))
+	}
+
+	// for (init; cond; incr) body => { init; while (cond) body, incr }
+	fn for_statement(&mut self) -> ResStmt {
+		self.consume(TokenType::LeftParen)?;
+
+		let init = if self.select(&[TokenType::Semicolon]) {
+			None
+		} else if self.select(&[TokenType::New]) {
+			Some(self.var_declaration(None)?)
+		} else {
+			Some(self.expression_statement()?)
+		};
+
+		let mut condition = Expr::Constant(Literal::Bool(true));
+		if self.tokens[self.current].toktype != TokenType::Semicolon {
+			condition = self.expression()?;
+		}
+		self.consume(TokenType::Semicolon)?;
+
+		let mut increment = None;
+		if self.tokens[self.current].toktype != TokenType::RightParen {
+			increment = Some(self.expression()?);
+		}
+		self.consume(TokenType::RightParen)?;
+
+		let body = self.loop_body()?;
+		let looped = Stmt::While(condition, Box::new(body), increment);
+
+		match init {
+			Some(init) => Ok(Stmt::Block(vec![init, looped])),
+			None => Ok(looped)
+		}
+	}
+
+	fn loop_body(&mut self) -> ResStmt {
+		self.loops += 1;
+		let body = self.statement();
+		self.loops -= 1;
+
+		body
+	}
+
+	fn jump_statement(&mut self) -> ResStmt {
+		let keyword = self.tokens[self.current - 1].clone();
+		if self.loops == 0 {
+			return Err(Error::fatal("break and continue are only allowed inside of a loop", Some(&keyword)));
+		}
+		self.consume(TokenType::Semicolon)?;
+
+		match keyword.toktype {
+			TokenType::Break => Ok(Stmt::Break),
+			_ => Ok(Stmt::Continue)
+		}
+	}
+
+	// try { } catch (e) { } finally { }, either of the last two can be left out but not both
+	fn try_statement(&mut self) -> ResStmt {
+		let keyword = self.tokens[self.current - 1].clone();
+		self.consume(TokenType::LeftBrace)?;
+		let body = self.block_statement()?;
+
+		let mut catch = None;
+		if self.select(&[TokenType::Catch]) {
+			self.consume(TokenType::LeftParen)?;
+			let name = self.consume(TokenType::Identifier)?;
+			self.consume(TokenType::RightParen)?;
+			self.consume(TokenType::LeftBrace)?;
+			catch = Some((name, self.block_statement()?));
+		}
+
+		let mut finally = None;
+		if self.select(&[TokenType::Finally]) {
+			self.consume(TokenType::LeftBrace)?;
+			finally = Some(Rc::new(self.block_statement()?));
+		}
+
+		if catch.is_none() && finally.is_none() {
+			return Err(Error::fatal("try needs a catch or a finally", Some(&keyword)));
+		}
+		Ok(Stmt::Try(keyword, body, catch, finally))
+	}
+
+	fn if_statement(&mut self) -> ResStmt {
+		let (keyword, condition, then) = self.if_arm()?;
+
+		// `else if` chains are flat code, not nesting, so they are read in a loop and put
+		// together from the last one
+		let mut arms = Vec::new();
 		let mut after = None;
-		if self.select(&[TokenType::Else]) {
-			after = Some(Box::new(self.statement()?));
+		while self.select(&[TokenType::Else]) {
+			if !self.select(&[TokenType::If]) {
+				after = Some(Box::new(self.statement()?));
+				break;
+			}
+			arms.push(self.if_arm()?);
+		}
+		for (keyword, condition, then) in arms.into_iter().rev() {
+			after = Some(Box::new(Stmt::If(keyword, condition, then, after)));
 		}
 
-		Ok(Stmt::If(condition, then, after))
+		Ok(Stmt::If(keyword, condition, then, after))
+	}
+
+	// `(condition) statement`, right after the `if`
+	fn if_arm(&mut self) -> Result<(Token, Expr, Box<Stmt>), Error> {
+		let keyword = self.tokens[self.current - 1].clone();
+		self.consume(TokenType::LeftParen)?;
+		let condition = self.expression()?;
+		self.consume(TokenType::RightParen)?;
+		Ok((keyword, condition, Box::new(self.statement()?)))
 	}
 	
+	fn return_statement(&mut self) -> ResStmt {
+		let keyword = self.tokens[self.current - 1].clone();
+		if self.functions == 0 {
+			return Err(Error::fatal("return is only allowed inside of a function", Some(&keyword)));
+		}
+
+		let mut value = Expr::Constant(Literal::Nil);
+		if self.tokens[self.current].toktype != TokenType::Semicolon {
+			value = self.expression()?;
+		}
+		self.consume(TokenType::Semicolon)?;
+
+		Ok(Stmt::Return(keyword, value))
+	}
+
 	fn print_statement(&mut self) -> ResStmt {
-		let expr = self.expression();
+		let expr = self.expression()?;
 		self.consume(TokenType::Semicolon)?;
 
-		Ok(Stmt::Print(expr?))
+		Ok(Stmt::Print(expr))
 	}
 
 	fn block_statement(&mut self) -> Result<Vec<Stmt>, Error> {
@@ -114,10 +464,10 @@ impl Parser {
 	}
 	
 	fn expression_statement(&mut self) -> ResStmt {
-		let expr = self.expression();
+		let expr = self.expression()?;
 		self.consume(TokenType::Semicolon)?;
 
-		Ok(Stmt::Expression(expr?))
+		Ok(Stmt::Expression(expr))
 	}
 
 	fn is_at_end(&self) -> bool {
@@ -135,7 +485,7 @@ impl Parser {
 			}
 
 			match self.tokens[self.current].toktype {
-				Class | Fun | New | For | If | While | Print | Return => return,
+				Class | Fun | New | For | If | While | Print | Return | Try | Throw => return,
 				_ => ()
 			}
 
@@ -144,17 +494,28 @@ impl Parser {
 	}
 	
 	fn expression(&mut self) -> ResExpr {
-		self.assignment()
+		self.nested(Self::assignment)
+	}
+
+	fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+		if self.nesting >= MAX_NESTING {
+			return Err(Error::fatal(format!("nested more than {MAX_NESTING} levels deep").as_str(), Some(&self.tokens[self.current])));
+		}
+
+		self.nesting += 1;
+		let res = parse(self);
+		self.nesting -= 1;
+		res
 	}
 
 	fn assignment(&mut self) -> ResExpr {
-		let expr = self.or()?;
+		let mut expr = self.or()?;
 
 		if self.select(&[TokenType::Equal]) {
 			let equals = self.tokens[self.current - 1].clone();
-			let value = self.assignment()?;
+			let value = self.expression()?;
 
-			match expr {
+			match &mut expr {
 				Expr::Variable(t) => {
 					match t.literal {
 						Literal::Identifier(_) => {
@@ -163,13 +524,44 @@ impl Parser {
 						_ => return Err(Error::fatal("invalid assignment target", Some(&equals)))
 					}
 				},
+				Expr::Index(list, t, index) => return Ok(Expr::SetIndex(take(list), t.clone(), take(index), None, Box::new(value))),
 				_ => return Err(Error::fatal("invalid assignment target", Some(&equals)))
 			}
+		} else if self.select(&[TokenType::PlusEqual, TokenType::MinusEqual, TokenType::StarEqual,
+								TokenType::SlashEqual, TokenType::PercentEqual]) {
+			let op = self.tokens[self.current - 1].clone();
+			let value = self.expression()?;
+			return self.compound(expr, op, value);
 		}
 
 		Ok(expr)
 	}
 
+	// `a += b` and `++a` are sugar for `a = a + b` and `a = a + 1`
+	fn compound(&self, mut target: Expr, op: Token, value: Expr) -> ResExpr {
+		use TokenType::*;
+
+		let mut binop = op.clone();
+		binop.toktype = match op.toktype {
+			PlusEqual | PlusPlus => Plus,
+			MinusEqual | MinusMinus => Minus,
+			StarEqual => Star,
+			SlashEqual => Slash,
+			PercentEqual => Percent,
+			_ => return Err(Error::fatal("unexpected compound operator", Some(&op)))
+		};
+
+		match &mut target {
+			Expr::Variable(t) => {
+				let current = Expr::Variable(t.clone());
+				Ok(Expr::Assign(t.clone(), Box::new(Expr::Binary(Box::new(current), binop, Box::new(value)))))
+			},
+			// the container and key must be evaluated only once, so this one can't be desugared
+			Expr::Index(list, t, index) => Ok(Expr::SetIndex(take(list), t.clone(), take(index), Some(binop), Box::new(value))),
+			_ => Err(Error::fatal("invalid assignment target", Some(&op)))
+		}
+	}
+
 	fn or(&mut self) -> ResExpr {
 		let mut expr = self.and();
 
@@ -187,7 +579,7 @@ impl Parser {
 
 		while self.select(&[TokenType::And]) {
 			let op = self.tokens[self.current - 1].clone();
-			let right = self.and();
+			let right = self.equality();
 			expr = Ok(Expr::Logical(Box::new(expr?), op, Box::new(right?)));
 		}
 
@@ -234,7 +626,7 @@ impl Parser {
 	fn factor(&mut self) -> ResExpr {
 		let mut expr1 = self.unary();
 
-		while self.select(&[TokenType::Slash, TokenType::Star]) {
+		while self.select(&[TokenType::Slash, TokenType::Star, TokenType::Percent]) {
 			let op = self.tokens[self.current - 1].clone();
 			let expr2 = self.unary();
 			expr1 = Ok(Expr::Binary(Box::new(expr1?), op, Box::new(expr2?)));
@@ -246,11 +638,71 @@ impl Parser {
 	fn unary(&mut self) -> ResExpr {
 		if self.select(&[TokenType::Minus, TokenType::Bang]) {
 			let op = self.tokens[self.current - 1].clone();
-			let expr = self.primary();
+			let expr = self.postfix();
 			return Ok(Expr::Unary(op, Box::new(expr?)))
+		} else if self.select(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+			let op = self.tokens[self.current - 1].clone();
+			let target = self.nested(Self::unary)?;
+			return self.compound(target, op, Expr::Constant(Literal::Float(1.0)));
 		}
 
-		self.primary()
+		self.postfix()
+	}
+
+	fn postfix(&mut self) -> ResExpr {
+		let expr = self.call()?;
+
+		if self.select(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+			let op = self.tokens[self.current - 1].clone();
+			return match expr {
+				Expr::Variable(_) | Expr::Index(..) => Ok(Expr::Postfix(op, Box::new(expr))),
+				_ => Err(Error::fatal("invalid increment target", Some(&op)))
+			}
+		}
+
+		Ok(expr)
+	}
+
+	fn call(&mut self) -> ResExpr {
+		let mut expr = self.primary()?;
+
+		loop {
+			if self.select(&[TokenType::LeftParen]) {
+				let paren = self.tokens[self.current - 1].clone();
+				let args = self.arguments(TokenType::RightParen)?;
+				expr = Expr::Call(Box::new(expr), paren, args);
+			} else if self.select(&[TokenType::LeftBracket]) {
+				let bracket = self.tokens[self.current - 1].clone();
+				let index = self.expression()?;
+				self.consume(TokenType::RightBracket)?;
+				expr = Expr::Index(Box::new(expr), bracket, Box::new(index));
+			} else if self.select(&[TokenType::Dot]) {
+				// `u.name` is `u["name"]`
+				let dot = self.tokens[self.current - 1].clone();
+				let name = self.consume(TokenType::Identifier)?;
+				let key = Expr::Constant(Literal::String(name.literal.to_string()));
+				expr = Expr::Index(Box::new(expr), dot, Box::new(key));
+			} else {
+				break;
+			}
+		}
+
+		Ok(expr)
+	}
+
+	// Comma separated expressions up to the closing token, trailing comma is allowed
+	fn arguments(&mut self, close: TokenType) -> Result<Vec<Expr>, Error> {
+		let mut args = Vec::new();
+
+		while !self.is_at_end() && self.tokens[self.current].toktype != close {
+			args.push(self.expression()?);
+			if !self.select(&[TokenType::Comma]) {
+				break;
+			}
+		}
+
+		self.consume(close)?;
+		Ok(args)
 	}
 
 	fn primary(&mut self) -> ResExpr {
@@ -258,15 +710,76 @@ impl Parser {
 			return Ok(Expr::Constant(self.tokens[self.current - 1].literal.clone()))
 		} else if self.select(&[TokenType::Identifier]) {
 			return Ok(Expr::Variable(self.tokens[self.current - 1].clone()));
+		} else if self.select(&[TokenType::Fun]) {
+			return Ok(Expr::Lambda(Rc::new(self.function(None, None)?)));
+		} else if self.tokens[self.current].toktype == TokenType::LeftParen && self.is_arrow() {
+			return self.arrow();
 		} else if self.select(&[TokenType::LeftParen]) {
-			let expr = self.expression();
+			let expr = self.expression()?;
 			self.consume(TokenType::RightParen)?;
-			return Ok(Expr::Group(Box::new(expr?)));
+			return Ok(Expr::Group(Box::new(expr)));
+		} else if self.select(&[TokenType::LeftBracket]) {
+			return Ok(Expr::List(self.arguments(TokenType::RightBracket)?));
+		} else if self.select(&[TokenType::LeftBrace]) {
+			// statements treat `{` as a block, so here it can only be a map
+			return self.map();
 		}
 
 		Err(Error::fatal("expected expression", Some(&self.tokens[self.current])))
 	}
 
+	// Looks past the parens for `=>` to tell `(a, b) => a + b` from a grouping
+	fn is_arrow(&self) -> bool {
+		let mut i = self.current + 1;
+		loop {
+			match self.tokens[i].toktype {
+				TokenType::RightParen => return self.tokens[i + 1].toktype == TokenType::Arrow,
+				TokenType::Identifier => {
+					i += 1;
+					match self.tokens[i].toktype {
+						TokenType::Comma => i += 1,
+						TokenType::RightParen => (),
+						_ => return false
+					}
+				},
+				_ => return false
+			}
+		}
+	}
+
+	// (a, b) => a + b is sugar for fun (a, b) { return a + b; }
+	fn arrow(&mut self) -> ResExpr {
+		self.consume(TokenType::LeftParen)?;
+		let mut params = Vec::new();
+		while self.tokens[self.current].toktype != TokenType::RightParen {
+			params.push(self.consume(TokenType::Identifier)?);
+			self.select(&[TokenType::Comma]);
+		}
+		self.consume(TokenType::RightParen)?;
+		let arrow = self.consume(TokenType::Arrow)?;
+
+		let body = self.function_body(|parser| Ok(vec![Stmt::Return(arrow, parser.expression()?)]))?;
+		Ok(Expr::Lambda(Rc::new(Function { name: None, doc: None, params, body })))
+	}
+
+	fn map(&mut self) -> ResExpr {
+		let brace = self.tokens[self.current - 1].clone();
+		let mut entries = Vec::new();
+
+		while !self.is_at_end() && self.tokens[self.current].toktype != TokenType::RightBrace {
+			let key = self.expression()?;
+			self.consume(TokenType::Colon)?;
+			let val = self.expression()?;
+			entries.push((key, val));
+			if !self.select(&[TokenType::Comma]) {
+				break;
+			}
+		}
+
+		self.consume(TokenType::RightBrace)?;
+		Ok(Expr::Map(brace, entries))
+	}
+
 	fn consume(&mut self, toktype: TokenType) -> Result<Token, Error> {
 		if !self.is_at_end() && self.tokens[self.current].toktype == toktype {
 			self.current += 1;
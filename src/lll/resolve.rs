@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use super::builtins;
+use super::error::Error;
+use super::parse::Expr;
+use super::parse::Function;
+use super::parse::Stmt;
+use super::symbol::Symbol;
+use super::token::Literal;
+use super::token::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeclKind {
+	Variable,
+	Function,
+	Parameter,
+	Import // the name after `as`
+}
+
+#[derive(Debug)]
+pub struct Declaration {
+	pub name: Token,
+	pub kind: DeclKind,
+	pub doc: Option<String>,
+	pub params: Vec<Symbol>,
+	// 0 for globals
+	pub depth: usize,
+	// a `new` in an inner scope hiding this declaration of an outer one
+	pub shadows: Option<usize>,
+	pub exported: bool
+}
+
+// A name read or assigned somewhere
+#[derive(Debug)]
+pub struct Use {
+	pub name: Token,
+	pub declaration: Option<usize>,
+	pub write: bool
+}
+
+// Every declaration in a program and what each use of a name refers to. A use that
+// points at no declaration is a native, or an error.
+#[derive(Debug, Default)]
+pub struct Resolution {
+	pub declarations: Vec<Declaration>,
+	pub uses: Vec<Use>,
+	pub errors: Vec<Error>
+}
+
+// Checks names without running anything. Globals may be used before they are
+// declared as long as it's inside a function, so the top level is declared up front.
+// What a bare `import` brings in is only known once it runs, so after one any unknown
+// name could be a global.
+pub fn resolve(stmts: &[Stmt]) -> Resolution {
+	let open = stmts.iter().any(|stmt| matches!(stmt, Stmt::Import(_, _, None)));
+	let mut resolver = Resolver { out: Resolution::default(), scopes: vec![HashMap::new()], globals: 0, open };
+	for stmt in stmts {
+		let (stmt, exported) = match stmt {
+			Stmt::Export(decl) => (decl.as_ref(), true),
+			stmt => (stmt, false)
+		};
+		match stmt {
+			Stmt::Variable(name, _, doc) => resolver.declare(name, DeclKind::Variable, doc.clone(), Vec::new()),
+			Stmt::Function(f) => resolver.function_name(f),
+			Stmt::Import(_, _, Some(alias)) => resolver.declare(alias, DeclKind::Import, None, Vec::new()),
+			_ => continue
+		}
+		resolver.out.declarations.last_mut().unwrap().exported = exported;
+	}
+	// a global declared twice means the first one until the second is reached
+	for stmt in stmts {
+		resolver.stmt(stmt, true);
+	}
+
+	resolver.out
+}
+
+fn symbol(t: &Token) -> Symbol {
+	match t.literal {
+		Literal::Identifier(name) => name,
+		_ => Symbol::intern("")
+	}
+}
+
+struct Resolver {
+	out: Resolution,
+	scopes: Vec<HashMap<Symbol, usize>>,
+	// top level declarations reached so far, they were all declared up front
+	globals: usize,
+	// a bare import could have defined any global
+	open: bool
+}
+
+impl Resolver {
+	fn declare(&mut self, name: &Token, kind: DeclKind, doc: Option<String>, params: Vec<Symbol>) {
+		let depth = self.scopes.len() - 1;
+		let shadows = match kind {
+			DeclKind::Variable if depth > 0 => self.scopes[..depth].iter().rev().find_map(|scope| scope.get(&symbol(name)).copied()),
+			_ => None
+		};
+		self.out.declarations.push(Declaration { name: name.clone(), kind, doc, params, depth, shadows, exported: false });
+		let index = self.out.declarations.len() - 1;
+		self.scopes.last_mut().unwrap().insert(symbol(name), index);
+	}
+
+	fn function_name(&mut self, f: &Function) {
+		if let Some(name) = &f.name {
+			let params = f.params.iter().map(symbol).collect();
+			self.declare(name, DeclKind::Function, f.doc.clone(), params);
+		}
+	}
+
+	fn reach_global(&mut self) {
+		let index = self.globals;
+		self.globals += 1;
+		let name = symbol(&self.out.declarations[index].name);
+		self.scopes[0].insert(name, index);
+	}
+
+	fn lookup(&mut self, name: &Token, write: bool) {
+		let sym = symbol(name);
+		let found = self.scopes.iter().rev().find_map(|scope| scope.get(&sym).copied());
+		if found.is_none() && !self.open && !builtins::is_predefined(&sym.as_str()) {
+			self.out.errors.push(Error::fatal(format!("undefined variable {sym}").as_str(), Some(name)));
+		}
+		self.out.uses.push(Use { name: name.clone(), declaration: found, write });
+	}
+
+	fn block(&mut self, stmts: &[Stmt]) {
+		self.scopes.push(HashMap::new());
+		for stmt in stmts {
+			self.stmt(stmt, false);
+		}
+		self.scopes.pop();
+	}
+
+	fn function(&mut self, f: &Function) {
+		self.scopes.push(HashMap::new());
+		for param in &f.params {
+			self.declare(param, DeclKind::Parameter, None, Vec::new());
+		}
+		for stmt in &f.body {
+			self.stmt(stmt, false);
+		}
+		self.scopes.pop();
+	}
+
+	fn stmt(&mut self, stmt: &Stmt, top: bool) {
+		match stmt {
+			Stmt::Print(e) | Stmt::Expression(e) | Stmt::Return(_, e) | Stmt::Throw(_, e) => self.expr(e),
+			Stmt::Variable(name, init, doc) => {
+				// the initializer can't see the variable it declares
+				self.expr(init);
+				if top {
+					self.reach_global();
+				} else {
+					self.declare(name, DeclKind::Variable, doc.clone(), Vec::new());
+				}
+			},
+			Stmt::Function(f) => {
+				if top {
+					self.reach_global();
+				} else {
+					self.function_name(f);
+				}
+				self.function(f);
+			},
+			Stmt::Block(stmts) => self.block(stmts),
+			Stmt::If(..) => {
+				let (arms, otherwise) = stmt.arms();
+				for (_, condition, then) in arms {
+					self.expr(condition);
+					self.stmt(then, false);
+				}
+				if let Some(otherwise) = otherwise {
+					self.stmt(otherwise, false);
+				}
+			},
+			Stmt::While(condition, body, increment) => {
+				self.expr(condition);
+				self.stmt(body, false);
+				if let Some(increment) = increment {
+					self.expr(increment);
+				}
+			},
+			Stmt::Import(_, _, alias) => {
+				if alias.is_some() {
+					self.reach_global();
+				}
+			},
+			Stmt::Export(decl) => self.stmt(decl, top),
+			Stmt::Try(_, body, catch, finally) => {
+				self.block(body);
+				if let Some((name, stmts)) = catch {
+					// bound like a parameter, nobody has to use it
+					self.scopes.push(HashMap::new());
+					self.declare(name, DeclKind::Parameter, None, Vec::new());
+					self.block(stmts);
+					self.scopes.pop();
+				}
+				if let Some(stmts) = finally {
+					self.block(stmts);
+				}
+			},
+			Stmt::Break | Stmt::Continue => ()
+		}
+	}
+
+	fn expr(&mut self, expr: &Expr) {
+		match expr {
+			Expr::Binary(..) | Expr::Logical(..) | Expr::Index(..) | Expr::Call(..) => {
+				let (first, links) = expr.chain();
+				self.expr(first);
+				for link in links {
+					match link {
+						Expr::Binary(_, _, r) | Expr::Logical(_, _, r) | Expr::Index(_, _, r) => self.expr(r),
+						Expr::Call(_, _, args) => args.iter().for_each(|e| self.expr(e)),
+						_ => ()
+					}
+				}
+			},
+			Expr::Unary(_, e) | Expr::Group(e) | Expr::Postfix(_, e) => self.expr(e),
+			Expr::Variable(name) => self.lookup(name, false),
+			Expr::Assign(name, value) => {
+				self.expr(value);
+				self.lookup(name, true);
+			},
+			Expr::List(items) => items.iter().for_each(|e| self.expr(e)),
+			Expr::Map(_, entries) => {
+				for (k, v) in entries {
+					self.expr(k);
+					self.expr(v);
+				}
+			},
+			Expr::SetIndex(container, _, key, _, value) => {
+				self.expr(container);
+				self.expr(key);
+				self.expr(value);
+			},
+			Expr::Lambda(f) => self.function(f),
+			Expr::Constant(_) => ()
+		}
+	}
+}
@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// An interned name or string constant, equal texts always get the same id so comparing
+// and hashing them never touches the text. The text belongs to the interner of the thread
+// that interned it and goes away with that thread, so every run starts from an empty one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+struct Interner {
+	ids: HashMap<Rc<str>, Symbol>,
+	names: Vec<Rc<str>>
+}
+
+thread_local! {
+	static INTERNER: RefCell<Interner> = RefCell::new(Interner { ids: HashMap::new(), names: Vec::new() });
+}
+
+impl Symbol {
+	pub fn intern(name: &str) -> Symbol {
+		INTERNER.with_borrow_mut(|interner| {
+			if let Some(id) = interner.ids.get(name) {
+				return *id;
+			}
+
+			let name: Rc<str> = Rc::from(name);
+			let id = Symbol(interner.names.len() as u32);
+			interner.names.push(name.clone());
+			interner.ids.insert(name, id);
+			id
+		})
+	}
+
+	pub fn as_str(&self) -> Rc<str> {
+		INTERNER.with_borrow(|interner| interner.names[self.0 as usize].clone())
+	}
+}
+
+impl std::fmt::Display for Symbol {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.as_str())
+	}
+}
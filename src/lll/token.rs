@@ -1,61 +1,138 @@
 
+use std::rc::Rc;
+use std::cell::RefCell;
+
 use super::error::Error;
+use super::error::ErrorKind;
+use super::builtins::Native;
+use super::builtins::new_list;
+use super::interpreter::Closure;
+use super::vm;
+use super::map::Map;
+use super::symbol::Symbol;
+
+pub type ListRef = Rc<RefCell<Items>>;
+pub type MapRef = Rc<RefCell<Map>>;
+
+// What a list holds, a type of its own so dropping it doesn't recurse
+#[derive(Debug, Clone, Default)]
+pub struct Items(pub Vec<Literal>);
+
+impl std::ops::Deref for Items {
+	type Target = Vec<Literal>;
+
+	fn deref(&self) -> &Vec<Literal> {
+		&self.0
+	}
+}
+
+impl std::ops::DerefMut for Items {
+	fn deref_mut(&mut self) -> &mut Vec<Literal> {
+		&mut self.0
+	}
+}
+
+impl Drop for Items {
+	fn drop(&mut self) {
+		drop_values(std::mem::take(&mut self.0));
+	}
+}
+
+// Lists and maps nobody else holds are taken apart one level at a time, so one nested
+// thousands deep is freed without a native call per level
+pub fn drop_values(mut pending: Vec<Literal>) {
+	while let Some(v) = pending.pop() {
+		match v {
+			Literal::List(l) => if let Ok(l) = Rc::try_unwrap(l) {
+				pending.append(&mut l.into_inner());
+			},
+			Literal::Map(m) => if let Ok(m) = Rc::try_unwrap(m) {
+				pending.extend(m.into_inner().take_values());
+			},
+			_ => ()
+		}
+	}
+}
 
-#[derive(Debug)]
 pub enum Literal {
 	Float(f64),
 	String(String),
 	Bool(bool),
-	Identifier(String),
+	Identifier(Symbol),
+	List(ListRef),
+	Map(MapRef),
+	Native(&'static Native),
+	Function(Rc<Closure>),
+	Compiled(Rc<vm::Closure>),
 	Nil
 }
 
-impl Literal { // sum, sub, mul, div, cmp
+#[allow(clippy::should_implement_trait)]
+impl Literal { // sum, sub, mul, div, rem, cmp
 	pub fn sum(v1: Literal, v2: Literal) -> Result<Literal, Error> {
 		use Literal::*;
 		match (v1, v2) {
 			(String(v1), String(v2)) => Ok(String(format!("{v1}{v2}"))),
 			(Float(v1), Float(v2)) => Ok(Float(v1 + v2)),
-			(String(_), Float(_)) => Err(Error::fatal("cannot sum string and float", None)),
-			(Float(_), String(_)) => Err(Error::fatal("cannot sum float and string", None)),
+			(String(_), Float(_)) => Err(Error::fatal("cannot sum string and float", None).of(ErrorKind::Type)),
+			(Float(_), String(_)) => Err(Error::fatal("cannot sum float and string", None).of(ErrorKind::Type)),
 			(Bool(v1), Bool(v2)) => Ok(Bool(v1 || v2)),
-			_ => Err(Error::fatal("cannot sum nil or identifier", None))
+			(List(v1), List(v2)) => {
+				let mut vals = v1.borrow().to_vec();
+				vals.extend(v2.borrow().iter().cloned());
+				Ok(new_list(vals))
+			},
+			_ => Err(Error::fatal("cannot sum nil or identifier", None).of(ErrorKind::Type))
 		}
 	}
 
 	pub fn sub(v1: Literal, v2: Literal) -> Result<Literal, Error> {
 		use Literal::*;
 		match (v1, v2) {
-			(String(_), String(_)) => Err(Error::fatal("cannot sub string and string", None)),
+			(String(_), String(_)) => Err(Error::fatal("cannot sub string and string", None).of(ErrorKind::Type)),
 			(Float(v1), Float(v2)) => Ok(Float(v1 - v2)),
-			(String(_), Float(_)) => Err(Error::fatal("cannot sub string and float", None)),
-			(Float(_), String(_)) => Err(Error::fatal("cannot sub float and string", None)),
-			(Bool(_), Bool(_)) => Err(Error::fatal("cannot sub bool and bool", None)),
-			_ => Err(Error::fatal("cannot sub nil or identifier", None)),
+			(String(_), Float(_)) => Err(Error::fatal("cannot sub string and float", None).of(ErrorKind::Type)),
+			(Float(_), String(_)) => Err(Error::fatal("cannot sub float and string", None).of(ErrorKind::Type)),
+			(Bool(_), Bool(_)) => Err(Error::fatal("cannot sub bool and bool", None).of(ErrorKind::Type)),
+			_ => Err(Error::fatal("cannot sub nil or identifier", None).of(ErrorKind::Type)),
 		}
 	}
 
 	pub fn mul(v1: Literal, v2: Literal) -> Result<Literal, Error> {
 		use Literal::*;
 		match (v1, v2) {
-			(String(_), String(_)) => Err(Error::fatal("cannot mul string and string", None)),
+			(String(_), String(_)) => Err(Error::fatal("cannot mul string and string", None).of(ErrorKind::Type)),
 			(Float(v1), Float(v2)) => Ok(Float(v1 * v2)),
-			(String(_), Float(_)) => Err(Error::fatal("cannot mul string and float", None)),
-			(Float(_), String(_)) => Err(Error::fatal("cannot mul float and string", None)),
+			(String(_), Float(_)) => Err(Error::fatal("cannot mul string and float", None).of(ErrorKind::Type)),
+			(Float(_), String(_)) => Err(Error::fatal("cannot mul float and string", None).of(ErrorKind::Type)),
 			(Bool(v1), Bool(v2)) => Ok(Bool(v1 && v2)),
-			_ => Err(Error::fatal("cannot mul nil or identifier", None)),
+			_ => Err(Error::fatal("cannot mul nil or identifier", None).of(ErrorKind::Type)),
 		}
 	}
 
 	pub fn div(v1: Literal, v2: Literal) -> Result<Literal, Error> {
 		use Literal::*;
 		match (v1, v2) {
-			(String(_), String(_)) => Err(Error::fatal("cannot div string and string", None)),
+			(String(_), String(_)) => Err(Error::fatal("cannot div string and string", None).of(ErrorKind::Type)),
+			(Float(_), Float(0.0)) => Err(Error::fatal("division by zero", None).of(ErrorKind::Division)),
 			(Float(v1), Float(v2)) => Ok(Float(v1 / v2)),
-			(String(_), Float(_)) => Err(Error::fatal("cannot div string and float", None)),
-			(Float(_), String(_)) => Err(Error::fatal("cannot div float and string", None)),
-			(Bool(_), Bool(_)) => Err(Error::fatal("cannot div bool and bool", None)),
-			_ => Err(Error::fatal("cannot div nil or identifier", None)),
+			(String(_), Float(_)) => Err(Error::fatal("cannot div string and float", None).of(ErrorKind::Type)),
+			(Float(_), String(_)) => Err(Error::fatal("cannot div float and string", None).of(ErrorKind::Type)),
+			(Bool(_), Bool(_)) => Err(Error::fatal("cannot div bool and bool", None).of(ErrorKind::Type)),
+			_ => Err(Error::fatal("cannot div nil or identifier", None).of(ErrorKind::Type)),
+		}
+	}
+
+	pub fn rem(v1: Literal, v2: Literal) -> Result<Literal, Error> {
+		use Literal::*;
+		match (v1, v2) {
+			(String(_), String(_)) => Err(Error::fatal("cannot rem string and string", None).of(ErrorKind::Type)),
+			(Float(_), Float(0.0)) => Err(Error::fatal("division by zero", None).of(ErrorKind::Division)),
+			(Float(v1), Float(v2)) => Ok(Float(v1 % v2)),
+			(String(_), Float(_)) => Err(Error::fatal("cannot rem string and float", None).of(ErrorKind::Type)),
+			(Float(_), String(_)) => Err(Error::fatal("cannot rem float and string", None).of(ErrorKind::Type)),
+			(Bool(_), Bool(_)) => Err(Error::fatal("cannot rem bool and bool", None).of(ErrorKind::Type)),
+			_ => Err(Error::fatal("cannot rem nil or identifier", None).of(ErrorKind::Type)),
 		}
 	}
 
@@ -64,10 +141,12 @@ impl Literal { // sum, sub, mul, div, cmp
 		match (v1, v2) {
 			(String(v1), String(v2)) => Ok(Bool(v1 == v2)),
 			(Float(v1), Float(v2)) => Ok(Bool(v1 == v2) ),
-			(String(_), Float(_)) => Err(Error::fatal("cannot eq string and float", None)),
-			(Float(_), String(_)) => Err(Error::fatal("cannot eq float and string", None)),
+			(String(_), Float(_)) => Err(Error::fatal("cannot eq string and float", None).of(ErrorKind::Type)),
+			(Float(_), String(_)) => Err(Error::fatal("cannot eq float and string", None).of(ErrorKind::Type)),
 			(Bool(v1), Bool(v2)) => Ok(Bool(v1 == v2)),
-			_ => Err(Error::fatal("cannot eq nil or identifier", None)),
+			(List(v1), List(v2)) => Ok(Bool(List(v1).equals(&List(v2)))),
+			(Map(v1), Map(v2)) => Ok(Bool(Map(v1).equals(&Map(v2)))),
+			_ => Err(Error::fatal("cannot eq nil or identifier", None).of(ErrorKind::Type)),
 		}
 	}
 
@@ -76,10 +155,10 @@ impl Literal { // sum, sub, mul, div, cmp
 		match (v1, v2) {
 			(String(v1), String(v2)) => Ok(Bool(v1.len() > v2.len())),
 			(Float(v1), Float(v2)) => Ok(Bool(v1 > v2)),
-			(String(_), Float(_)) => Err(Error::fatal("cannot gt string and float", None)),
-			(Float(_), String(_)) => Err(Error::fatal("cannot gt float and string", None)),
-			(Bool(v1), Bool(v2)) => Ok(Bool(v1 > v2)),
-			_ => Err(Error::fatal("cannot gt nil or identifier", None)),
+			(String(_), Float(_)) => Err(Error::fatal("cannot gt string and float", None).of(ErrorKind::Type)),
+			(Float(_), String(_)) => Err(Error::fatal("cannot gt float and string", None).of(ErrorKind::Type)),
+			(Bool(v1), Bool(v2)) => Ok(Bool(v1 & !v2)),
+			_ => Err(Error::fatal("cannot gt nil or identifier", None).of(ErrorKind::Type)),
 		}
 	}
 
@@ -88,10 +167,10 @@ impl Literal { // sum, sub, mul, div, cmp
 		match (v1, v2) {
 			(String(v1), String(v2)) => Ok(Bool(v1.len() >= v2.len())),
 			(Float(v1), Float(v2)) => Ok(Bool(v1 >= v2)),
-			(String(_), Float(_)) => Err(Error::fatal("cannot gt string and float", None)),
-			(Float(_), String(_)) => Err(Error::fatal("cannot gt float and string", None)),
+			(String(_), Float(_)) => Err(Error::fatal("cannot gt string and float", None).of(ErrorKind::Type)),
+			(Float(_), String(_)) => Err(Error::fatal("cannot gt float and string", None).of(ErrorKind::Type)),
 			(Bool(v1), Bool(v2)) => Ok(Bool(v1 >= v2)),
-			_ => Err(Error::fatal("cannot gt nil or identifier", None)),
+			_ => Err(Error::fatal("cannot gt nil or identifier", None).of(ErrorKind::Type)),
 		}
 	}
 
@@ -100,10 +179,10 @@ impl Literal { // sum, sub, mul, div, cmp
 		match (v1, v2) {
 			(String(v1), String(v2)) => Ok(Bool(v1.len() < v2.len())),
 			(Float(v1), Float(v2)) => Ok(Bool(v1 < v2)),
-			(String(_), Float(_)) => Err(Error::fatal("cannot gt string and float", None)),
-			(Float(_), String(_)) => Err(Error::fatal("cannot gt float and string", None)),
-			(Bool(v1), Bool(v2)) => Ok(Bool(v1 < v2)),
-			_ => Err(Error::fatal("cannot gt nil or identifier", None)),
+			(String(_), Float(_)) => Err(Error::fatal("cannot gt string and float", None).of(ErrorKind::Type)),
+			(Float(_), String(_)) => Err(Error::fatal("cannot gt float and string", None).of(ErrorKind::Type)),
+			(Bool(v1), Bool(v2)) => Ok(Bool(!v1 & v2)),
+			_ => Err(Error::fatal("cannot gt nil or identifier", None).of(ErrorKind::Type)),
 		}
 	}
 
@@ -112,52 +191,150 @@ impl Literal { // sum, sub, mul, div, cmp
 		match (v1, v2) {
 			(String(v1), String(v2)) => Ok(Bool(v1.len() <= v2.len())),
 			(Float(v1), Float(v2)) => Ok(Bool(v1 <= v2)),
-			(String(_), Float(_)) => Err(Error::fatal("cannot gt string and float", None)),
-			(Float(_), String(_)) => Err(Error::fatal("cannot gt float and string", None)),
+			(String(_), Float(_)) => Err(Error::fatal("cannot gt string and float", None).of(ErrorKind::Type)),
+			(Float(_), String(_)) => Err(Error::fatal("cannot gt float and string", None).of(ErrorKind::Type)),
 			(Bool(v1), Bool(v2)) => Ok(Bool(v1 <= v2)),
-			_ => Err(Error::fatal("cannot gt nil or identifier", None))
+			_ => Err(Error::fatal("cannot gt nil or identifier", None).of(ErrorKind::Type))
 		}
 	}
 
 	pub fn is_true_val(v: Literal) -> Result<bool, Error> {
 		use Literal::*;
 		match v {
-			String(v) => Ok(v.len() != 0),
+			String(v) => Ok(!v.is_empty()),
 			Float(v) => Ok(v != 0.0),
 			Bool(v) => Ok(v),
-			Identifier(_) => Err(Error::fatal("cannot identifier cannot be true value", None)),
+			Identifier(_) => Err(Error::fatal("cannot identifier cannot be true value", None).of(ErrorKind::Type)),
+			List(v) => Ok(!v.borrow().is_empty()),
+			Map(v) => Ok(!v.borrow().is_empty()),
+			Native(_) | Function(_) | Compiled(_) => Ok(true),
 			Nil => Ok(false)
 		}
 	}
-}
 
-impl ToString for Literal {
-	fn to_string(&self) -> String {
+	// Structural equality that never fails, lists are compared element by element
+	pub fn equals(&self, other: &Literal) -> bool {
+		self.equals_seen(other, &mut Vec::new())
+	}
+
+	// `seen` holds the pairs of collections being compared further up, running into one
+	// again means a cycle that didn't find a difference so far
+	fn equals_seen(&self, other: &Literal, seen: &mut Vec<(usize, usize)>) -> bool {
+		use Literal::*;
+		match (self, other) {
+			(Float(v1), Float(v2)) => v1 == v2,
+			(String(v1), String(v2)) => v1 == v2,
+			(Bool(v1), Bool(v2)) => v1 == v2,
+			(Identifier(v1), Identifier(v2)) => v1 == v2,
+			(List(v1), List(v2)) => {
+				let pair = (Rc::as_ptr(v1) as usize, Rc::as_ptr(v2) as usize);
+				if Rc::ptr_eq(v1, v2) || seen.contains(&pair) {
+					return true;
+				}
+				seen.push(pair);
+				let (v1, v2) = (v1.borrow(), v2.borrow());
+				let equal = v1.len() == v2.len() && v1.iter().zip(v2.iter()).all(|(a, b)| a.equals_seen(b, seen));
+				seen.pop();
+				equal
+			},
+			(Map(v1), Map(v2)) => {
+				let pair = (Rc::as_ptr(v1) as usize, Rc::as_ptr(v2) as usize);
+				if Rc::ptr_eq(v1, v2) || seen.contains(&pair) {
+					return true;
+				}
+				seen.push(pair);
+				let (v1, v2) = (v1.borrow(), v2.borrow());
+				let equal = v1.len() == v2.len() && v1.iter().all(|(k, a)| v2.get(k).is_some_and(|b| a.equals_seen(b, seen)));
+				seen.pop();
+				equal
+			},
+			(Native(v1), Native(v2)) => std::ptr::eq(*v1, *v2),
+			(Function(v1), Function(v2)) => Rc::ptr_eq(v1, v2),
+			(Compiled(v1), Compiled(v2)) => Rc::ptr_eq(v1, v2),
+			(Nil, Nil) => true,
+			_ => false
+		}
+	}
+
+	// Strings inside of collections are shown quoted. `seen` holds the collections being
+	// written further up, one that contains itself is shown as `[...]` or `{...}` there.
+	fn fmt_seen(&self, f: &mut std::fmt::Formatter<'_>, nested: bool, seen: &mut Vec<usize>) -> std::fmt::Result {
 		use Literal::*;
 		match self {
-			Float(v) => v.to_string(),
-			String(v) => v.clone(),
-			Bool(v) => {
-				if *v {
-					format!("true")
-				} else {
-					format!("false")
+			Float(v) => write!(f, "{v}"),
+			// written back the way it would be in the source
+			String(v) if nested => write!(f, "\"{}\"", v.replace('\n', "\\n")),
+			String(v) => write!(f, "{v}"),
+			Bool(v) => write!(f, "{v}"),
+			Identifier(v) => write!(f, "{v}"),
+			List(v) => {
+				let addr = Rc::as_ptr(v) as usize;
+				if seen.contains(&addr) {
+					return write!(f, "[...]");
+				}
+				seen.push(addr);
+				write!(f, "[")?;
+				for (i, val) in v.borrow().iter().enumerate() {
+					if i != 0 {
+						write!(f, ", ")?;
+					}
+					val.fmt_seen(f, true, seen)?;
+				}
+				seen.pop();
+				write!(f, "]")
+			},
+			Map(v) => {
+				let addr = Rc::as_ptr(v) as usize;
+				if seen.contains(&addr) {
+					return write!(f, "{{...}}");
 				}
+				seen.push(addr);
+				write!(f, "{{")?;
+				for (i, (key, val)) in v.borrow().iter().enumerate() {
+					if i != 0 {
+						write!(f, ", ")?;
+					}
+					key.fmt_seen(f, true, seen)?;
+					write!(f, ": ")?;
+					val.fmt_seen(f, true, seen)?;
+				}
+				seen.pop();
+				write!(f, "}}")
 			},
-			Identifier(v) => v.clone(),
-			Nil => format!("nil")
+			Native(v) => write!(f, "<native {}>", v.name),
+			Function(v) => write!(f, "{v:?}"),
+			Compiled(v) => write!(f, "{v:?}"),
+			Nil => write!(f, "nil")
 		}
 	}
 }
 
+impl std::fmt::Display for Literal {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		self.fmt_seen(f, false, &mut Vec::new())
+	}
+}
+
+// The way it's shown nested, a derived one would never end on a cycle
+impl std::fmt::Debug for Literal {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		self.fmt_seen(f, true, &mut Vec::new())
+	}
+}
+
 impl Clone for Literal {
 	fn clone(&self) -> Self {
 		use Literal::*;
 		match self {
-			Float(v) => Float(v.clone()),
+			Float(v) => Float(*v),
 			String(v) => String(v.clone()),
-			Bool(v) => Bool(v.clone()),
-			Identifier(v) => Identifier(v.clone()),
+			Bool(v) => Bool(*v),
+			Identifier(v) => Identifier(*v),
+			List(v) => List(v.clone()),
+			Map(v) => Map(v.clone()),
+			Native(v) => Native(v),
+			Function(v) => Function(v.clone()),
+			Compiled(v) => Compiled(v.clone()),
 			Nil => Nil
 		}
 	}
@@ -166,14 +343,16 @@ impl Clone for Literal {
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum TokenType {
 		// Single-character tokens.
-		LeftParen, RightParen, LeftBrace, RightBrace,
-		Comma, Dot, Minus, Plus, Semicolon, Slash, Star,
+		LeftParen, RightParen, LeftBrace, RightBrace, LeftBracket, RightBracket,
+		Comma, Colon, Dot, Minus, Plus, Semicolon, Slash, Star, Percent,
 
 		// One or two character tokens.
 		Bang, BangEqual,
 		Equal, EqualEqual,
 		Greater, GreaterEqual,
 		Less, LessEqual,
+		PlusEqual, MinusEqual, StarEqual, SlashEqual, PercentEqual,
+		PlusPlus, MinusMinus, Arrow,
 
 		// Literals.
 		Identifier, String, Number,
@@ -181,10 +360,37 @@ pub enum TokenType {
 		// Keywords.
 		And, Class, Else, False, Fun, For, If, Nil, Or,
 		Print, Return, Super, This, True, New, While,
+		Break, Continue, Import, Export, As,
+		Try, Catch, Finally, Throw,
+
+		// `///` comment, kept only in front of a declaration.
+		Doc,
 
 		Eof
 }
 
+impl TokenType {
+	// Source text of the token, empty for the ones carrying a literal
+	pub fn lexeme(&self) -> &'static str {
+		use TokenType::*;
+		match self {
+			LeftParen => "(", RightParen => ")", LeftBrace => "{", RightBrace => "}",
+			LeftBracket => "[", RightBracket => "]", Comma => ",", Colon => ":", Dot => ".",
+			Minus => "-", Plus => "+", Semicolon => ";", Slash => "/", Star => "*", Percent => "%",
+			Bang => "!", BangEqual => "!=", Equal => "=", EqualEqual => "==",
+			Greater => ">", GreaterEqual => ">=", Less => "<", LessEqual => "<=",
+			PlusEqual => "+=", MinusEqual => "-=", StarEqual => "*=", SlashEqual => "/=", PercentEqual => "%=",
+			PlusPlus => "++", MinusMinus => "--", Arrow => "=>",
+			And => "and", Class => "class", Else => "else", False => "false", Fun => "fun",
+			For => "for", If => "if", Nil => "nil", Or => "or", Print => "print", Return => "return",
+			Super => "super", This => "this", True => "true", New => "new", While => "while",
+			Break => "break", Continue => "continue", Import => "import", Export => "export", As => "as",
+			Try => "try", Catch => "catch", Finally => "finally", Throw => "throw",
+			Identifier | String | Number | Doc | Eof => ""
+		}
+	}
+}
+
 impl std::fmt::Display for TokenType {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -199,8 +405,8 @@ pub struct Token {
 	pub line: usize
 }
 
-impl ToString for Token {
-	fn to_string(&self) -> String {
-		format!("[INFO] TOKEN( literal: {}, toktype: {}, place: {}, line: {} )", self.literal.to_string(), self.toktype.to_string(), self.place, self.line)
+impl std::fmt::Display for Token {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "[INFO] TOKEN( literal: {}, toktype: {}, place: {}, line: {} )", self.literal, self.toktype, self.place, self.line)
 	}
 }
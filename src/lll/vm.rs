@@ -0,0 +1,672 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use super::budget::Budget;
+use super::budget::Limits;
+use super::builtins;
+use super::capability::Capability;
+use super::capability::Capabilities;
+use super::builtins::Caller;
+use super::gc;
+use super::gc::Object;
+use super::chunk::OpCode;
+use super::chunk::Proto;
+use super::compiler::Compiler;
+use super::error::Error;
+use super::error::ErrorKind;
+use super::map::Map;
+use super::module;
+use super::module::Modules;
+use super::module::ModulesRef;
+use super::parse::Stmt;
+use super::symbol::Symbol;
+use super::token::Literal;
+use super::token::Token;
+
+// Points at a stack slot while the variable is alive, owns the value once it went out of scope
+pub enum Upvalue {
+	Open(usize),
+	Closed(Literal)
+}
+
+pub type UpvalueRef = Rc<RefCell<Upvalue>>;
+
+// The globals of one module, every closure made in it keeps them
+pub type Globals = Rc<RefCell<HashMap<Symbol, Literal>>>;
+
+pub struct Closure {
+	pub proto: Rc<Proto>,
+	pub upvalues: Vec<UpvalueRef>,
+	globals: Globals
+}
+
+impl Closure {
+	fn new(proto: Rc<Proto>, upvalues: Vec<UpvalueRef>, globals: Globals) -> Rc<Closure> {
+		let closure = Rc::new(Closure { proto, upvalues, globals });
+		gc::track(Object::Compiled(closure.clone()));
+		closure
+	}
+}
+
+impl std::fmt::Debug for Closure {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.proto.name {
+			Some(name) => write!(f, "<fn {name}>"),
+			None => write!(f, "<fn>")
+		}
+	}
+}
+
+struct CallFrame {
+	closure: Rc<Closure>,
+	ip: usize,
+	base: usize
+}
+
+// Where an error inside a `try` goes, everything the try didn't start with is dropped
+struct Handler {
+	frames: usize,
+	stack: usize,
+	ip: usize,
+	// a catch gets the error value pushed, a finally keeps the error for `Rethrow`
+	catches: bool
+}
+
+// Script calls don't use the native stack, but natives like `map` calling back into the
+// script do: run it through `lang::run` or `Engine` so it gets a stack big enough.
+pub struct Vm {
+	stack: Vec<Literal>,
+	frames: Vec<CallFrame>,
+	globals: Globals,
+	open_upvalues: Vec<UpvalueRef>,
+	handlers: Vec<Handler>,
+	// errors waiting for the finally blocks they are passing through
+	unwinding: Vec<Error>,
+	modules: ModulesRef,
+	// the file being run, imports are relative to it
+	path: Option<PathBuf>,
+	// calls allowed on top of the script's own frame
+	max_depth: usize,
+	budget: Option<Budget>,
+	capabilities: Capabilities,
+	// the `args` global, modules get the same list
+	args: Literal,
+	// a top-level statement ended in an error
+	failed: bool
+}
+
+impl Default for Vm {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Vm {
+	pub fn new() -> Self {
+		Self::with_modules(Modules::new(None, Vec::new()), None)
+	}
+
+	pub fn with_modules(modules: ModulesRef, path: Option<PathBuf>) -> Self {
+		let mut globals = HashMap::new();
+		for native in builtins::natives() {
+			globals.insert(Symbol::intern(native.name), Literal::Native(native));
+		}
+
+		let mut vm = Self {
+			stack: Vec::new(),
+			frames: Vec::new(),
+			globals: Rc::new(RefCell::new(globals)),
+			open_upvalues: Vec::new(),
+			handlers: Vec::new(),
+			unwinding: Vec::new(),
+			modules,
+			path,
+			max_depth: builtins::MAX_DEPTH,
+			budget: None,
+			capabilities: Capabilities::default(),
+			args: Literal::Nil,
+			failed: false
+		};
+		vm.define_args(builtins::new_list(Vec::new()));
+		vm
+	}
+
+	pub fn set_max_depth(&mut self, max_depth: usize) {
+		self.max_depth = max_depth;
+	}
+
+	pub fn set_limits(&mut self, limits: Limits) -> Result<(), Error> {
+		self.budget = Some(Budget::new(limits)?);
+		Ok(())
+	}
+
+	pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+		self.capabilities = capabilities;
+	}
+
+	// Whether any statement `interpret` ran ended in an error, the run still went on
+	pub fn failed(&self) -> bool {
+		self.failed
+	}
+
+	// What the script sees as `args`
+	pub fn set_args(&mut self, args: Vec<String>) {
+		self.define_args(builtins::new_list(args.into_iter().map(Literal::String).collect()));
+	}
+
+	fn define_args(&mut self, args: Literal) {
+		self.globals.borrow_mut().insert(Symbol::intern("args"), args.clone());
+		self.args = args;
+	}
+
+	// On a vm of its own, set up like this one and spending from the same budget
+	fn run_imported(&mut self, path: PathBuf, stmts: &[Stmt]) -> Result<HashMap<Symbol, Literal>, Error> {
+		let mut vm = Vm::with_modules(self.modules.clone(), Some(path));
+		vm.max_depth = self.max_depth;
+		vm.capabilities = self.capabilities;
+		vm.define_args(self.args.clone());
+		vm.budget = self.budget.take();
+		let res = Compiler::compile(stmts).and_then(|script| vm.run_module(script));
+		self.budget = vm.budget.take();
+		res
+	}
+
+	// Runs an imported module, the first error stops it
+	pub fn run_module(&mut self, script: Proto) -> Result<HashMap<Symbol, Literal>, Error> {
+		let closure = Closure::new(Rc::new(script), Vec::new(), self.globals.clone());
+		self.stack.push(Literal::Compiled(closure.clone()));
+		self.frames.push(CallFrame { closure, ip: 0, base: 0 });
+
+		let res = self.run(1);
+		self.reset();
+		res.map(|_| self.globals.borrow().clone())
+	}
+
+	// Like the tree walker, an error only stops the top level statement it happened in
+	pub fn interpret(&mut self, script: Proto) -> Result<(), Error> {
+		let closure = Closure::new(Rc::new(script), Vec::new(), self.globals.clone());
+		self.stack.push(Literal::Compiled(closure.clone()));
+		self.frames.push(CallFrame { closure, ip: 0, base: 0 });
+
+		while let Err(e) = self.run(1) {
+			if !e.kind().catchable() {
+				self.reset();
+				return Err(e);
+			}
+			println!("{e}");
+			self.failed = true;
+			if !self.recover() {
+				break;
+			}
+		}
+
+		self.reset();
+		Ok(())
+	}
+
+	fn reset(&mut self) {
+		self.frames.clear();
+		self.stack.clear();
+		self.open_upvalues.clear();
+		self.handlers.clear();
+		self.unwinding.clear();
+	}
+
+	fn recover(&mut self) -> bool {
+		self.frames.truncate(1);
+		self.close_upvalues(1);
+		self.stack.truncate(1);
+		self.handlers.clear();
+		self.unwinding.clear();
+
+		let frame = &mut self.frames[0];
+		match frame.closure.proto.chunk.statements.iter().find(|s| **s >= frame.ip) {
+			Some(next) => {
+				frame.ip = *next;
+				true
+			},
+			None => false
+		}
+	}
+
+	fn collect_garbage(&self) {
+		let globals = self.globals.borrow();
+		let mut roots: Vec<Object> = self.stack.iter().chain(globals.values()).filter_map(Object::of).collect();
+		roots.extend(self.open_upvalues.iter().map(|u| Object::Upvalue(u.clone())));
+		roots.extend(self.frames.iter().map(|f| Object::Compiled(f.closure.clone())));
+		gc::collect(roots);
+	}
+
+	fn read_byte(&mut self, closure: &Closure) -> u8 {
+		let frame = self.frames.last_mut().unwrap();
+		let byte = closure.proto.chunk.code[frame.ip];
+		frame.ip += 1;
+		byte
+	}
+
+	fn read_u16(&mut self, closure: &Closure) -> usize {
+		let frame = self.frames.last_mut().unwrap();
+		let v = closure.proto.chunk.read_u16(frame.ip);
+		frame.ip += 2;
+		v as usize
+	}
+
+	fn name(t: &Token) -> Symbol {
+		match &t.literal {
+			Literal::Identifier(name) => *name,
+			_ => Symbol::intern("")
+		}
+	}
+
+	fn pop(&mut self) -> Literal {
+		self.stack.pop().unwrap()
+	}
+
+	fn peek(&self) -> &Literal {
+		self.stack.last().unwrap()
+	}
+
+	// Runs until the frame at `depth` returns. Errors inside a try of these frames are handled
+	// here, the rest go to the caller.
+	fn run(&mut self, depth: usize) -> Result<Literal, Error> {
+		loop {
+			match self.execute(depth) {
+				Ok(v) => return Ok(v),
+				Err(e) => self.catch(e, depth)?
+			}
+		}
+	}
+
+	fn catch(&mut self, e: Error, depth: usize) -> Result<(), Error> {
+		let frame = self.frames.last().unwrap();
+		if !e.kind().catchable() {
+			// a limit or `exit` ends the run wherever it happens, that's not a line to point at
+			return Err(self.traced(e, depth));
+		}
+		let e = e.on_line(frame.closure.proto.chunk.lines[frame.ip.saturating_sub(1)]);
+		if self.handlers.last().is_none_or(|h| h.frames < depth) {
+			return Err(self.traced(e, depth));
+		}
+
+		let handler = self.handlers.pop().unwrap();
+		self.frames.truncate(handler.frames);
+		self.close_upvalues(handler.stack);
+		self.stack.truncate(handler.stack);
+		self.frames.last_mut().unwrap().ip = handler.ip;
+		if handler.catches {
+			self.stack.push(e.value());
+		} else {
+			self.unwinding.push(e);
+		}
+		Ok(())
+	}
+
+	// Adds the frames an uncaught error leaves, up to the first one of `run(depth)`. The
+	// script's own frame is not a call.
+	fn traced(&self, mut e: Error, depth: usize) -> Error {
+		for i in (depth.max(2) - 1..self.frames.len()).rev() {
+			let caller = &self.frames[i - 1];
+			let line = caller.closure.proto.chunk.lines[caller.ip - 1];
+			e = e.traced(format!("{:?}", self.frames[i].closure), line);
+		}
+		e
+	}
+
+	fn execute(&mut self, depth: usize) -> Result<Literal, Error> {
+		let mut closure = self.frames.last().unwrap().closure.clone();
+
+		loop {
+			if gc::pending() {
+				self.collect_garbage();
+			}
+			if let Some(budget) = &mut self.budget {
+				budget.step()?;
+			}
+
+			let byte = self.read_byte(&closure);
+			let Some(op) = OpCode::from_byte(byte) else {
+				return Err(Error::fatal(format!("unknown opcode {byte}").as_str(), None));
+			};
+
+			match op {
+				OpCode::Constant => {
+					let i = self.read_u16(&closure);
+					self.stack.push(closure.proto.chunk.constants[i].clone());
+				},
+				OpCode::Nil => self.stack.push(Literal::Nil),
+				OpCode::True => self.stack.push(Literal::Bool(true)),
+				OpCode::False => self.stack.push(Literal::Bool(false)),
+				OpCode::Pop => {
+					self.pop();
+				},
+				OpCode::GetLocal => {
+					let slot = self.read_byte(&closure) as usize + self.frames.last().unwrap().base;
+					self.stack.push(self.stack[slot].clone());
+				},
+				OpCode::SetLocal => {
+					let slot = self.read_byte(&closure) as usize + self.frames.last().unwrap().base;
+					self.stack[slot] = self.peek().clone();
+				},
+				OpCode::DefineGlobal => {
+					let t = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let v = self.pop();
+					closure.globals.borrow_mut().insert(Self::name(t), v);
+				},
+				OpCode::GetGlobal => {
+					let t = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let Some(v) = closure.globals.borrow().get(&Self::name(t)).cloned() else {
+						return Err(Error::fatal("variable identifier not found", Some(t)).of(ErrorKind::Name));
+					};
+					self.stack.push(v);
+				},
+				OpCode::SetGlobal => {
+					let t = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let v = self.peek().clone();
+					let mut globals = closure.globals.borrow_mut();
+					let Some(global) = globals.get_mut(&Self::name(t)) else {
+						return Err(Error::fatal("trying to change non-existing variable", Some(t)).of(ErrorKind::Name));
+					};
+					*global = v;
+				},
+				OpCode::GetUpvalue => {
+					let i = self.read_byte(&closure) as usize;
+					let v = match &*closure.upvalues[i].borrow() {
+						Upvalue::Open(slot) => self.stack[*slot].clone(),
+						Upvalue::Closed(v) => v.clone()
+					};
+					self.stack.push(v);
+				},
+				OpCode::SetUpvalue => {
+					let i = self.read_byte(&closure) as usize;
+					let v = self.peek().clone();
+					match &mut *closure.upvalues[i].borrow_mut() {
+						Upvalue::Open(slot) => self.stack[*slot] = v,
+						Upvalue::Closed(closed) => *closed = v
+					}
+				},
+				OpCode::Add | OpCode::Sub | OpCode::Mul | OpCode::Div | OpCode::Rem |
+				OpCode::Equal | OpCode::NotEqual | OpCode::Greater | OpCode::GreaterEqual |
+				OpCode::Less | OpCode::LessEqual => {
+					let v2 = self.pop();
+					let v1 = self.pop();
+					self.stack.push(Self::operate(op, v1, v2)?);
+				},
+				OpCode::Not => {
+					let t = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let Literal::Bool(v) = self.pop() else {
+						return Err(Error::fatal("unexpected operator in unary!", Some(t)).of(ErrorKind::Type));
+					};
+					self.stack.push(Literal::Bool(!v));
+				},
+				OpCode::Negate => {
+					let v = self.pop();
+					self.stack.push(Literal::sub(Literal::Float(0.0), v)?);
+				},
+				OpCode::Step => {
+					let direction = self.read_byte(&closure);
+					let t = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let v = self.pop();
+					self.stack.push(Self::step(direction, &v, t)?);
+				},
+				OpCode::StepIndex => {
+					let direction = self.read_byte(&closure);
+					let t = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let bracket = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let key = self.pop();
+					let container = self.pop();
+
+					let old = builtins::get_item(&container, &key, bracket)?;
+					builtins::set_item(&container, key, Self::step(direction, &old, t)?, bracket)?;
+					self.stack.push(old);
+				},
+				OpCode::Print => {
+					let v = self.pop();
+					builtins::print(&v);
+				},
+				OpCode::Jump => {
+					let offset = self.read_u16(&closure);
+					self.frames.last_mut().unwrap().ip += offset;
+				},
+				OpCode::JumpIfFalse => {
+					let offset = self.read_u16(&closure);
+					if !Literal::is_true_val(self.peek().clone())? {
+						self.frames.last_mut().unwrap().ip += offset;
+					}
+				},
+				OpCode::Loop => {
+					let offset = self.read_u16(&closure);
+					self.frames.last_mut().unwrap().ip -= offset;
+				},
+				OpCode::Call => {
+					let argc = self.read_byte(&closure) as usize;
+					let t = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let callee = self.stack[self.stack.len() - 1 - argc].clone();
+
+					match &callee {
+						Literal::Compiled(f) => {
+							self.push_frame(f, argc, t)?;
+							closure = f.clone();
+						},
+						_ => {
+							let args = self.stack.split_off(self.stack.len() - argc);
+							self.pop();
+							let v = self.call_value(&callee, t, args)?;
+							self.stack.push(v);
+						}
+					}
+				},
+				OpCode::Closure => {
+					let proto = closure.proto.chunk.functions[self.read_u16(&closure)].clone();
+					let base = self.frames.last().unwrap().base;
+
+					let mut upvalues = Vec::with_capacity(proto.upvalues);
+					for _ in 0..proto.upvalues {
+						let is_local = self.read_byte(&closure) == 1;
+						let index = self.read_byte(&closure) as usize;
+						if is_local {
+							upvalues.push(self.capture_upvalue(base + index));
+						} else {
+							upvalues.push(closure.upvalues[index].clone());
+						}
+					}
+
+					self.stack.push(Literal::Compiled(Closure::new(proto, upvalues, closure.globals.clone())));
+				},
+				OpCode::CloseUpvalue => {
+					self.close_upvalues(self.stack.len() - 1);
+					self.pop();
+				},
+				OpCode::Return => {
+					let v = self.pop();
+					let frame = self.frames.pop().unwrap();
+					self.close_upvalues(frame.base);
+					self.stack.truncate(frame.base);
+
+					if self.frames.len() < depth {
+						return Ok(v);
+					}
+					self.stack.push(v);
+					closure = self.frames.last().unwrap().closure.clone();
+				},
+				OpCode::List => {
+					let count = self.read_u16(&closure);
+					let vals = self.stack.split_off(self.stack.len() - count);
+					self.stack.push(builtins::new_list(vals));
+				},
+				OpCode::Map => {
+					let count = self.read_u16(&closure);
+					let t = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let mut vals = self.stack.split_off(self.stack.len() - count * 2).into_iter();
+
+					let mut map = Map::new();
+					while let (Some(key), Some(val)) = (vals.next(), vals.next()) {
+						if !map.insert(key, val) {
+							return Err(Error::fatal("map keys must be strings or numbers", Some(t)).of(ErrorKind::Type));
+						}
+					}
+					self.stack.push(builtins::new_map(map));
+				},
+				OpCode::Index => {
+					let t = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let key = self.pop();
+					let container = self.pop();
+					self.stack.push(builtins::get_item(&container, &key, t)?);
+				},
+				OpCode::SetIndex => {
+					let t = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let val = self.pop();
+					let key = self.pop();
+					let container = self.pop();
+					builtins::set_item(&container, key, val.clone(), t)?;
+					self.stack.push(val);
+				},
+				OpCode::UpdateIndex => {
+					let binary = OpCode::from_byte(self.read_byte(&closure)).unwrap();
+					let t = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let val = self.pop();
+					let key = self.pop();
+					let container = self.pop();
+
+					let current = builtins::get_item(&container, &key, t)?;
+					let val = Self::operate(binary, current, val)?;
+					builtins::set_item(&container, key, val.clone(), t)?;
+					self.stack.push(val);
+				},
+				OpCode::Import => {
+					let path = closure.proto.chunk.constants[self.read_u16(&closure)].to_string();
+					let t = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let (modules, from) = (self.modules.clone(), self.path.clone());
+					let exports = module::load(&modules, from.as_deref(), self.capabilities, t, &path, |path, stmts| self.run_imported(path, stmts))?;
+					self.stack.push(exports);
+				},
+				OpCode::DefineExports => {
+					let exports = self.pop();
+					module::bind(exports, &None, |name, v| {
+						closure.globals.borrow_mut().insert(name, v);
+					});
+				},
+				OpCode::Try => {
+					let catches = self.read_byte(&closure) == 1;
+					let offset = self.read_u16(&closure);
+					let ip = self.frames.last().unwrap().ip + offset;
+					self.handlers.push(Handler { frames: self.frames.len(), stack: self.stack.len(), ip, catches });
+				},
+				OpCode::EndTry => {
+					self.handlers.pop();
+				},
+				OpCode::Throw => {
+					let t = &closure.proto.chunk.tokens[self.read_u16(&closure)];
+					let v = self.pop();
+					return Err(Error::throw(v, t));
+				},
+				OpCode::Rethrow => {
+					let Some(e) = self.unwinding.pop() else {
+						return Err(Error::fatal("nothing to rethrow", None));
+					};
+					return Err(e);
+				}
+			}
+		}
+	}
+
+	fn push_frame(&mut self, f: &Rc<Closure>, argc: usize, t: &Token) -> Result<(), Error> {
+		if argc != f.proto.arity {
+			return Err(Error::fatal(format!("{f:?} expects {} arguments, got {argc}", f.proto.arity).as_str(), Some(t)).of(ErrorKind::Arity));
+		}
+
+		if self.frames.len() > self.max_depth {
+			return Err(builtins::stack_overflow(t, self.max_depth));
+		}
+
+		let base = self.stack.len() - 1 - argc;
+		self.frames.push(CallFrame { closure: f.clone(), ip: 0, base });
+		Ok(())
+	}
+
+	fn capture_upvalue(&mut self, slot: usize) -> UpvalueRef {
+		for upvalue in &self.open_upvalues {
+			if let Upvalue::Open(s) = &*upvalue.borrow() {
+				if *s == slot {
+					return upvalue.clone();
+				}
+			}
+		}
+
+		let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+		gc::track(Object::Upvalue(upvalue.clone()));
+		self.open_upvalues.push(upvalue.clone());
+		upvalue
+	}
+
+	// Moves every variable living at or above `from` off the stack into its upvalue
+	fn close_upvalues(&mut self, from: usize) {
+		let stack = &self.stack;
+		self.open_upvalues.retain(|upvalue| {
+			let mut upvalue = upvalue.borrow_mut();
+			match &*upvalue {
+				Upvalue::Open(slot) if *slot >= from => {
+					*upvalue = Upvalue::Closed(stack[*slot].clone());
+					false
+				},
+				_ => true
+			}
+		});
+	}
+
+	fn operate(op: OpCode, v1: Literal, v2: Literal) -> Result<Literal, Error> {
+		match op {
+			OpCode::Add => Literal::sum(v1, v2),
+			OpCode::Sub => Literal::sub(v1, v2),
+			OpCode::Mul => Literal::mul(v1, v2),
+			OpCode::Div => Literal::div(v1, v2),
+			OpCode::Rem => Literal::rem(v1, v2),
+			OpCode::Equal => Literal::eq(v1, v2),
+			OpCode::NotEqual => Ok(Literal::Bool(!Literal::is_true_val(Literal::eq(v1, v2)?)?)),
+			OpCode::Greater => Literal::gt(v1, v2),
+			OpCode::GreaterEqual => Literal::egt(v1, v2),
+			OpCode::Less => Literal::lt(v1, v2),
+			OpCode::LessEqual => Literal::elt(v1, v2),
+			_ => Err(Error::fatal(format!("unexpected opcode {op:?} in binary!").as_str(), None))
+		}
+	}
+
+	fn step(direction: u8, v: &Literal, t: &Token) -> Result<Literal, Error> {
+		let Literal::Float(v) = v else {
+			return Err(Error::fatal("cannot increment non-number", Some(t)).of(ErrorKind::Type))
+		};
+
+		match direction {
+			0 => Ok(Literal::Float(v + 1.0)),
+			_ => Ok(Literal::Float(v - 1.0))
+		}
+	}
+}
+
+impl Caller for Vm {
+	fn call_value(&mut self, callee: &Literal, t: &Token, args: Vec<Literal>) -> Result<Literal, Error> {
+		match callee {
+			Literal::Native(native) => builtins::call_native(self, native, t, args),
+			Literal::Compiled(f) => {
+				let base = self.stack.len();
+				let argc = args.len();
+				self.stack.push(callee.clone());
+				self.stack.extend(args);
+				self.push_frame(f, argc, t).inspect_err(|_| self.stack.truncate(base))?;
+
+				let depth = self.frames.len();
+				self.run(depth).inspect_err(|_| {
+					self.frames.truncate(depth - 1);
+					self.close_upvalues(base);
+					self.stack.truncate(base);
+				})
+			},
+			_ => Err(Error::fatal("can only call functions", Some(t)).of(ErrorKind::Type))
+		}
+	}
+
+	fn allows(&self, capability: Capability) -> bool {
+		self.capabilities.allows(capability)
+	}
+}
@@ -1,21 +1,160 @@
-mod lll;
-mod lang;
-use lang::*;
+use lll::lang::*;
+use lll::lll::lint::Lint;
+use lll::lll::lint::Level;
+use lll::lll::budget;
+use lll::lll::capability::Capability;
+
+// counts heap bytes, for `--max-memory`
+#[global_allocator]
+static ALLOCATOR: budget::Counting = budget::Counting;
 
 // Incremental error system, where I just add a &str every error and continue parse [LOL I DIDN'T DO THAT YET]
 fn main() -> std::process::ExitCode {
 	let args: Vec<_> = std::env::args().collect();
 
-	if args.len() > 2 {
-		eprintln!("USE: ./lll [source file].");
+	if args.get(1).is_some_and(|arg| arg == "fmt") {
+		return format(&args[2..]);
+	}
+	if args.get(1).is_some_and(|arg| arg == "lsp") {
+		return if serve_lsp() { std::process::ExitCode::SUCCESS } else { std::process::ExitCode::FAILURE };
+	}
+
+	// `lll run` takes the same flags, but the file comes from lll.toml
+	let project = args.get(1).is_some_and(|arg| arg == "run");
+
+	let mut options = Options::default();
+	let mut files = Vec::new();
+	// the program from `-e`
+	let mut eval = None;
+	let mut args_iter = args[if project { 2 } else { 1 }..].iter();
+	while let Some(arg) = args_iter.next() {
+		match arg.as_str() {
+			"--deny" | "--allow" => {
+				let level = if arg == "--deny" { Level::Deny } else { Level::Allow };
+				let name = args_iter.next().map(String::as_str);
+				match name.and_then(Lint::from_name) {
+					Some(lint) => options.lints.set(lint, level),
+					None if name == Some("all") => options.lints.set_all(level),
+					None => {
+						let names: Vec<_> = Lint::ALL.iter().map(Lint::name).collect();
+						eprintln!("FATAL: unknown lint {}, expected all or one of {}", name.unwrap_or("(none)"), names.join(", "));
+						return std::process::ExitCode::FAILURE;
+					}
+				}
+			},
+			"--allow-cap" | "--deny-cap" => {
+				let allowed = arg == "--allow-cap";
+				let name = args_iter.next().map(String::as_str);
+				match name.and_then(Capability::from_name) {
+					Some(capability) => options.capabilities.set(capability, allowed),
+					None if name == Some("all") => options.capabilities.set_all(allowed),
+					None => {
+						let names: Vec<_> = Capability::ALL.iter().map(Capability::name).collect();
+						eprintln!("FATAL: unknown capability {}, expected all or one of {}", name.unwrap_or("(none)"), names.join(", "));
+						return std::process::ExitCode::FAILURE;
+					}
+				}
+			},
+			"--lib-dir" => match args_iter.next() {
+				Some(dir) => options.lib_dirs.push(std::path::PathBuf::from(dir)),
+				None => {
+					eprintln!("FATAL: --lib-dir expects a directory");
+					return std::process::ExitCode::FAILURE;
+				}
+			},
+			"--max-depth" => match args_iter.next().and_then(|n| n.parse().ok()) {
+				Some(n) => options.max_depth = n,
+				None => {
+					eprintln!("FATAL: --max-depth expects a number of calls");
+					return std::process::ExitCode::FAILURE;
+				}
+			},
+			"--max-steps" | "--timeout" | "--max-memory" => {
+				let Some(n) = args_iter.next().and_then(|n| n.parse::<u64>().ok()) else {
+					eprintln!("FATAL: {arg} expects a number");
+					return std::process::ExitCode::FAILURE;
+				};
+				match arg.as_str() {
+					"--max-steps" => options.limits.steps = Some(n),
+					"--timeout" => options.limits.time = Some(std::time::Duration::from_millis(n)),
+					_ => options.limits.memory = Some(n as usize * 1024 * 1024)
+				}
+			},
+			// like a file, everything after the program is for the program
+			"-e" | "--eval" if !project => match args_iter.next() {
+				Some(source) => {
+					eval = Some(source.clone());
+					options.args = args_iter.by_ref().cloned().collect();
+				},
+				None => {
+					eprintln!("FATAL: {arg} expects a program");
+					return std::process::ExitCode::FAILURE;
+				}
+			},
+			"--vm" => options.backend = Backend::Vm,
+			"--dump-tokens" => options.dump_tokens = true,
+			"--dump-ast" => options.dump_ast = true,
+			"--dump-bytecode" => options.dump_bytecode = true,
+			"--gc-stress" => options.gc_stress = true,
+			// everything after the script is for the script
+			_ if !project => {
+				files.push(arg);
+				options.args = args_iter.by_ref().cloned().collect();
+			},
+			_ => files.push(arg)
+		}
+	}
+
+	if files.len() > 1 || project && !files.is_empty() {
+		eprintln!("USE: ./lll [--vm] [--dump-tokens] [--dump-ast] [--dump-bytecode] [--gc-stress] [--deny lint] [--allow lint] [--lib-dir dir] [--max-depth calls]");
+		eprintln!("            [--max-steps steps] [--timeout ms] [--max-memory mb] [--allow-cap capability] [--deny-cap capability] [source file [args...]].");
+		eprintln!("     ./lll [flags] -e|--eval program [args...], runs the program given inline, lints only if denied.");
+		eprintln!("     ./lll [flags] - [args...], runs the program read from stdin.");
+		eprintln!("     ./lll run [flags], runs the entry of lll.toml in this directory or a parent.");
+		eprintln!("     ./lll fmt [--check] [source files].");
+		eprintln!("     ./lll lsp");
 		eprintln!("INFO: provided args {args:?}");
-		return std::process::ExitCode::FAILURE;
-	} else if args.len() == 2 {
-		let path = std::path::PathBuf::from(&args[1]);
-		run_file(&path);
-		return std::process::ExitCode::SUCCESS;
+		std::process::ExitCode::FAILURE
+	} else if project {
+		let dir = std::env::current_dir().unwrap_or_default();
+		exit_code(run_project(&dir, &options))
+	} else if eval.is_some() || files.first().is_some_and(|file| *file == "-") {
+		// nobody reads a one-off program back, so only the lints asked to deny have a say
+		for lint in Lint::ALL {
+			if options.lints.level(lint) == Level::Warn {
+				options.lints.set(lint, Level::Allow);
+			}
+		}
+		match eval {
+			Some(source) => exit_code(run(source, None, &options)),
+			None => exit_code(run_stdin(&options))
+		}
+	} else if let Some(file) = files.first() {
+		let path = std::path::PathBuf::from(file);
+		exit_code(run_file(&path, &options))
+	} else {
+		exit_code(run_interactive(options))
+	}
+}
+
+// Like a shell, only the low byte of what the script passed to `exit` is kept
+fn exit_code(outcome: Outcome) -> std::process::ExitCode {
+	std::process::ExitCode::from((outcome.code() & 0xff) as u8)
+}
+
+fn format(args: &[String]) -> std::process::ExitCode {
+	let check = args.iter().any(|arg| arg == "--check");
+	let files: Vec<_> = args.iter().filter(|arg| *arg != "--check").map(std::path::PathBuf::from).collect();
+
+	let ok = if files.is_empty() {
+		format_stdin(check)
+	} else {
+		format_files(&files, check)
+	};
+
+	if ok {
+		std::process::ExitCode::SUCCESS
 	} else {
-		run_interactive();
-		return std::process::ExitCode::SUCCESS;
+		std::process::ExitCode::FAILURE
 	}
 }
@@ -0,0 +1,80 @@
+// Built-ins that reach outside of the script only run with the capability they need
+mod common;
+
+use std::process::Command;
+
+use lll::lang::Engine;
+use lll::lll::capability::Capability;
+
+use common::Sandbox;
+
+// stdout of `files` with the first one run, the same on both backends
+fn run(name: &str, flags: &[&str], files: &[(&str, &str)]) -> String {
+	let args: Vec<_> = flags.iter().chain(&[files[0].0]).copied().collect();
+	Sandbox::with_files(name, files).both(&args).stdout
+}
+
+const CLOCK: &str = "\
+try {
+	print clock() > 0;
+} catch (e) {
+	print e.kind + \": \" + e.message;
+}
+";
+
+#[test]
+fn denied_calls_are_errors() {
+	assert_eq!(run("default", &[], &[("main.lll", CLOCK)]), "true");
+
+	let denied = "denied: clock needs the time capability, which is denied";
+	assert_eq!(run("denied", &["--deny-cap", "time"], &[("main.lll", CLOCK)]), denied);
+	assert_eq!(run("none", &["--deny-cap", "all"], &[("main.lll", CLOCK)]), denied);
+	// later flags win
+	assert_eq!(run("allowed", &["--deny-cap", "all", "--allow-cap", "time"], &[("main.lll", CLOCK)]), "true");
+
+	// uncaught, like any other error
+	let out = run("uncaught", &["--deny-cap", "time"], &[("main.lll", "print clock();\nprint \"after\";\n")]);
+	assert_eq!(out, "FATAL: clock needs the time capability, which is denied, line 1, token LeftParen\nafter");
+}
+
+#[test]
+fn modules_get_the_same_capabilities() {
+	let out = run("module", &["--deny-cap", "time"], &[
+		("main.lll", "import \"m.lll\";\n"),
+		("m.lll", "print clock();\n")
+	]);
+	assert!(out.starts_with("FATAL: clock needs the time capability, which is denied in m.lll:1"), "{out}");
+}
+
+#[test]
+fn file_imports_need_fs_read() {
+	let files = [
+		("main.lll", "import \"fs\" as fs;\nimport \"m.lll\" as m;\nprint m.secret;\nprint \"after\";\n"),
+		("m.lll", "export new secret = \"s3\";\n")
+	];
+	assert_eq!(run("import", &[], &files), "s3after");
+
+	// nothing about the file gets out, the built-in module still loads
+	let out = run("import-denied", &["--deny-cap", "fs-read"], &files);
+	assert!(out.starts_with("FATAL: import of m.lll needs the fs-read capability, which is denied, line 2, token Import\n"), "{out}");
+	assert!(!out.contains("s3") && out.ends_with("after"), "{out}");
+	let out = run("import-missing", &["--deny-cap", "fs-read"], &[("main.lll", "import \"/no/such/file.lll\";\n")]);
+	assert_eq!(out, "FATAL: import of /no/such/file.lll needs the fs-read capability, which is denied, line 1, token Import\n");
+}
+
+#[test]
+fn unknown_capability() {
+	let output = Command::new(env!("CARGO_BIN_EXE_lll")).args(["--deny-cap", "network"]).output().unwrap();
+	assert!(!output.status.success());
+	assert!(String::from_utf8_lossy(&output.stderr).contains("unknown capability network, expected all or one of fs-read, fs-write, env, time, process, stdin"));
+}
+
+#[test]
+fn engine_builder() {
+	let engine = Engine::new().deny_all().allow(Capability::Time).allow(Capability::Env).deny(Capability::Env);
+	let capabilities = engine.options().capabilities;
+	assert!(capabilities.allows(Capability::Time));
+	assert!(Capability::ALL.iter().filter(|c| **c != Capability::Time).all(|c| !capabilities.allows(*c)));
+
+	assert!(Capability::ALL.iter().all(|c| Engine::new().options().capabilities.allows(*c)));
+}
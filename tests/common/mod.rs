@@ -0,0 +1,90 @@
+// What the tests that run the lll binary share: a directory of their own for the files,
+// both backends run on them and compared, and the directory gone again afterwards.
+// Every test crate uses only some of it.
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+pub struct Run {
+	pub stdout: String,
+	pub stderr: String,
+	pub code: Option<i32>
+}
+
+// A directory under the temp directory, removed when it goes out of scope, also when an
+// assert fails first
+pub struct Sandbox {
+	pub dir: PathBuf
+}
+
+impl Sandbox {
+	pub fn new(name: &str) -> Self {
+		let dir = std::env::temp_dir().join(format!("lll-{}-{name}-{}", env!("CARGO_CRATE_NAME"), std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		Self { dir }
+	}
+
+	pub fn with_files(name: &str, files: &[(&str, &str)]) -> Self {
+		let sandbox = Self::new(name);
+		for (path, text) in files {
+			sandbox.write(path, text);
+		}
+		sandbox
+	}
+
+	// `path` is relative to the sandbox, missing directories on the way are made
+	pub fn write(&self, path: &str, text: &str) {
+		let path = self.dir.join(path);
+		std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+		std::fs::write(path, text).unwrap();
+	}
+
+	// lll started in the sandbox, with no `LLL_PATH` from whoever runs the tests
+	pub fn command(&self) -> Command {
+		let mut command = Command::new(env!("CARGO_BIN_EXE_lll"));
+		command.current_dir(&self.dir).env_remove("LLL_PATH");
+		command
+	}
+
+	// lll with `args` on both backends, which have to agree on everything
+	pub fn both(&self, args: &[&str]) -> Run {
+		self.both_with(args, "", |_| ())
+	}
+
+	// The same with `stdin` piped in and `setup` done to the command first
+	pub fn both_with(&self, args: &[&str], stdin: &str, setup: impl Fn(&mut Command)) -> Run {
+		let run = |backend: &[&str]| {
+			let mut command = self.command();
+			setup(&mut command);
+			let mut child = command
+				.args(backend)
+				.args(args)
+				.stdin(Stdio::piped())
+				.stdout(Stdio::piped())
+				.stderr(Stdio::piped())
+				.spawn()
+				.unwrap();
+			// a script that never reads stdin may be gone before it is written
+			let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+			let output = child.wait_with_output().unwrap();
+			Run {
+				stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+				stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+				code: output.status.code()
+			}
+		};
+		let tree = run(&[]);
+		let vm = run(&["--vm"]);
+		assert_eq!((&vm.stdout, &vm.stderr, vm.code), (&tree.stdout, &tree.stderr, tree.code));
+		tree
+	}
+}
+
+impl Drop for Sandbox {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_dir_all(&self.dir);
+	}
+}
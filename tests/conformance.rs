@@ -0,0 +1,42 @@
+// Every tests/scripts/*.lll is run by both backends and compared against the .out next to it
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn scripts() -> Vec<PathBuf> {
+	let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("scripts");
+	let mut scripts: Vec<PathBuf> = std::fs::read_dir(dir).unwrap()
+		.map(|entry| entry.unwrap().path())
+		.filter(|path| path.extension().is_some_and(|ext| ext == "lll"))
+		.collect();
+	scripts.sort();
+	scripts
+}
+
+fn check(flags: &[&str]) {
+	for script in scripts() {
+		let expected = std::fs::read_to_string(script.with_extension("out")).unwrap();
+		let output = Command::new(env!("CARGO_BIN_EXE_lll")).args(flags).arg(&script).output().unwrap();
+		assert_eq!(String::from_utf8_lossy(&output.stdout), expected, "{} with {flags:?}", script.display());
+	}
+}
+
+#[test]
+fn tree_walker() {
+	check(&[]);
+}
+
+#[test]
+fn bytecode_vm() {
+	check(&["--vm"]);
+}
+
+#[test]
+fn tree_walker_gc_stress() {
+	check(&["--gc-stress"]);
+}
+
+#[test]
+fn bytecode_vm_gc_stress() {
+	check(&["--vm", "--gc-stress"]);
+}
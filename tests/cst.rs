@@ -0,0 +1,42 @@
+// The concrete syntax tree has to give back the exact source, whatever is in it
+use std::path::Path;
+
+use lll::lll::cst;
+
+fn round_trip(source: &str) {
+	assert_eq!(cst::parse(source).text(), source);
+}
+
+#[test]
+fn scripts() {
+	let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("scripts");
+	for entry in std::fs::read_dir(dir).unwrap() {
+		let path = entry.unwrap().path();
+		if path.extension().is_some_and(|ext| ext == "lll") {
+			round_trip(&std::fs::read_to_string(&path).unwrap());
+		}
+	}
+}
+
+#[test]
+fn trivia() {
+	round_trip("");
+	round_trip("  \n\t// only a comment");
+	round_trip("new a = 1; // trailing\r\n/// doc\nfun f(x) { /* inside */ return x; }\n\n");
+	round_trip("/* outer /* nested */ still outer */ print 1;");
+}
+
+#[test]
+fn broken_source() {
+	round_trip("print \"unterminated");
+	round_trip("new = ; } ) ] fun ( { [ 1, 2 $ @");
+	round_trip("/* never closed");
+	round_trip("if (a { while } else for (;;");
+}
+
+#[test]
+fn trailing_trivia_stays_on_the_line() {
+	let tokens = cst::tokens("a; // one\n// two\nb;");
+	assert_eq!(tokens[1].trailing.iter().map(|t| t.text.as_str()).collect::<String>(), " // one");
+	assert_eq!(tokens[2].leading.iter().map(|t| t.text.as_str()).collect::<String>(), "\n// two\n");
+}
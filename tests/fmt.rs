@@ -0,0 +1,113 @@
+// `lll fmt` has to be idempotent, keep every comment and never change what a program means
+mod common;
+
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use lll::lll::cst;
+use lll::lll::cst::TriviaKind;
+use lll::lll::fmt;
+
+use common::Sandbox;
+
+fn comments(source: &str) -> Vec<String> {
+	let mut comments: Vec<String> = cst::tokens(source).iter()
+		.flat_map(|t| t.leading.iter().chain(t.trailing.iter()))
+		.filter(|t| !matches!(t.kind, TriviaKind::Whitespace | TriviaKind::Newline))
+		.map(|t| t.text.clone())
+		.collect();
+	comments.sort();
+	comments
+}
+
+// `format` itself refuses output that parses differently, so equivalence is checked there
+fn round_trip(source: &str) -> String {
+	let formatted = fmt::format(source).unwrap();
+	assert_eq!(fmt::format(&formatted).unwrap(), formatted, "not idempotent:\n{formatted}");
+	assert_eq!(comments(&formatted), comments(source));
+	formatted
+}
+
+fn scripts() -> Vec<PathBuf> {
+	let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("scripts");
+	std::fs::read_dir(dir).unwrap()
+		.map(|entry| entry.unwrap().path())
+		.filter(|path| path.extension().is_some_and(|ext| ext == "lll"))
+		.collect()
+}
+
+#[test]
+fn scripts_round_trip() {
+	for script in scripts() {
+		round_trip(&std::fs::read_to_string(script).unwrap());
+	}
+}
+
+#[test]
+fn canonical_layout() {
+	let source = "fun   add(a,b){return a+b;}   // sum\nnew xs=[1,2,3,];\nnew m={\"a\":1};\nfor(new i=0;i<3;i++){if(i==1)continue;else print i;}\nif (xs) { print -(-1); } else print 2;\nwhile(false){}\n";
+	let expected = "fun add(a, b) {\n\treturn a + b;\n} // sum\nnew xs = [1, 2, 3];\nnew m = {\"a\": 1};\nfor (new i = 0; i < 3; i++) {\n\tif (i == 1) continue;\n\telse print i;\n}\nif (xs) {\n\tprint -(-1);\n} else print 2;\nwhile (false) {}\n";
+	assert_eq!(round_trip(source), expected);
+}
+
+#[test]
+fn comments_and_blank_lines() {
+	let source = "/* header */\n\n\n/// Doubles.\nfun double(x) {\n\n  // why\n  return x * 2; /* inline */\n  // last\n}\nprint double(1, // one\n  2);\n";
+	let expected = "/* header */\n\n/// Doubles.\nfun double(x) {\n\t// why\n\treturn x * 2; /* inline */\n\t// last\n}\nprint double(1, // one\n\t2);\n";
+	assert_eq!(round_trip(source), expected);
+}
+
+#[test]
+fn lambdas() {
+	let source = "print map([1], fun (x) { return x; });\nprint map([1], (x)=>x+1);\n";
+	let expected = "print map([1], fun (x) {\n\treturn x;\n});\nprint map([1], (x) => x + 1);\n";
+	assert_eq!(round_trip(source), expected);
+}
+
+#[test]
+fn long_chains() {
+	// flat code however long, formatting it must not go one level deeper per link
+	let sum = format!("print {};\n", vec!["1"; 30_000].join(" + "));
+	assert_eq!(round_trip(&sum), sum);
+	let calls = format!("print f{};\n", "(x)[0].y".repeat(10_000));
+	assert_eq!(round_trip(&calls), calls);
+	let arms: Vec<String> = (0..10_000).map(|i| format!("if (x == {i}) print {i};\n")).collect();
+	let chain = arms.join("else ") + "else print x;\n";
+	assert_eq!(round_trip(&chain), chain);
+}
+
+#[test]
+fn syntax_errors_are_refused() {
+	assert!(fmt::format("print (1;").is_err());
+	assert!(fmt::format("print \"open").is_err());
+}
+
+#[test]
+fn check_flag() {
+	let sandbox = Sandbox::with_files("check", &[("tidy.lll", "print 1 + 2;\n"), ("messy.lll", "print 1+2;")]);
+	let messy = sandbox.dir.join("messy.lll");
+
+	let lll = |args: &[&str]| sandbox.command().arg("fmt").args(args).output().unwrap();
+	assert!(lll(&["--check", "tidy.lll"]).status.success());
+	assert!(!lll(&["--check", "messy.lll"]).status.success());
+	assert_eq!(std::fs::read_to_string(&messy).unwrap(), "print 1+2;");
+
+	assert!(lll(&["messy.lll"]).status.success());
+	assert_eq!(std::fs::read_to_string(&messy).unwrap(), "print 1 + 2;\n");
+
+	// without files it checks stdin
+	let stdin = |args: &[&str], text: &str| {
+		let mut child = sandbox.command().arg("fmt").args(args)
+			.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+		child.stdin.take().unwrap().write_all(text.as_bytes()).unwrap();
+		child.wait_with_output().unwrap()
+	};
+	let output = stdin(&["--check"], "print   1;\n");
+	assert_eq!((String::from_utf8_lossy(&output.stdout).as_ref(), output.status.success()), ("stdin is not formatted\n", false));
+	let output = stdin(&["--check"], "print 1;\n");
+	assert_eq!((String::from_utf8_lossy(&output.stdout).as_ref(), output.status.success()), ("", true));
+	let output = stdin(&[], "print   1;\n");
+	assert_eq!((String::from_utf8_lossy(&output.stdout).as_ref(), output.status.success()), ("print 1;\n", true));
+}
@@ -0,0 +1,127 @@
+// The fs module, run inside a directory of its own on both backends
+mod common;
+
+use common::Sandbox;
+
+// stdout of `source` run from the sandbox, the same on both backends. Scripts clean up
+// after themselves so the second backend starts from the same files.
+fn run(sandbox: &Sandbox, flags: &[&str], source: &str) -> String {
+	sandbox.write("main.lll", source);
+	let args: Vec<_> = flags.iter().chain(&["main.lll"]).copied().collect();
+	sandbox.both(&args).stdout
+}
+
+#[test]
+fn files_and_directories() {
+	let sandbox = Sandbox::new("files");
+	let out = run(&sandbox, &[], "\
+import \"fs\" as fs;
+fs.mkdir(\"out/deeper\");
+fs.mkdir(\"out/deeper\");
+fs.write_text(\"out/notes.txt\", \"first\\nsecond\");
+fs.append_text(\"out/notes.txt\", \"\\nthird\\n\");
+fs.append_text(\"out/new.txt\", \"made\");
+print fs.read_lines(\"out/notes.txt\");
+print \"\\n\";
+print len(fs.read_text(\"out/notes.txt\"));
+print \"\\n\";
+print fs.list_dir(\"out\");
+print \"\\n\";
+print fs.exists(\"out/new.txt\");
+fs.remove_path(\"out/new.txt\");
+fs.remove_path(\"out/notes.txt\");
+fs.remove_path(\"out/deeper\");
+fs.remove_path(\"out\");
+print fs.exists(\"out\");
+print \"\\n\";
+");
+	assert_eq!(out, "[\"first\", \"second\", \"third\"]\n19\n[\"deeper\", \"new.txt\", \"notes.txt\"]\ntruefalse\n");
+}
+
+#[test]
+fn text_is_written_as_it_is() {
+	let sandbox = Sandbox::new("verbatim");
+	sandbox.write("source.txt", "a \\n stays\n");
+	let out = run(&sandbox, &[], "\
+import \"fs\" as fs;
+new text = fs.read_text(\"source.txt\");
+fs.write_text(\"copy.txt\", text);
+fs.append_text(\"copy.txt\", text);
+print len(text);
+print [text];
+");
+	assert_eq!(out, "11[\"a \\n stays\\n\"]");
+	assert_eq!(std::fs::read_to_string(sandbox.dir.join("copy.txt")).unwrap(), "a \\n stays\na \\n stays\n");
+}
+
+#[test]
+fn failures_are_io_errors() {
+	let sandbox = Sandbox::new("failures");
+	let out = run(&sandbox, &[], "\
+import \"fs\" as fs;
+try { fs.read_text(\"missing.txt\"); } catch (e) { print e.kind + \": \" + e.message + \"\\n\"; }
+fs.mkdir(\"full/inside\");
+try { fs.remove_path(\"full\"); } catch (e) { print e.kind + \"\\n\"; }
+try { fs.list_dir(\"main.lll\"); } catch (e) { print e.kind + \"\\n\"; }
+try { fs.write_text(\"full\", \"x\"); } catch (e) { print e.kind + \"\\n\"; }
+try { fs.write_text(\"x.txt\", 1); } catch (e) { print e.kind + \": \" + e.message + \"\\n\"; }
+fs.remove_path(\"full/inside\");
+fs.remove_path(\"full\");
+fs.read_lines(\"missing.txt\");
+");
+	assert_eq!(out, "\
+io: read_text missing.txt: No such file or directory
+io
+io
+io
+type: write_text expects a string
+FATAL: read_lines missing.txt: No such file or directory, line 10, token LeftParen
+");
+}
+
+#[test]
+fn reading_and_writing_are_capabilities() {
+	let sandbox = Sandbox::new("capabilities");
+	let source = "\
+import \"fs\";
+try { print exists(\"main.lll\"); } catch (e) { print e.message; }
+print \"\\n\";
+try { mkdir(\"made\"); print \"made\"; } catch (e) { print e.message; }
+print \"\\n\";
+if (exists(\"made\")) remove_path(\"made\");
+";
+	assert_eq!(run(&sandbox, &[], source), "true\nmade\n");
+	assert_eq!(run(&sandbox, &["--deny-cap", "fs-write"], source), "true\nmkdir needs the fs-write capability, which is denied\n");
+	assert_eq!(run(&sandbox, &["--deny-cap", "fs-read"], source), "\
+exists needs the fs-read capability, which is denied
+made
+FATAL: exists needs the fs-read capability, which is denied, line 6, token LeftParen
+");
+	std::fs::remove_dir(sandbox.dir.join("made")).unwrap();
+}
+
+#[test]
+fn bare_import_keeps_the_list_builtins() {
+	let sandbox = Sandbox::new("bare");
+	let out = run(&sandbox, &[], "\
+import \"fs\";
+new xs = [1, 2, 3];
+remove(xs, 0);
+print xs;
+write_text(\"list.txt\", \"written\");
+print read_text(\"list.txt\");
+remove_path(\"list.txt\");
+print exists(\"list.txt\");
+new m = {\"a\": 1};
+remove(m, \"a\");
+print len(m);
+");
+	assert_eq!(out, "[2, 3]writtenfalse0");
+}
+
+#[test]
+fn builtin_modules_come_first() {
+	let sandbox = Sandbox::new("shadowed");
+	sandbox.write("fs", "export new shadowed = true;\n");
+	assert_eq!(run(&sandbox, &[], "import \"fs\" as fs;\nprint has(fs, \"shadowed\");\nprint has(fs, \"read_text\");\n"), "falsetrue");
+}
@@ -0,0 +1,245 @@
+// How deep scripts can go, in calls and in nesting, and the budgets they run on, on both
+// backends
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use lll::lll::budget;
+use lll::lll::budget::Limits;
+use lll::lll::compiler::Compiler;
+use lll::lll::interpreter::Interpreter;
+use lll::lll::lexer::Lexer;
+use lll::lll::parse::Parser;
+use lll::lll::token::Literal;
+use lll::lll::token::Token;
+use lll::lll::token::TokenType;
+use lll::lll::vm::Vm;
+
+use common::Run;
+use common::Sandbox;
+
+#[global_allocator]
+static ALLOCATOR: budget::Counting = budget::Counting;
+
+// `files` written to a directory of their own, the first one run with `flags` on both
+// backends, which have to agree
+fn lll(name: &str, flags: &[&str], files: &[(&str, &str)]) -> Run {
+	let args: Vec<_> = flags.iter().chain(&[files[0].0]).copied().collect();
+	Sandbox::with_files(name, files).both(&args)
+}
+
+// stdout of a script that isn't stopped by a limit, errors in it only end their statement
+fn run(name: &str, flags: &[&str], source: &str) -> String {
+	let run = lll(name, flags, &[("main.lll", source)]);
+	assert!(run.stderr.is_empty(), "{}", run.stderr);
+	run.stdout
+}
+
+// stdout and stderr of a script that is
+fn limited(name: &str, flags: &[&str], files: &[(&str, &str)]) -> (String, String) {
+	let run = lll(name, flags, files);
+	(run.stdout, run.stderr)
+}
+
+const RECURSION: &str = "\
+fun forever(n) {
+	return forever(n + 1);
+}
+try {
+	forever(0);
+} catch (e) {
+	print e.kind + \": \" + e.message + \"\\n\";
+}
+
+fun count(n) {
+	if (n == 0) return 0;
+	return 1 + count(n - 1);
+}
+print count(40);
+print \"\\n\";
+
+fun ping(n) { return pong(n + 1); }
+fun pong(n) { return ping(n + 1); }
+try { ping(0); } catch (e) { print e.kind + \"\\n\"; }
+fun nest(n) { return map([n], nest); }
+try { nest(0); } catch (e) { print e.kind + \"\\n\"; }
+
+fun probe(n) {
+	try {
+		return probe(n + 1);
+	} catch (e) {
+		return n;
+	}
+}
+print probe(0);
+print \"\\n\";
+
+fun down(n) {
+	return down(n);
+}
+down(1);
+print \"after\\n\";
+";
+
+#[test]
+fn stack_overflow_is_catchable() {
+	let out = run("overflow", &["--max-depth", "50"], RECURSION);
+	assert_eq!(out, "\
+recursion: stack overflow, more than 50 calls deep
+40
+recursion
+recursion
+49
+FATAL: stack overflow, more than 50 calls deep, line 34, token LeftParen
+    in <fn down> called on line 34
+    ... 48 more of the same
+    in <fn down> called on line 36
+after
+");
+	// the same script under gc stress
+	assert_eq!(run("overflow-gc", &["--max-depth", "50", "--gc-stress"], RECURSION), out);
+}
+
+#[test]
+fn default_and_raised_depth() {
+	let source = "fun count(n) {\n\tif (n == 0) return 0;\n\treturn 1 + count(n - 1);\n}\nprint count(999);\nprint count(1000);\n";
+	let out = run("default", &[], source);
+	assert!(out.starts_with("999FATAL: stack overflow, more than 1000 calls deep, line 3"), "{out}");
+
+	// deeper than the native stack of the main thread would allow the tree walker
+	let source = "fun count(n) {\n\tif (n == 0) return 0;\n\treturn 1 + count(n - 1);\n}\nprint count(49999);\n";
+	assert_eq!(run("raised", &["--max-depth", "50000"], source), "49999");
+}
+
+#[test]
+fn nesting() {
+	let parens = |n: usize| format!("print {}1{};\n", "(".repeat(n), ")".repeat(n));
+	let blocks = |n: usize| format!("{}print 2;{}\n", "{".repeat(n), "}".repeat(n));
+	let ok = format!("{}{}", parens(250), blocks(250));
+	assert_eq!(run("nesting-ok", &[], &ok), "12");
+
+	let deep = format!("{}{}print 3;\n", parens(100_000), blocks(100_000));
+	let out = run("nesting-deep", &[], &deep);
+	assert!(out.starts_with("FATAL: nested more than 256 levels deep, line 1, token LeftParen\nFATAL: nested more than 256 levels deep, line 2, token LeftBrace\n"), "{out}");
+	// the parser gets back on track for the statements after
+	assert!(out.ends_with('3'), "{out}");
+
+	// a long chain of operators or of `else if`s is flat code, it's not nesting however long it is
+	let sum = format!("print {};\n", vec!["1"; 50_000].join(" + "));
+	assert_eq!(run("nesting-sum", &[], &sum), "50000");
+	let concat = format!("print {};\n", vec!["\"a\""; 300].join(" + "));
+	assert_eq!(run("nesting-concat", &[], &concat), "a".repeat(300));
+	let logical = format!("print {} or true;\n", vec!["false"; 100_000].join(" or "));
+	assert_eq!(run("nesting-logical", &[], &logical), "true");
+	let index = format!("new l = [0];\nl[0] = l;\nprint l{} == l;\n", "[0]".repeat(50_000));
+	assert_eq!(run("nesting-index", &[], &index), "true");
+	assert_eq!(run("nesting-else-if", &[], &else_ifs(2_000)), "1999 none");
+}
+
+// `if` with `n - 1` `else if`s after it and an `else`, run for the last one and for none
+fn else_ifs(n: usize) -> String {
+	let arms: Vec<String> = (0..n).map(|i| format!("if (x == {i}) print {i};\n")).collect();
+	let chain = arms.join("else ") + "else print \" none\";\n";
+	format!("new x = {};\n{chain}x = -1;\n{chain}", n - 1)
+}
+
+#[test]
+fn string_constants_are_stored_once() {
+	// more of them than a chunk has room for, unless they share one
+	let source = format!("print {};\n", vec!["\"ab\""; 70_000].join(" + "));
+	assert_eq!(run("string-constants", &[], &source), "ab".repeat(70_000));
+}
+
+#[test]
+fn deep_lists_are_freed() {
+	// dropping one used to recurse once per level
+	let source = "\
+new xs = [];
+for (new i = 0; i < 300000; i++) xs = [xs];
+print \"built\";
+xs = nil;
+print \" freed\";
+";
+	assert_eq!(run("deep-collections", &[], source), "built freed");
+}
+
+const SPIN: &str = "\
+print \"start\\n\";
+try {
+	while (true) {}
+} catch (e) {
+	print \"caught\\n\";
+} finally {
+	print \"finally\\n\";
+}
+print \"never\\n\";
+";
+
+#[test]
+fn limits_end_the_run() {
+	// no catch or finally runs, and neither does the rest of the script
+	let (out, err) = limited("steps", &["--max-steps", "1000"], &[("main.lll", SPIN)]);
+	assert_eq!(out, "start\n");
+	assert_eq!(err, "FATAL: step limit of 1000 reached\n");
+
+	let (out, err) = limited("time", &["--timeout", "100"], &[("main.lll", SPIN)]);
+	assert_eq!(out, "start\n");
+	assert_eq!(err, "FATAL: time limit of 100ms reached\n");
+
+	// doubling a string allocates no objects, the heap is still counted
+	let (out, err) = limited("memory", &["--max-memory", "8"], &[("main.lll", "new s = \"ab\";\nwhile (true) s = s + s;\n")]);
+	assert_eq!(out, "");
+	assert_eq!(err, "FATAL: memory limit of 8388608 bytes reached\n");
+	let (_, err) = limited("memory-lists", &["--max-memory", "8"], &[("main.lll", "new l = [];\nwhile (true) push(l, [1, 2, 3]);\n")]);
+	assert_eq!(err, "FATAL: memory limit of 8388608 bytes reached\n");
+
+	// enough of a budget changes nothing
+	assert_eq!(run("enough", &["--max-steps", "100000", "--timeout", "10000", "--max-memory", "64"], "print 1 + 2;\n"), "3");
+}
+
+#[test]
+fn modules_share_the_budget() {
+	let (out, err) = limited("module", &["--max-steps", "1000"], &[
+		("main.lll", "print \"main\\n\";\nimport \"spin.lll\";\nprint \"after\\n\";\n"),
+		("spin.lll", "fun spin() {\n\twhile (true) {}\n}\ntry { spin(); } catch (e) { print \"caught\\n\"; }\n")
+	]);
+	assert_eq!(out, "main\n");
+	assert!(err.starts_with("FATAL: step limit of 1000 reached\n    in <fn spin> called on line 4"), "{err}");
+}
+
+fn parse(source: &str) -> Vec<lll::lll::parse::Stmt> {
+	let (mut tokens, errors) = Lexer::new(source.to_string()).scan_with_errors();
+	assert!(errors.is_empty());
+	tokens.push(Token { literal: Literal::Nil, toktype: TokenType::Eof, place: source.len(), line: 0 });
+	let (stmts, errors) = Parser::new(tokens).parse_with_errors();
+	assert!(errors.is_empty());
+	stmts
+}
+
+#[test]
+fn cancelled_from_another_thread() {
+	for vm in [false, true] {
+		let cancel = Arc::new(AtomicBool::new(false));
+		let limits = Limits { cancel: Some(cancel.clone()), ..Limits::default() };
+		let runner = std::thread::spawn(move || {
+			let stmts = parse("new i = 0;\nwhile (true) i++;\n");
+			let res = if vm {
+				let mut vm = Vm::new();
+				vm.set_limits(limits).unwrap();
+				vm.interpret(Compiler::compile(&stmts).unwrap())
+			} else {
+				let mut interpreter = Interpreter::new();
+				interpreter.set_limits(limits).unwrap();
+				interpreter.interpret(&stmts)
+			};
+			res.map_err(|e| e.to_string())
+		});
+
+		std::thread::sleep(Duration::from_millis(50));
+		cancel.store(true, Ordering::Relaxed);
+		assert_eq!(runner.join().unwrap(), Err("FATAL: cancelled".to_string()));
+	}
+}
@@ -0,0 +1,150 @@
+// Each lint fires on what it's about and stays quiet on the code next to it
+mod common;
+
+use std::process::Command;
+
+use lll::lll::lexer::Lexer;
+use lll::lll::lint;
+use lll::lll::lint::Level;
+use lll::lll::lint::Lint;
+use lll::lll::lint::Lints;
+use lll::lll::parse::Parser;
+use lll::lll::token::Literal;
+use lll::lll::token::Token;
+use lll::lll::token::TokenType;
+
+use common::Sandbox;
+
+fn lints(source: &str, config: &Lints) -> Vec<String> {
+	let (mut tokens, errors) = Lexer::new(source.to_string()).scan_with_errors();
+	assert!(errors.is_empty());
+	tokens.push(Token { literal: Literal::Nil, toktype: TokenType::Eof, place: source.len(), line: 0 });
+	let (stmts, errors) = Parser::new(tokens).parse_with_errors();
+	assert!(errors.is_empty());
+	lint::lint(&stmts, config).iter().map(|e| e.to_string()).collect()
+}
+
+fn warnings(source: &str) -> Vec<String> {
+	lints(source, &Lints::default())
+}
+
+fn only(source: &str, lint: Lint) -> Vec<String> {
+	let found = warnings(source);
+	assert!(found.iter().all(|w| w.contains(lint.name())), "{found:?}");
+	found
+}
+
+#[test]
+fn unused_variables() {
+	let found = only("new a = 1;\nnew b = 2;\nb = 3;\nnew c = 4;\nprint c;\nfun f(unused) {}\nf(1);\n", Lint::UnusedVariable);
+	assert_eq!(found.len(), 2);
+	assert!(found[0].starts_with("WARN: variable a is never read"));
+	assert!(found[1].contains("variable b"));
+
+	// a global read from a function declared before it still counts
+	assert!(warnings("fun f() { return late; }\nnew late = 1;\nprint f();\n").is_empty());
+	// so does reading the first of two declarations with the same name
+	assert!(warnings("new x = 1;\nprint x;\nnew x = 2;\nprint x;\n").is_empty());
+	// a closure in a block reads the locals declared before it, the way both backends run it
+	assert!(warnings("{\n\tnew x = 5;\n\tfun f() { return x; }\n\tprint f();\n}\n").is_empty());
+	let found = only("{\n\tfun f() { return x; }\n\tnew x = 5;\n\tprint f();\n}\n", Lint::UnusedVariable);
+	assert!(found.len() == 1 && found[0].contains("line 3"), "{found:?}");
+}
+
+#[test]
+fn shadowed_variables() {
+	let found = only("new x = 1;\n{\n\tnew x = 2;\n\tprint x;\n}\nprint x;\n", Lint::ShadowedVariable);
+	assert_eq!(found.len(), 1);
+	assert!(found[0].contains("line 3"));
+
+	// siblings and redeclarations in the same scope are not shadowing
+	assert!(warnings("{ new y = 1; print y; }\n{ new y = 2; print y; }\n").is_empty());
+	assert!(warnings("fun f(a) { return a; }\nfun g(a) { return a; }\nprint f(1) + g(2);\n").is_empty());
+}
+
+#[test]
+fn unreachable_code() {
+	let found = only("fun f() {\n\treturn 1;\n\tprint \"a\";\n\tprint f;\n}\nprint f();\n", Lint::UnreachableCode);
+	assert_eq!(found.len(), 1);
+	assert!(found[0].contains("line 2"), "{found:?}");
+
+	let found = only("new i = 0;\nwhile (i < 3) {\n\tif (i > 1) { break; } else { continue; }\n\ti = i + 1;\n}\n", Lint::UnreachableCode);
+	assert_eq!(found.len(), 1);
+	assert!(found[0].contains("line 4"), "{found:?}");
+
+	assert!(warnings("fun f(a) {\n\tif (a) return 1;\n\treturn 2;\n}\nprint f(true);\n").is_empty());
+
+	// a throw ends a function, but at the top level only its own statement
+	let found = only("fun f() {\n\tthrow 1;\n\tprint 2;\n}\nf();\n", Lint::UnreachableCode);
+	assert_eq!(found.len(), 1);
+	assert!(warnings("throw 1;\nprint 2;\n").is_empty());
+}
+
+#[test]
+fn constant_conditions() {
+	let found = only("if (!true) print 1;\nif (!nil) print 2;\nif ([]) print 3;\nif (1 < 2) print 4;\n", Lint::ConstantCondition);
+	assert_eq!(found.len(), 4);
+
+	// `while (true)` is how loops are written, and a bare `true` or `false` switches code on or off
+	assert!(warnings("new x = 1;\nif (x) print x;\nwhile (true) { break; }\nif (false) print 1;\nif (true) print 2; else if (false) print 3;\n").is_empty());
+}
+
+#[test]
+fn self_assignment() {
+	let found = only("new x = 1;\nx = x;\nprint x;\n", Lint::SelfAssignment);
+	assert_eq!(found.len(), 1);
+	assert!(warnings("new x = 1;\nx = x + 1;\nprint x;\n").is_empty());
+}
+
+#[test]
+fn mismatched_comparisons() {
+	let found = only("print 1 == \"1\";\nprint nil == nil;\nprint [] < {};\n", Lint::MismatchedComparison);
+	assert_eq!(found.len(), 3);
+	assert!(found[0].contains("comparing number with string is a type error"));
+
+	assert!(warnings("print 1 == 2;\nprint \"a\" < \"b\";\nprint 1 + \"a\";\n").is_empty());
+}
+
+#[test]
+fn levels() {
+	let source = "new unused = 1;\nif (1 < 2) print 1;\n";
+
+	let mut config = Lints::default();
+	config.set(Lint::ConstantCondition, Level::Deny);
+	config.set(Lint::UnusedVariable, Level::Allow);
+	let found = lints(source, &config);
+	assert_eq!(found.len(), 1);
+	assert!(found[0].starts_with("FATAL: condition is always the same"));
+
+	config.set_all(Level::Allow);
+	assert!(lints(source, &config).is_empty());
+}
+
+#[test]
+fn command_line() {
+	let sandbox = Sandbox::with_files("command-line", &[("script.lll", "new x = 1;\nx = x;\nprint x;\n")]);
+
+	let run = |flags: &[&str]| sandbox.command().args(flags).arg("script.lll").output().unwrap();
+
+	// warnings go to stderr and the program still runs
+	let output = run(&[]);
+	assert_eq!(String::from_utf8_lossy(&output.stdout), "1");
+	assert!(String::from_utf8_lossy(&output.stderr).contains("WARN: variable is assigned to itself [self-assignment]"));
+
+	// a denied lint stops it before it starts
+	let output = run(&["--deny", "self-assignment"]);
+	assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+	assert!(String::from_utf8_lossy(&output.stderr).contains("FATAL: variable is assigned to itself"));
+
+	let output = run(&["--allow", "all"]);
+	assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+	// a program from `-e` only hears about what it denies
+	let output = Command::new(env!("CARGO_BIN_EXE_lll")).args(["-e", "new y = 1; y = y;"]).output().unwrap();
+	assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+	let output = Command::new(env!("CARGO_BIN_EXE_lll")).args(["--deny", "self-assignment", "-e", "new y = 1; y = y;"]).output().unwrap();
+	assert!(String::from_utf8_lossy(&output.stderr).starts_with("FATAL: variable is assigned to itself"));
+
+	let output = run(&["--deny", "no-such-lint"]);
+	assert!(!output.status.success());
+}
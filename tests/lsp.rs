@@ -0,0 +1,224 @@
+// `lll lsp` driven by a scripted client, every request goes in up front and the
+// replies are matched by id once the server exits
+use std::io::Read;
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+
+use lll::lll::json::Json;
+
+fn frame(message: Json) -> String {
+	let body = message.to_string();
+	format!("Content-Length: {}\r\n\r\n{body}", body.len())
+}
+
+fn request(id: usize, method: &str, params: Json) -> String {
+	frame(Json::object(vec![("jsonrpc", "2.0".into()), ("id", id.into()), ("method", method.into()), ("params", params)]))
+}
+
+fn notify(method: &str, params: Json) -> String {
+	frame(Json::object(vec![("jsonrpc", "2.0".into()), ("method", method.into()), ("params", params)]))
+}
+
+fn open(uri: &str, text: &str) -> String {
+	notify("textDocument/didOpen", Json::object(vec![
+		("textDocument", Json::object(vec![("uri", uri.into()), ("languageId", "lll".into()), ("version", 1usize.into()), ("text", text.into())]))
+	]))
+}
+
+fn at(uri: &str, line: usize, character: usize) -> Json {
+	Json::object(vec![
+		("textDocument", Json::object(vec![("uri", uri.into())])),
+		("position", Json::object(vec![("line", line.into()), ("character", character.into())]))
+	])
+}
+
+fn document(uri: &str) -> Json {
+	Json::object(vec![("textDocument", Json::object(vec![("uri", uri.into())]))])
+}
+
+// Runs a whole session, hands back every message the server sent and whether it exited cleanly
+fn session(input: &str) -> (Vec<Json>, bool) {
+	let mut child = Command::new(env!("CARGO_BIN_EXE_lll"))
+		.arg("lsp")
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.spawn()
+		.unwrap();
+	child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+	let mut output = String::new();
+	child.stdout.take().unwrap().read_to_string(&mut output).unwrap();
+	let status = child.wait().unwrap();
+
+	let mut messages = Vec::new();
+	let mut rest = output.as_str();
+	while let Some((header, body)) = rest.split_once("\r\n\r\n") {
+		let length: usize = header.trim_start_matches("Content-Length: ").parse().unwrap();
+		messages.push(Json::parse(&body[..length]).unwrap());
+		rest = &body[length..];
+	}
+	(messages, status.success())
+}
+
+fn reply(messages: &[Json], id: usize) -> &Json {
+	messages.iter()
+		.find(|m| m.get("id").and_then(Json::as_f64) == Some(id as f64))
+		.and_then(|m| m.get("result"))
+		.unwrap_or_else(|| panic!("no reply to {id}"))
+}
+
+fn diagnostics(messages: &[Json]) -> Vec<&Json> {
+	messages.iter().filter(|m| m.get("method").and_then(Json::as_str) == Some("textDocument/publishDiagnostics")).collect()
+}
+
+fn number(v: &Json, path: &[&str]) -> usize {
+	v.at(path).and_then(Json::as_f64).unwrap() as usize
+}
+
+const SOURCE: &str = "/// Adds two numbers
+fun add(a, b) {
+	return a + b;
+}
+
+new total = add(1, 2);
+print total;
+";
+
+#[test]
+fn navigation() {
+	let uri = "file:///add.lll";
+	let input = [
+		request(1, "initialize", Json::object(vec![("capabilities", Json::object(vec![]))])),
+		notify("initialized", Json::object(vec![])),
+		open(uri, SOURCE),
+		request(2, "textDocument/definition", at(uri, 5, 12)),
+		request(3, "textDocument/hover", at(uri, 5, 12)),
+		request(4, "textDocument/documentSymbol", document(uri)),
+		request(5, "textDocument/completion", at(uri, 6, 0)),
+		request(6, "textDocument/definition", at(uri, 2, 12)),
+		request(7, "shutdown", Json::Null),
+		notify("exit", Json::Null)
+	].concat();
+	let (messages, clean) = session(&input);
+	assert!(clean);
+
+	let capabilities = reply(&messages, 1).get("capabilities").unwrap();
+	assert_eq!(capabilities.get("definitionProvider"), Some(&Json::Bool(true)));
+
+	let published = diagnostics(&messages);
+	assert_eq!(published.len(), 1);
+	assert_eq!(published[0].at(&["params", "diagnostics"]), Some(&Json::Array(vec![])));
+
+	// `add` in `add(1, 2)` goes to `fun add`
+	let definition = reply(&messages, 2);
+	assert_eq!(number(definition, &["range", "start", "line"]), 1);
+	assert_eq!(number(definition, &["range", "start", "character"]), 4);
+	assert_eq!(number(definition, &["range", "end", "character"]), 7);
+
+	let hover = reply(&messages, 3).at(&["contents", "value"]).and_then(Json::as_str).unwrap();
+	assert!(hover.contains("fun add(a, b)"), "{hover}");
+	assert!(hover.contains("Adds two numbers"), "{hover}");
+
+	let symbols: Vec<_> = reply(&messages, 4).as_array().unwrap().iter().map(|s| s.get("name").and_then(Json::as_str).unwrap()).collect();
+	assert_eq!(symbols, ["add", "total"]);
+
+	let labels: Vec<_> = reply(&messages, 5).as_array().unwrap().iter().map(|s| s.get("label").and_then(Json::as_str).unwrap()).collect();
+	for label in ["while", "new", "len", "add", "total"] {
+		assert!(labels.contains(&label), "{label} missing from {labels:?}");
+	}
+
+	// a parameter is its own definition's target
+	let definition = reply(&messages, 6);
+	assert_eq!(number(definition, &["range", "start", "line"]), 1);
+	assert_eq!(number(definition, &["range", "start", "character"]), 11);
+}
+
+#[test]
+fn diagnostics_follow_edits() {
+	let uri = "file:///broken.lll";
+	let change = |text: &str| notify("textDocument/didChange", Json::object(vec![
+		("textDocument", Json::object(vec![("uri", uri.into()), ("version", 2usize.into())])),
+		("contentChanges", Json::Array(vec![Json::object(vec![("text", text.into())])]))
+	]));
+	let input = [
+		request(1, "initialize", Json::object(vec![])),
+		open(uri, "new x = 1;\nprint x + y;\n"),
+		change("new x = 1;\nprint x +;\n"),
+		change("new s = \"ok\";\nprint s; @\n"),
+		change("new x = 1;\nif (1 < 2) print x;\n"),
+		change("new x = 1;\nprint x;\n"),
+		request(2, "shutdown", Json::Null),
+		notify("exit", Json::Null)
+	].concat();
+	let (messages, clean) = session(&input);
+	assert!(clean);
+
+	let published: Vec<_> = diagnostics(&messages).iter().map(|m| m.at(&["params", "diagnostics"]).and_then(Json::as_array).unwrap().to_vec()).collect();
+	assert_eq!(published.len(), 5);
+
+	// resolver: `y` was never declared
+	assert_eq!(published[0].len(), 1);
+	assert!(published[0][0].get("message").and_then(Json::as_str).unwrap().contains("undefined variable y"));
+	assert_eq!(number(&published[0][0], &["range", "start", "line"]), 1);
+	assert_eq!(number(&published[0][0], &["range", "start", "character"]), 10);
+
+	// parser
+	assert!(!published[1].is_empty());
+	assert_eq!(number(&published[1][0], &["range", "start", "line"]), 1);
+
+	// lexer, pointing right at the character
+	assert_eq!(published[2].len(), 1);
+	assert_eq!(number(&published[2][0], &["range", "start", "line"]), 1);
+	assert_eq!(number(&published[2][0], &["range", "start", "character"]), 9);
+
+	// lints come through as warnings
+	assert_eq!(published[3].len(), 1);
+	assert_eq!(number(&published[3][0], &["severity"]), 2);
+	assert!(published[3][0].get("message").and_then(Json::as_str).unwrap().contains("constant-condition"));
+
+	assert!(published[4].is_empty());
+}
+
+#[test]
+fn half_typed_code() {
+	// every prefix of the file is something an editor sends while it's being typed
+	let uri = "file:///typing.lll";
+	let source = "/// Doc\nexport fun f(a, b) { return (x) => a[b].c(x); }\nimport \"m\" as m;\nif (f) print {\"k\": [1, -2]}; else if (!f) f++;\ntry { throw 1; } catch (e) {} finally {}\nfor (new i = 0; i < 2; i += 1) continue;\n";
+	let mut input = request(1, "initialize", Json::object(vec![]));
+	for end in (0..=source.len()).filter(|end| source.is_char_boundary(*end)) {
+		input += &open(uri, &source[..end]);
+	}
+	input += &request(2, "shutdown", Json::Null);
+	input += &notify("exit", Json::Null);
+
+	let (messages, clean) = session(&input);
+	assert!(clean);
+	assert_eq!(diagnostics(&messages).len(), source.len() + 1);
+}
+
+#[test]
+fn deep_json_is_refused() {
+	let deep = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+	let input = [
+		format!("Content-Length: {}\r\n\r\n{deep}", deep.len()),
+		request(1, "initialize", Json::object(vec![])),
+		request(2, "shutdown", Json::Null),
+		notify("exit", Json::Null)
+	].concat();
+	let (messages, clean) = session(&input);
+	assert!(clean);
+	let refused = messages[0].at(&["error", "message"]).and_then(Json::as_str).unwrap();
+	assert!(refused.starts_with("nested more than 128 levels deep"), "{refused}");
+	reply(&messages, 1);
+
+	let shallow = format!("{}{}", "[".repeat(128), "]".repeat(128));
+	assert!(Json::parse(&shallow).is_ok());
+	assert!(Json::parse(&format!("[{shallow}]")).is_err());
+}
+
+#[test]
+fn exit_without_shutdown_fails() {
+	let (messages, clean) = session(&[request(1, "initialize", Json::object(vec![])), notify("exit", Json::Null)].concat());
+	assert_eq!(messages.len(), 1);
+	assert!(!clean);
+}
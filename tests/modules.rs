@@ -0,0 +1,149 @@
+// Imports that go wrong and where modules are looked for, on both backends. The imports
+// that work are in tests/scripts/modules.lll
+mod common;
+
+use std::process::Command;
+use std::process::Output;
+
+use common::Sandbox;
+
+// `LLL_PATH` set to the given project directories
+fn search(project: &Sandbox, command: &mut Command, lll_path: &[&str]) {
+	if !lll_path.is_empty() {
+		command.env("LLL_PATH", std::env::join_paths(lll_path.iter().map(|p| project.dir.join(p))).unwrap());
+	}
+}
+
+// lll started from `dir` inside the project
+fn lll(project: &Sandbox, dir: &str, args: &[&str], lll_path: &[&str]) -> Output {
+	let mut command = project.command();
+	command.current_dir(project.dir.join(dir)).args(args);
+	search(project, &mut command, lll_path);
+	command.output().unwrap()
+}
+
+// stdout of `main.lll` with `flags`, the same on both backends
+fn run_with(project: &Sandbox, flags: &[&str], lll_path: &[&str]) -> String {
+	let args: Vec<_> = flags.iter().chain(&["main.lll"]).copied().collect();
+	project.both_with(&args, "", |command| search(project, command, lll_path)).stdout
+}
+
+fn run(project: &Sandbox) -> String {
+	run_with(project, &[], &[])
+}
+
+#[test]
+fn cycles_are_reported() {
+	let project = Sandbox::with_files("cycle", &[
+		("main.lll", "import \"a.lll\";\nprint \"after\";\n"),
+		("a.lll", "import \"lib/b.lll\";\n"),
+		("lib/b.lll", "import \"../a.lll\";\n")
+	]);
+	let out = run(&project);
+	assert!(out.contains("import cycle: a.lll -> lib/b.lll -> ../a.lll"), "{out}");
+	assert!(out.ends_with("after"), "{out}");
+}
+
+#[test]
+fn importing_itself() {
+	let project = Sandbox::with_files("self", &[("main.lll", "import \"main.lll\";\n")]);
+	let out = run(&project);
+	assert!(out.contains("import cycle:"), "{out}");
+	assert!(out.contains("main.lll -> main.lll"), "{out}");
+}
+
+#[test]
+fn missing_module() {
+	let project = Sandbox::with_files("missing", &[("main.lll", "import \"nope.lll\" as n;\n")]);
+	assert_eq!(run(&project), "FATAL: cannot find module nope.lll, tried nope.lll, line 1, token Import\n");
+}
+
+#[test]
+fn errors_inside_a_module_point_at_it() {
+	let project = Sandbox::with_files("broken", &[
+		("main.lll", "import \"syntax.lll\";\nimport \"runtime.lll\";\n"),
+		("syntax.lll", "new x = ;\n"),
+		("runtime.lll", "print \"ran\";\n\nprint nothing;\n")
+	]);
+	let out = run(&project);
+	assert!(out.contains("FATAL: expected expression in syntax.lll:1, line 1, token Import"), "{out}");
+	assert!(out.contains("FATAL: variable identifier not found in runtime.lll:3, line 2, token Import"), "{out}");
+}
+
+#[test]
+fn only_exports_are_visible() {
+	let project = Sandbox::with_files("exports", &[
+		("main.lll", "import \"m.lll\";\nprint shown;\nprint hidden;\n"),
+		("m.lll", "export new shown = 1;\nnew hidden = 2;\n")
+	]);
+	let out = run(&project);
+	assert!(out.starts_with("1FATAL: variable identifier not found, line 3"), "{out}");
+}
+
+#[test]
+fn imports_and_exports_stay_at_the_top_level() {
+	let cases = [
+		("{ import \"m.lll\"; }\n", "import is only allowed at the top level"),
+		("fun f() { export new x = 1; }\n", "export is only allowed at the top level"),
+		("export print 1;\n", "only new and fun declarations can be exported")
+	];
+	for (source, expected) in cases {
+		let project = Sandbox::with_files("nested", &[("main.lll", source), ("m.lll", "")]);
+		let out = run(&project);
+		assert!(out.starts_with(&format!("FATAL: {expected}, line 1")), "{out}");
+	}
+}
+
+#[test]
+fn search_path() {
+	let project = Sandbox::with_files("search", &[
+		("main.lll", "import \"text.lll\" as text;\nimport \"list.lll\" as list;\nprint text.name + list.name;\n"),
+		("first/text.lll", "export new name = \"first\";\n"),
+		("second/text.lll", "export new name = \"second\";\n"),
+		("second/list.lll", "import \"text.lll\" as text;\nexport new name = \" \" + text.name;\n")
+	]);
+
+	// earlier directories win, but a module's own directory comes before all of them
+	assert_eq!(run_with(&project, &["--lib-dir", "first", "--lib-dir", "second"], &[]), "first second");
+	assert_eq!(run_with(&project, &[], &["second", "first"]), "second second");
+	// --lib-dir goes before LLL_PATH
+	assert_eq!(run_with(&project, &["--lib-dir", "first"], &["second"]), "first second");
+
+	let out = run_with(&project, &["--lib-dir", "nowhere"], &["first"]);
+	let tried = format!("tried list.lll, nowhere/list.lll, {}", project.dir.join("first/list.lll").display());
+	assert!(out.starts_with(&format!("FATAL: cannot find module list.lll, {tried}, line 2")), "{out}");
+}
+
+#[test]
+fn projects() {
+	let project = Sandbox::with_files("project", &[
+		("lll.toml", "# the demo\nentry = \"src/app.lll\"\nroots = [\"lib\", \"vendor\"]\n"),
+		("src/app.lll", "import \"greet.lll\";\nimport \"shout.lll\";\nprint shout(greet(\"project\"));\n"),
+		("lib/greet.lll", "export fun greet(who) { return \"hello \" + who; }\n"),
+		("vendor/shout.lll", "export fun shout(s) { return s + \"!\"; }\n"),
+		("src/deeper/nothing.txt", ""),
+		("broken/lll.toml", "entry = src/app.lll\n"),
+		("empty/lll.toml", "roots = []\n")
+	]);
+
+	// found from any directory inside the project
+	for dir in [".", "src", "src/deeper"] {
+		for flags in [&["run"][..], &["run", "--vm"]] {
+			let output = lll(&project, dir, flags, &[]);
+			assert!(output.status.success());
+			assert_eq!(String::from_utf8_lossy(&output.stdout), "hello project!");
+		}
+	}
+
+	let output = lll(&project, "broken", &["run"], &[]);
+	assert!(!output.status.success());
+	assert!(String::from_utf8_lossy(&output.stderr).contains("FATAL: entry must be a string in"));
+	assert!(String::from_utf8_lossy(&output.stderr).contains("lll.toml:1"));
+
+	let output = lll(&project, "empty", &["run"], &[]);
+	assert!(!output.status.success());
+	assert!(String::from_utf8_lossy(&output.stderr).contains("FATAL: missing entry in"));
+
+	let output = lll(&project, ".", &["run", "src/app.lll"], &[]);
+	assert!(!output.status.success());
+}
@@ -0,0 +1,128 @@
+// What a script gets from the shell that runs it, and what it gives back, on both backends
+mod common;
+
+use common::Run;
+use common::Sandbox;
+
+// lll run in `sandbox` with `args`, `stdin` piped in and a variable set for `env` to find
+fn both(sandbox: &Sandbox, args: &[&str], stdin: &str) -> Run {
+	sandbox.both_with(args, stdin, |command| {
+		command.env("LLL_SCRIPT_TEST", "from the shell").env_remove("LLL_SCRIPT_UNSET");
+	})
+}
+
+// `source` run with `flags` before the file and `args` after it
+fn lll(name: &str, flags: &[&str], source: &str, args: &[&str], stdin: &str) -> Run {
+	let sandbox = Sandbox::with_files(name, &[("main.lll", source)]);
+	let argv: Vec<&str> = flags.iter().copied().chain(["main.lll"]).chain(args.iter().copied()).collect();
+	both(&sandbox, &argv, stdin)
+}
+
+#[test]
+fn arguments_after_the_file() {
+	let source = "print args;\nprint \"\\n\";\nprint len(args);\n";
+	assert_eq!(lll("args", &[], source, &["one", "two words", "--vm"], "").stdout, "[\"one\", \"two words\", \"--vm\"]\n3");
+	assert_eq!(lll("no-args", &[], source, &[], "").stdout, "[]\n0");
+
+	// a script can still use the name itself
+	let source = "fun first(args) { return args[0]; }\nprint first(args);\nnew args = \"mine\";\nprint args;\n";
+	assert_eq!(lll("shadowed", &[], source, &["given"], "").stdout, "givenmine");
+}
+
+#[test]
+fn environment_and_stdin() {
+	let source = "\
+print env(\"LLL_SCRIPT_TEST\");
+print \"\\n\";
+print env(\"LLL_SCRIPT_UNSET\");
+print \"\\n\";
+new text = read_stdin();
+print len(text);
+print \"\\n\" + text + \"|\";
+print read_stdin() + \"|\";
+";
+	// the second read finds stdin empty
+	let run = lll("env", &[], source, &[], "first\nsecond");
+	assert_eq!(run.stdout, "from the shell\nnil\n12\nfirst\nsecond||");
+	assert_eq!(run.code, Some(0));
+}
+
+#[test]
+fn exit_ends_the_run() {
+	// no catch or finally runs, what was printed before still comes out
+	let source = "\
+print \"before\\n\";
+fun leave(code) {
+	try {
+		exit(code);
+	} catch (e) {
+		print \"caught\\n\";
+	} finally {
+		print \"finally\\n\";
+	}
+}
+leave(len(args) + 2);
+print \"never\\n\";
+";
+	let run = lll("exit", &[], source, &["x"], "");
+	assert_eq!((run.stdout.as_str(), run.stderr.as_str(), run.code), ("before\n", "", Some(3)));
+
+	let run = lll("exit-zero", &[], "print 1;\nexit(0);\nprint 2;\n", &[], "");
+	assert_eq!((run.stdout.as_str(), run.code), ("1", Some(0)));
+
+	// only the low byte reaches the shell
+	assert_eq!(lll("exit-wrap", &[], "exit(257);\n", &[], "").code, Some(1));
+
+	let run = lll("exit-bad", &[], "try { exit(1.5); } catch (e) { print e.kind + \": \" + e.message; }\n", &[], "");
+	assert_eq!((run.stdout.as_str(), run.code), ("type: exit expects a whole number", Some(0)));
+}
+
+#[test]
+fn each_needs_its_capability() {
+	let source = "\
+try { env(\"LLL_SCRIPT_TEST\"); } catch (e) { print e.message + \"\\n\"; }
+try { read_stdin(); } catch (e) { print e.message + \"\\n\"; }
+try { exit(4); } catch (e) { print e.message + \"\\n\"; }
+print args;
+";
+	let run = lll("denied", &["--deny-cap", "all"], source, &["still here"], "");
+	assert_eq!(run.stdout, "\
+env needs the env capability, which is denied
+read_stdin needs the stdin capability, which is denied
+exit needs the process capability, which is denied
+[\"still here\"]");
+	assert_eq!(run.code, Some(0));
+}
+
+#[test]
+fn inline_and_stdin_programs() {
+	let sandbox = Sandbox::new("inline");
+	let run = both(&sandbox, &["-e", "print args;\nprint 1 + 2;", "a", "--vm"], "");
+	assert_eq!((run.stdout.as_str(), run.code), ("[\"a\", \"--vm\"]3", Some(0)));
+	let run = both(&sandbox, &["--eval", "exit(len(args));", "a", "b"], "");
+	assert_eq!(run.code, Some(2));
+
+	let run = both(&sandbox, &["-", "given"], "print args;\nprint \"\\n\";\nprint read_stdin();\n");
+	assert_eq!((run.stdout.as_str(), run.code), ("[\"given\"]\n", Some(0)));
+
+	// flags still come first
+	let run = both(&sandbox, &["--max-steps", "10", "-e", "while (true) {}"], "");
+	assert_eq!((run.stderr.as_str(), run.code), ("FATAL: step limit of 10 reached\n", Some(1)));
+	assert_eq!(both(&sandbox, &["-e"], "").stderr, "FATAL: -e expects a program\n");
+}
+
+#[test]
+fn errors_fail_the_run() {
+	// the statements after an error still run, but the shell hears about it
+	let run = lll("runtime", &[], "print missing;\nprint \"after\";\n", &[], "");
+	assert_eq!((run.stdout.as_str(), run.code), ("FATAL: variable identifier not found, line 1, token Identifier\nafter", Some(1)));
+	let run = lll("parse", &[], "print (1;\nprint 2;\n", &[], "");
+	assert_eq!((run.stdout.as_str(), run.code), ("FATAL: expected RightParen, line 1, token Semicolon\n2", Some(1)));
+
+	// a caught error is handled, and `exit` decides over the errors before it
+	assert_eq!(lll("caught", &[], "try { print missing; } catch (e) {}\n", &[], "").code, Some(0));
+	assert_eq!(lll("exit-after", &[], "print missing;\nexit(0);\n", &[], "").code, Some(0));
+
+	let run = both(&Sandbox::new("no-file"), &["no-such-file.lll"], "");
+	assert_eq!(run.code, Some(1));
+}